@@ -0,0 +1,36 @@
+//! Compares the hand-written parser with the original pest one on the example files, scaled up by
+//! repeating them inside of an array.
+//!
+//! ```sh
+//! cargo bench --features pest-parser
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const FILES: [&str; 2] = ["example_files/01.json", "example_files/02.json"];
+const COPIES: [usize; 3] = [1, 100, 1000];
+
+fn scaled(file: &str, copies: usize) -> String {
+    let source = std::fs::read_to_string(file).unwrap();
+    format!("[{}]", vec![source.as_str(); copies].join(","))
+}
+
+fn parse(c: &mut Criterion) {
+    for file in FILES {
+        let mut group = c.benchmark_group(file);
+        for copies in COPIES {
+            let source = scaled(file, copies);
+            group.throughput(Throughput::Bytes(source.len() as u64));
+            group.bench_with_input(BenchmarkId::new("iterative", copies), &source, |b, s| {
+                b.iter(|| tispect::value::parse(s).unwrap())
+            });
+            group.bench_with_input(BenchmarkId::new("pest", copies), &source, |b, s| {
+                b.iter(|| tispect::value::pest_parser::parse(s).unwrap())
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
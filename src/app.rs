@@ -1,19 +1,238 @@
-use derive_more::IsVariant;
-use std::{path::Path, pin::Pin, rc::Rc};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
 
 use crossterm::event::{Event, KeyCode};
+use derive_more::IsVariant;
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     Frame,
 };
 
 use crate::{
-    components::{log_popup::LogPopup, query::Query, viewer::Viewer},
+    components::{
+        confirm_popup::ConfirmPopup,
+        diagnostics::{Diagnostic, DiagnosticsPopup},
+        line_input::{LineInput, LineInputResult},
+        log_popup::LogPopup,
+        query::{Query, QueryAction, QueryFailure, QueryResult, PANE_WIDTH, RESULT_WIDTH},
+        string_popup::StringPopup,
+        viewer::Viewer,
+    },
+    config::{Config, SavedQuery},
     logger::Logger,
+    query::{
+        self,
+        complete::{self, Completion},
+        format_path, format_path_preview,
+        history::QueryHistory,
+        jq,
+        task::{self, QueryTask},
+        Language, Output, RunError,
+    },
     utils::{container_block, inset_chunk},
-    value::ContainedValue,
+    value::{
+        edit::Edit,
+        history::{History, Step},
+        parse_with,
+        writer::{Indent, LayoutConfig, Writer},
+        Document, ParseOptions, Value,
+    },
+    vi::vistate::ViCommand,
 };
 
+/// What the inline editor changes once its text is submitted.
+#[derive(Debug, Clone)]
+enum EditTarget {
+    Value(Vec<usize>),
+    Key(Vec<usize>),
+    /// A new item at the path.
+    Insert(Vec<usize>),
+}
+
+/// A result of the query in the projection.
+enum Projected {
+    /// A value of the document, at its path.
+    Document(Vec<usize>),
+    /// A value built by the query.
+    Built(Value),
+}
+
+/// Shown for a result whose path isn't in the document anymore.
+static MISSING: Value = Value::Null;
+
+/// The results of the query shown in the Explorer instead of the document, as an array. The values
+/// of the document are looked up by their path when they are shown, only the values built by the
+/// query are kept.
+struct Projection {
+    results: Vec<Projected>,
+}
+
+impl Projection {
+    fn new(outputs: Vec<Output>) -> Self {
+        let results = outputs
+            .into_iter()
+            .map(|output| match output {
+                Output::Ref(path, _) => Projected::Document(path),
+                Output::Owned(value) => Projected::Built(value),
+            })
+            .collect();
+        Self { results }
+    }
+
+    /// The values of the results, `document` being the value the query ran on.
+    fn values<'a>(&'a self, document: &'a Value) -> Vec<&'a Value> {
+        self.results
+            .iter()
+            .map(|result| match result {
+                Projected::Document(path) => document.get_path(path).unwrap_or(&MISSING),
+                Projected::Built(value) => value,
+            })
+            .collect()
+    }
+
+    /// The value at `path` in the projection, `None` for the array of the results.
+    fn get<'a>(&'a self, document: &'a Value, path: &[usize]) -> Option<&'a Value> {
+        let (&i, rest) = path.split_first()?;
+        match self.results.get(i)? {
+            Projected::Document(path) => document.get_path(path)?.get_path(rest),
+            Projected::Built(value) => value.get_path(rest),
+        }
+    }
+
+    /// The value at `path` in the projection when the query built it.
+    fn get_built_mut(&mut self, path: &[usize]) -> Option<&mut Value> {
+        let (&i, rest) = path.split_first()?;
+        match self.results.get_mut(i)? {
+            Projected::Document(_) => None,
+            Projected::Built(value) => value.get_path_mut(rest),
+        }
+    }
+
+    /// The paths in the projection of the values matching `predicate`, in order.
+    fn find_paths(&self, document: &Value, predicate: impl Fn(&Value) -> bool) -> Vec<Vec<usize>> {
+        self.values(document)
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, value)| {
+                value
+                    .find_paths(&predicate)
+                    .into_iter()
+                    .map(move |path| [&[i], path.as_slice()].concat())
+            })
+            .collect()
+    }
+
+    /// The path in the document of the value at `path` in the projection.
+    fn document_path(&self, path: &[usize]) -> Option<Vec<usize>> {
+        let (&i, rest) = path.split_first()?;
+        match self.results.get(i)? {
+            Projected::Document(document_path) => Some([document_path, rest].concat()),
+            Projected::Built(_) => None,
+        }
+    }
+
+    /// The path in the projection of the value at `path` in the document, in the first result
+    /// that contains it.
+    fn projected_path(&self, path: &[usize]) -> Option<Vec<usize>> {
+        self.results.iter().enumerate().find_map(|(i, result)| {
+            let Projected::Document(result) = result else {
+                return None;
+            };
+            let rest = path.strip_prefix(result.as_slice())?;
+            Some([&[i], rest].concat())
+        })
+    }
+}
+
+/// How long the query has to stay the same before it runs, so that typing it doesn't start a
+/// query per key.
+const QUERY_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// What a query computed on its thread, without references into the document.
+struct Evaluation {
+    /// The results, or why the query failed.
+    results: Result<Vec<QueryResult>, QueryFailure>,
+    /// The paths selected by the query, in document order.
+    matches: Vec<Vec<usize>>,
+    /// Only built while the projection is shown.
+    projection: Option<Projection>,
+    completion: Option<Completion>,
+    language: Language,
+    query: String,
+}
+
+/// Runs the query on `value` and completes its end. The results are built on the thread of the
+/// query, which stops building them when it is cancelled.
+fn evaluate(value: &Value, language: Language, query: &str, project: bool) -> Evaluation {
+    let completion = complete::complete(language, query, value);
+    let failed = |failure, completion| Evaluation {
+        results: Err(failure),
+        matches: Vec::new(),
+        projection: None,
+        completion,
+        language,
+        query: query.to_string(),
+    };
+    let cancelled = || QueryFailure {
+        message: RunError::Cancelled.to_string(),
+        offset: None,
+    };
+    let outputs = if query.trim().is_empty() {
+        Vec::new()
+    } else {
+        match query::run(language, query, value) {
+            Ok(outputs) => outputs,
+            Err(err) => {
+                let failure = match err {
+                    RunError::Query(err) => QueryFailure {
+                        message: err.kind.to_string(),
+                        offset: Some(err.offset),
+                    },
+                    err => QueryFailure {
+                        message: err.to_string(),
+                        offset: None,
+                    },
+                };
+                return failed(failure, completion);
+            }
+        }
+    };
+
+    // values that were built by the query are shown as JSON, the others by their path
+    let mut results = Vec::with_capacity(outputs.len());
+    for output in &outputs {
+        if task::is_cancelled() {
+            return failed(cancelled(), completion);
+        }
+        let path = output.path().map(<[usize]>::to_vec);
+        let text = match &path {
+            Some(path) => format_path_preview(value, path, RESULT_WIDTH),
+            None => jq::to_json_preview(output.value(), RESULT_WIDTH),
+        };
+        results.push(QueryResult::new(path, text, output.value()));
+    }
+    let mut matches = outputs
+        .iter()
+        .filter_map(|output| output.path().map(<[usize]>::to_vec))
+        .collect::<Vec<_>>();
+    if task::is_cancelled() {
+        return failed(cancelled(), completion);
+    }
+    let projection = project.then(|| Projection::new(outputs));
+    matches.sort();
+    matches.dedup();
+    Evaluation {
+        results: Ok(results),
+        matches,
+        projection,
+        completion,
+        language,
+        query: query.to_string(),
+    }
+}
+
 #[derive(Debug, IsVariant, Clone, Copy, PartialEq, Eq)]
 pub enum CurrentScreen {
     Viewer,
@@ -29,41 +248,823 @@ impl CurrentScreen {
     }
 }
 
-pub struct App<'a> {
+pub struct App {
     done: bool,
-    value: Pin<Box<ContainedValue<'a>>>,
+    document: Document,
     current_screen: CurrentScreen,
     logger: Logger,
     show_logs: bool,
     logs: LogPopup,
+    string_popup: StringPopup,
+    diagnostics: DiagnosticsPopup,
+    confirm_quit: ConfirmPopup,
+    decode_strings: bool,
+    /// The layout of the config, edits are saved with it.
+    file_layout: LayoutConfig,
+    /// The layout of the Explorer, with tabs shown as spaces.
+    layout: LayoutConfig,
     viewer: Viewer,
     query: Query,
+    /// The paths selected by the query, in document order.
+    matches: Vec<Vec<usize>>,
+    editor: Option<(EditTarget, LineInput)>,
+    command_line: Option<LineInput>,
+    history: History,
+    /// The queries of the config file, by their names.
+    saved_queries: BTreeMap<String, SavedQuery>,
+    /// Shown instead of the document when set.
+    projection: Option<Projection>,
+    /// Where the cursor goes once the projection that was just turned on is evaluated, a path of
+    /// the document.
+    projection_target: Option<Vec<usize>>,
+    /// When the query should run, it is delayed while it is being typed.
+    query_due: Option<Instant>,
+    query_task: Option<QueryTask<Evaluation>>,
+    /// The queries that were cancelled but may still hold a snapshot of the document.
+    cancelled_queries: Vec<QueryTask<Evaluation>>,
 }
 
-impl<'a> App<'a> {
-    pub fn new(file: impl AsRef<Path>) -> Self {
-        let source = std::fs::read_to_string(file).unwrap();
+impl App {
+    pub fn new(document: Document, config: Config) -> Self {
         let logger = Logger::new();
-        let mut out = Self {
+        let mut layout = config.layout.clone();
+        // the viewer counts columns in characters, tabs are shown as spaces
+        if layout.indent == Indent::TAB {
+            layout.indent = Indent::Spaces(4);
+        }
+        let mut query = Query::new(logger.clone());
+        query.set_vi_mode(config.query.vi_mode);
+        // the history of the queries run on files of the same type
+        let file_type = document
+            .path()
+            .and_then(|path| path.extension())
+            .map_or("json".into(), |ext| ext.to_string_lossy());
+        match QueryHistory::load(&file_type) {
+            Ok(history) => query.set_history(history),
+            Err(err) => logger.log(format!("could not read the query history: {err}")),
+        }
+        let out = Self {
             logger: logger.clone(),
-            value: ContainedValue::parse(source),
+            document,
             done: false,
             current_screen: CurrentScreen::Query,
             show_logs: false,
             logs: LogPopup::new(logger.clone()),
+            string_popup: StringPopup::new(),
+            diagnostics: DiagnosticsPopup::new(),
+            confirm_quit: ConfirmPopup::new(),
+            decode_strings: false,
+            file_layout: config.layout,
+            layout,
             viewer: Viewer::new(logger.clone()),
-            query: Query::new(logger.clone()),
+            query,
+            matches: Vec::new(),
+            editor: None,
+            command_line: None,
+            history: History::new(),
+            saved_queries: config.queries,
+            projection: None,
+            projection_target: None,
+            query_due: None,
+            query_task: None,
+            cancelled_queries: Vec::new(),
         };
-        out.viewer.set_value_elemnets(out.value.get().elemnets());
+        if out.document.is_lazy() {
+            out.logger.log("the document is loaded lazily");
+        }
+        for err in out.document.errors() {
+            out.logger.log(format!("parse error: {err}"));
+        }
+        if !out.document.extensions().is_empty() {
+            out.logger.log(format!(
+                "lenient extensions used: {}",
+                out.document.extensions().names().join(", ")
+            ));
+        }
+        match out.document.get().find_duplicate_keys().len() {
+            0 => {}
+            n => out.logger.log(format!(
+                "{n} entries have a repeated key, press D to list them"
+            )),
+        }
         out
     }
 
+    /// The language the Query pane starts in.
+    pub fn set_query_language(&mut self, language: Language) {
+        self.query.set_language(language);
+    }
+
+    /// Renders the lines in `window` into the viewer. When `target` is given the cursor is moved
+    /// to the start of that value.
+    fn render_value(&mut self, window: [i32; 2], target: Option<Vec<usize>>) {
+        let mut writer = self.writer();
+        writer.with_window(window);
+        if let Some(target) = target {
+            writer.with_target(target);
+        }
+        match &self.projection {
+            Some(projection) => writer.write_array(&projection.values(self.document.get())),
+            None => writer.write_root(self.document.get(), self.document.comments()),
+        }
+        let rendered = writer.finish();
+        if let Some(target) = &rendered.target {
+            self.viewer.set_cursor(target.key.unwrap_or(target.start));
+        }
+        self.viewer.set_rendered(rendered);
+        self.highlight_matches();
+    }
+
+    /// The value at `path` in the Explorer, in the document or the results of the query.
+    fn shown(&self, path: &[usize]) -> Option<&Value> {
+        match &self.projection {
+            Some(projection) => projection.get(self.document.get(), path),
+            None => self.document.get().get_path(path),
+        }
+    }
+
+    fn writer(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer.with_layout(&self.layout);
+        writer.with_decoded_strings(self.decode_strings);
+        writer
+    }
+
+    /// Renders the value at `path` again and splices it into the viewer, which is much cheaper than
+    /// rendering the window when only the value changed. The cursor is moved to the value.
+    /// Returns false when the value can't be rendered on its own.
+    fn render_subtree(&mut self, path: &[usize]) -> bool {
+        let mut writer = self.writer();
+        // a container that got narrower may let its parents fit on a single line
+        let mut root = path;
+        while let Some((_, parent)) = root.split_last() {
+            let fits = self.viewer.node(parent).is_some_and(|node| {
+                self.shown(parent)
+                    .is_some_and(|parent_value| writer.fits_inline(parent_value, node.start[1]))
+            });
+            if !fits {
+                break;
+            }
+            root = parent;
+        }
+
+        let Some(old) = self.viewer.node(root).cloned() else {
+            return false;
+        };
+        let (Some(indent), Some(value)) = (self.viewer.splice_indent(&old), self.shown(root))
+        else {
+            return false;
+        };
+
+        writer.with_window(self.viewer.window());
+        writer.with_start(&old, writer.indent_levels(indent));
+        writer.with_target(root.to_vec());
+        writer.write_value(value);
+        self.viewer.splice(&old, writer.finish());
+        if let Some(node) = self.viewer.node(path) {
+            self.viewer.set_cursor(node.key.unwrap_or(node.start));
+        }
+        self.highlight_matches();
+        true
+    }
+
+    /// Highlights the values selected by the query, every value of the projection is one.
+    fn highlight_matches(&mut self) {
+        if self.projection.is_some() {
+            self.viewer.highlight_nodes(|_| false);
+            return;
+        }
+        let matches = &self.matches;
+        self.viewer
+            .highlight_nodes(|path| matches.binary_search_by(|m| m.as_slice().cmp(path)).is_ok());
+    }
+
+    fn toggle_string_decoding(&mut self) {
+        self.decode_strings = !self.decode_strings;
+        self.render_value(self.viewer.window(), None);
+    }
+
+    fn toggle_fold(&mut self) {
+        let Some(mut path) = self.viewer.node_at_cursor().map(|path| path.to_vec()) else {
+            return;
+        };
+        // folding a scalar folds the container it is in
+        while !path.is_empty()
+            && !self
+                .shown(&path)
+                .is_some_and(|value| value.is_container() || value.is_lazy())
+        {
+            path.pop();
+        }
+        if self.projection.is_some() {
+            return self.toggle_projected_fold(path);
+        }
+
+        self.stop_query();
+        match self.document.apply(Edit::toggle_collapse(path.clone())) {
+            Ok(inverse) => self.history.record(inverse, self.viewer.cursor()),
+            Err(err) => {
+                self.logger.log(format!("could not expand: {err}"));
+                return;
+            }
+        }
+        if !self.render_subtree(&path) {
+            self.render_value(self.viewer.window(), Some(path));
+        }
+    }
+
+    /// Folds the container at `path` of the projection. The values of the document are folded in
+    /// the document too, which isn't recorded as an edit.
+    fn toggle_projected_fold(&mut self, path: Vec<usize>) {
+        let Some(projection) = &mut self.projection else {
+            return;
+        };
+        match projection.document_path(&path) {
+            Some(document_path) => {
+                self.stop_query();
+                if let Err(err) = self.document.toggle_collapse(&document_path) {
+                    self.logger.log(format!("could not expand: {err}"));
+                    return;
+                }
+            }
+            None => match projection.get_built_mut(&path) {
+                Some(Value::Array(arr)) => arr.toggle_collapse(),
+                Some(Value::Object(object)) => object.toggle_collapse(),
+                _ => return,
+            },
+        }
+        if !self.render_subtree(&path) {
+            self.render_value(self.viewer.window(), Some(path));
+        }
+    }
+
+    /// Shows only the results of the query in the Explorer, or the document again. The cursor
+    /// stays on the value it was on when the other view has it.
+    fn toggle_projection(&mut self) {
+        let cursor = self.viewer.node_at_cursor().map(<[usize]>::to_vec);
+        if let Some(projection) = self.projection.take() {
+            self.projection_target = None;
+            match cursor.and_then(|path| projection.document_path(&path)) {
+                Some(path) => self.go_to(path),
+                None => self.render_value(self.viewer.window(), Some(Vec::new())),
+            }
+            return;
+        }
+
+        // the results come from the query, which runs again
+        self.projection = Some(Projection::new(Vec::new()));
+        self.projection_target = cursor;
+        self.restart_query(Instant::now());
+        self.render_value(self.viewer.window(), Some(Vec::new()));
+    }
+
+    /// Shows the decoded string under the cursor in a popup, or the full message of an error.
+    fn show_string(&mut self) {
+        let Some(path) = self.viewer.node_at_cursor() else {
+            return;
+        };
+        match self.shown(path) {
+            Some(value) if value.is_string() => {
+                let content = value.get_decoded_string().unwrap().into_owned();
+                self.string_popup.show(content);
+            }
+            Some(Value::Error(err)) => self.string_popup.show(err.to_string()),
+            _ => self.logger.log("no string under the cursor"),
+        }
+    }
+
+    /// Expands the containers around the value at `path` of the document and moves the cursor to
+    /// it. The projection shows the value in the first result that contains it, or is left when
+    /// none does.
+    fn go_to(&mut self, path: Vec<usize>) {
+        if let Some(projection) = &self.projection {
+            match projection.projected_path(&path) {
+                Some(path) => return self.go_to_projected(path),
+                None => self.projection = None,
+            }
+        }
+        self.stop_query();
+        if let Err(err) = self.document.expand_to(&path) {
+            self.logger.log(format!("could not expand: {err}"));
+            return;
+        }
+        self.render_value(self.viewer.window(), Some(path));
+    }
+
+    /// Like [`App::go_to`] with a path of the projection.
+    fn go_to_projected(&mut self, path: Vec<usize>) {
+        let Some(projection) = &mut self.projection else {
+            return;
+        };
+        match projection.document_path(&path) {
+            Some(document_path) => {
+                self.stop_query();
+                if let Err(err) = self.document.expand_to(&document_path) {
+                    self.logger.log(format!("could not expand: {err}"));
+                    return;
+                }
+            }
+            None => {
+                for depth in 1..path.len() {
+                    match projection.get_built_mut(&path[..depth]) {
+                        Some(Value::Array(arr)) => arr.expand(),
+                        Some(Value::Object(object)) => object.expand(),
+                        _ => break,
+                    }
+                }
+            }
+        }
+        self.render_value(self.viewer.window(), Some(path));
+    }
+
+    /// Moves the cursor to the value at `path` of the Explorer, in the document or the projection.
+    fn go_to_shown(&mut self, path: Vec<usize>) {
+        match self.projection {
+            Some(_) => self.go_to_projected(path),
+            None => self.go_to(path),
+        }
+    }
+
+    /// The first of `paths` after the cursor (or the last one before it), wrapping around the
+    /// document. `paths` must be in document order.
+    fn next_path(&self, paths: &[Vec<usize>], forward: bool) -> Option<Vec<usize>> {
+        let current = self
+            .viewer
+            .node_at_cursor()
+            .map(|path| path.to_vec())
+            .unwrap_or_default();
+        if forward {
+            paths.iter().find(|path| **path > current).or(paths.first())
+        } else {
+            paths
+                .iter()
+                .rev()
+                .find(|path| **path < current)
+                .or(paths.last())
+        }
+        .cloned()
+    }
+
+    /// Moves the cursor to the next (or previous) error node.
+    fn jump_to_error(&mut self, forward: bool) {
+        let errors = match &self.projection {
+            Some(projection) => projection.find_paths(self.document.get(), Value::is_error),
+            None => self.document.get().find_paths(Value::is_error),
+        };
+        match self.next_path(&errors, forward) {
+            Some(target) => self.go_to_shown(target),
+            None => self.logger.log("no errors in the document"),
+        }
+    }
+
+    /// Moves the cursor to the next (or previous) value selected by the query, or result in the
+    /// projection.
+    fn jump_to_match(&mut self, forward: bool) {
+        let target = match &self.projection {
+            Some(projection) => {
+                let results = (0..projection.results.len())
+                    .map(|i| vec![i])
+                    .collect::<Vec<_>>();
+                self.next_path(&results, forward)
+            }
+            None => self.next_path(&self.matches, forward),
+        };
+        match target {
+            Some(target) => self.go_to_shown(target),
+            None => self.logger.log("no matches"),
+        }
+    }
+
+    /// The error nodes and the entries with a repeated key, in document order.
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        let value = self.document.get();
+        let errors = value.find_paths(Value::is_error).into_iter().map(|path| {
+            let Some(Value::Error(err)) = value.get_path(&path) else {
+                unreachable!()
+            };
+            Diagnostic {
+                message: format!("{}: {err}", format_path(value, &path)),
+                path,
+            }
+        });
+        let duplicates = value
+            .find_duplicate_keys()
+            .into_iter()
+            .map(|path| Diagnostic {
+                message: format!(
+                    "{}: repeated key (entry {})",
+                    format_path(value, &path),
+                    path.last().unwrap()
+                ),
+                path,
+            });
+        let mut diagnostics = errors.chain(duplicates).collect::<Vec<_>>();
+        diagnostics.sort_by(|a, b| a.path.cmp(&b.path));
+        diagnostics
+    }
+
+    fn process_viewer_command(&mut self, command: ViCommand) {
+        match command {
+            ViCommand::EditValue
+            | ViCommand::ChangeValue
+            | ViCommand::Delete
+            | ViCommand::InsertSibling
+            | ViCommand::Undo
+            | ViCommand::Redo
+                if self.projection.is_some() =>
+            {
+                self.logger
+                    .log("the results can't be edited, gr shows the document again")
+            }
+            ViCommand::ToggleProjection => self.toggle_projection(),
+            ViCommand::ToggleStringDecoding => self.toggle_string_decoding(),
+            ViCommand::ShowString => self.show_string(),
+            ViCommand::ToggleFold => self.toggle_fold(),
+            ViCommand::NextError => self.jump_to_error(true),
+            ViCommand::PreviousError => self.jump_to_error(false),
+            ViCommand::ShowDiagnostics => self.diagnostics.show(self.diagnostics()),
+            ViCommand::Next => self.jump_to_match(true),
+            ViCommand::Previous => self.jump_to_match(false),
+            ViCommand::EditValue => self.start_edit(false),
+            ViCommand::ChangeValue => self.start_edit(true),
+            ViCommand::Delete => self.delete(),
+            ViCommand::InsertSibling => self.start_insert(),
+            ViCommand::CommandLine => self.command_line = Some(LineInput::new(String::new(), 0)),
+            ViCommand::Undo => self.undo(false),
+            ViCommand::Redo => self.undo(true),
+            _ => {}
+        }
+    }
+
+    /// The options used to parse the text typed into the editor. Recovering from errors would
+    /// accept anything, so it is turned off.
+    fn edit_options(&self) -> ParseOptions {
+        ParseOptions {
+            recover: false,
+            ..self.document.options()
+        }
+    }
+
+    /// Opens the inline editor on the value or key under the cursor, with its current text unless
+    /// `clear` is set.
+    fn start_edit(&mut self, clear: bool) {
+        let Some(path) = self.viewer.node_at_cursor().map(|path| path.to_vec()) else {
+            return;
+        };
+        let Some(node) = self.viewer.node(&path).cloned() else {
+            return;
+        };
+        let cursor = self.viewer.cursor();
+        let value = self.document.get();
+
+        let (target, text, column) = match node.key {
+            Some(key) if cursor < node.start => {
+                let (&i, parent) = path.split_last().unwrap();
+                let Some(Value::Object(object)) = value.get_path(parent) else {
+                    return;
+                };
+                // the key is drawn inside of quotes
+                let text = object.entries()[i].0.clone();
+                (EditTarget::Key(path), text, (cursor[1] - key[1] - 1).max(0))
+            }
+            _ => match value.get_path(&path) {
+                Some(value) if !value.is_container() && !value.is_lazy() => {
+                    let column = if cursor[0] == node.start[0] {
+                        cursor[1] - node.start[1]
+                    } else {
+                        0
+                    };
+                    (EditTarget::Value(path), value.to_string(), column)
+                }
+                _ => {
+                    self.logger.log("only scalars can be edited inline");
+                    return;
+                }
+            },
+        };
+        let editor = if clear {
+            LineInput::new(String::new(), 0)
+        } else {
+            LineInput::new(text, column as usize)
+        };
+        self.editor = Some((target, editor));
+    }
+
+    /// Opens the inline editor for a new item after the value under the cursor.
+    fn start_insert(&mut self) {
+        let Some(mut path) = self.viewer.node_at_cursor().map(|path| path.to_vec()) else {
+            return;
+        };
+        let Some(last) = path.last_mut() else {
+            self.logger.log("the root value has no siblings");
+            return;
+        };
+        *last += 1;
+        self.editor = Some((EditTarget::Insert(path), LineInput::new(String::new(), 0)));
+    }
+
+    /// Turns the text of the editor into an edit. The editor stays open when the text is invalid.
+    fn submit_edit(&mut self, target: &EditTarget, text: &str) -> Result<Edit, String> {
+        let options = self.edit_options();
+        let parse = |text: &str| {
+            parse_with(text, options)
+                .map(|parsed| parsed.value)
+                .map_err(|err| format!("invalid value: {err}"))
+        };
+        Ok(match target {
+            EditTarget::Value(path) => Edit::replace(path.clone(), parse(text)?),
+            EditTarget::Key(path) => {
+                parse(&format!("\"{text}\"")).map_err(|_| format!("invalid key: {text}"))?;
+                Edit::rename(path.clone(), text.to_string())
+            }
+            EditTarget::Insert(path) => {
+                let (_, parent) = path.split_last().unwrap();
+                match self.document.get().get_path(parent) {
+                    Some(Value::Object(_)) => match parse(&format!("{{{text}}}"))? {
+                        Value::Object(mut object) if object.len() == 1 => {
+                            let (key, value) = object.remove(0);
+                            Edit::insert(path.clone(), Some(key), value)
+                        }
+                        _ => return Err("expected a single `key: value` entry".to_string()),
+                    },
+                    _ => Edit::insert(path.clone(), None, parse(text)?),
+                }
+            }
+        })
+    }
+
+    /// Applies the edit, evaluates the query again and moves the cursor to the edited value (or
+    /// to where it was).
+    fn apply_edit(&mut self, edit: Edit) {
+        let target = edit.path().to_vec();
+        self.stop_query();
+        match self.document.apply(edit) {
+            Ok(inverse) => self.history.record(inverse, self.viewer.cursor()),
+            Err(err) => {
+                self.logger.log(format!("could not edit: {err}"));
+                return;
+            }
+        }
+        self.show_changed_value(target);
+    }
+
+    /// Evaluates the query again and renders the document with the cursor on the value at
+    /// `target`, or on the one before it when it was removed.
+    fn show_changed_value(&mut self, mut target: Vec<usize>) {
+        let value = self.document.get();
+        while !target.is_empty() && value.get_path(&target).is_none() {
+            match target.last_mut() {
+                Some(0) | None => {
+                    target.pop();
+                }
+                Some(i) => *i -= 1,
+            }
+        }
+        self.restart_query(Instant::now());
+        self.render_value(self.viewer.window(), Some(target));
+    }
+
+    /// Undoes the last change, or redoes the last undone one, and moves the cursor back to where it
+    /// was before the change.
+    fn undo(&mut self, redo: bool) {
+        let cursor = self.viewer.cursor();
+        self.stop_query();
+        let result = if redo {
+            self.history.redo(&mut self.document, cursor)
+        } else {
+            self.history.undo(&mut self.document, cursor)
+        };
+        let step = match result {
+            Ok(Some(step)) => step,
+            Ok(None) if redo => return self.logger.log("already at the newest change"),
+            Ok(None) => return self.logger.log("already at the oldest change"),
+            Err(err) => return self.logger.log(format!("could not undo: {err}")),
+        };
+        let Step {
+            path,
+            cursor,
+            changes_value,
+        } = step;
+        if changes_value {
+            self.show_changed_value(path);
+        } else if !self.render_subtree(&path) {
+            self.render_value(self.viewer.window(), Some(path));
+        }
+        self.viewer.set_cursor(cursor);
+    }
+
+    fn delete(&mut self) {
+        let Some(path) = self.viewer.node_at_cursor().map(|path| path.to_vec()) else {
+            return;
+        };
+        self.apply_edit(Edit::remove(path));
+    }
+
+    fn save(&mut self) {
+        match self.document.save(&self.file_layout) {
+            Ok(()) => {
+                self.history.mark_saved();
+                self.logger.log(match self.document.path() {
+                    Some(path) => format!("saved {}", path.display()),
+                    None => "saved".to_string(),
+                });
+            }
+            Err(err) => self.logger.log(format!("could not save: {err}")),
+        }
+    }
+
+    /// Quits, after asking when there are unsaved changes.
+    fn quit(&mut self) {
+        if self.history.is_modified() {
+            self.confirm_quit
+                .show("The document has unsaved changes, quit anyway?");
+        } else {
+            self.done = true;
+        }
+    }
+
+    /// Saves the query of the Query pane in the config file.
+    fn save_query(&mut self, name: &str) {
+        let query = SavedQuery {
+            query: self.query.get().to_string(),
+            language: self.query.language(),
+        };
+        if query.query.trim().is_empty() {
+            return self.logger.log("there is no query to save");
+        }
+        match Config::save_query(name, &query) {
+            Ok(()) => {
+                self.logger.log(format!("saved the query as {name}"));
+                self.saved_queries.insert(name.to_string(), query);
+            }
+            Err(err) => self.logger.log(format!("could not save the query: {err}")),
+        }
+    }
+
+    /// Puts the saved query in the Query pane and focuses it.
+    fn load_query(&mut self, name: &str) {
+        match self.saved_queries.get(name) {
+            Some(saved) => {
+                self.query.set_query(saved.language, saved.query.clone());
+                self.current_screen = CurrentScreen::Query;
+            }
+            None => self.logger.log(format!("no saved query named {name}")),
+        }
+    }
+
+    fn run_command(&mut self, command: &str) {
+        let command = command.trim();
+        if let Some((name, argument)) = command.split_once(' ') {
+            let argument = argument.trim();
+            match name {
+                "save" => self.save_query(argument),
+                "load" => self.load_query(argument),
+                _ => self.logger.log(format!("unknown command: {command}")),
+            }
+            return;
+        }
+        match command {
+            "" => {}
+            "save" => self.logger.log("usage: :save NAME"),
+            // lists the names
+            "load" => match self.saved_queries.len() {
+                0 => self
+                    .logger
+                    .log("no saved queries, save one with :save NAME"),
+                _ => self.logger.log(format!(
+                    "saved queries: {}",
+                    self.saved_queries
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+            "w" => self.save(),
+            "q" => self.quit(),
+            "q!" => self.done = true,
+            "wq" | "x" => {
+                self.save();
+                self.quit();
+            }
+            command => self.logger.log(format!("unknown command: {command}")),
+        }
+    }
+
+    fn handle_editor_input(&mut self, event: Option<Event>) {
+        let Some((target, editor)) = &mut self.editor else {
+            return;
+        };
+        match editor.handle_input(event) {
+            Some(LineInputResult::Submit(text)) => {
+                let target = target.clone();
+                match self.submit_edit(&target, &text) {
+                    Ok(edit) => {
+                        self.editor = None;
+                        self.apply_edit(edit);
+                    }
+                    Err(err) => self.logger.log(err),
+                }
+            }
+            Some(LineInputResult::Cancel) => self.editor = None,
+            None => {}
+        }
+    }
+
+    fn handle_command_line_input(&mut self, event: Option<Event>) {
+        let Some(command_line) = &mut self.command_line else {
+            return;
+        };
+        match command_line.handle_input(event) {
+            Some(LineInputResult::Submit(command)) => {
+                self.command_line = None;
+                self.run_command(&command);
+            }
+            Some(LineInputResult::Cancel) => self.command_line = None,
+            None => {}
+        }
+    }
+
     pub fn done(&self) -> bool {
         self.done
     }
 
-    fn recalculate_query(&mut self, _query: &str) {
-        self.logger.log("recalculate_query");
+    /// Cancels the query that is running, and runs it again at `due`.
+    fn restart_query(&mut self, due: Instant) {
+        if let Some(task) = self.query_task.take() {
+            task.cancel();
+            self.cancelled_queries.push(task);
+        }
+        self.query_due = Some(due);
+    }
+
+    /// Stops the query that is running before the document changes, so that the document
+    /// doesn't have to be copied for it. It runs again right away.
+    fn stop_query(&mut self) {
+        if let Some(task) = self.query_task.take() {
+            task.stop();
+            self.query_due = Some(Instant::now());
+        }
+        for task in self.cancelled_queries.drain(..) {
+            task.stop();
+        }
+    }
+
+    /// Starts the query once it is due and shows its results once it finished.
+    fn poll_query(&mut self) {
+        if let Some(query) = self.query.get_if_changed() {
+            // an empty query has nothing to wait for
+            let delay = match query.trim() {
+                "" => Duration::ZERO,
+                _ => QUERY_DEBOUNCE,
+            };
+            self.query.set_completion(None);
+            self.restart_query(Instant::now() + delay);
+        }
+        self.cancelled_queries.retain(|task| !task.is_finished());
+        if self.query_due.is_some_and(|due| due <= Instant::now()) {
+            self.query_due = None;
+            let value = self.document.snapshot();
+            let language = self.query.language();
+            let query = self.query.get().to_string();
+            let project = self.projection.is_some();
+            self.query_task = Some(QueryTask::spawn(move || {
+                evaluate(&value, language, &query, project)
+            }));
+            self.query.set_running(true);
+        }
+        if self.query_task.as_ref().is_some_and(QueryTask::is_finished) {
+            let evaluation = self.query_task.take().unwrap().join();
+            self.query.set_running(false);
+            self.show_evaluation(evaluation);
+        }
+    }
+
+    fn show_evaluation(&mut self, evaluation: Evaluation) {
+        self.query.set_completion(evaluation.completion);
+        match evaluation.results {
+            Ok(results) => {
+                self.query.set_failure(None);
+                self.query.set_results(results);
+                self.matches = evaluation.matches;
+                if let (Some(_), Some(projection)) = (&self.projection, evaluation.projection) {
+                    self.projection = Some(projection);
+                }
+            }
+            // the results and highlights of the last query that ran are kept
+            Err(failure) => self.query.set_failure(Some(failure)),
+        }
+        self.query
+            .set_evaluated(evaluation.language, evaluation.query);
+
+        let target = self.projection_target.take().and_then(|path| {
+            let projection = self.projection.as_ref()?;
+            projection.projected_path(&path)
+        });
+        match target {
+            Some(path) => self.go_to_projected(path),
+            None => self.render_value(self.viewer.window(), None),
+        }
     }
 
     fn toggle_screen(&mut self) {
@@ -71,13 +1072,11 @@ impl<'a> App<'a> {
     }
 
     pub fn draw(&mut self, f: &mut Frame<'_>) {
-        if let Some(new_query) = self.query.get_if_changed().map(|s| s.to_string()) {
-            self.recalculate_query(&new_query);
-        }
+        self.poll_query();
 
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Min(1), Constraint::Length(30)])
+            .constraints([Constraint::Min(1), Constraint::Length(PANE_WIDTH)])
             .split(f.size());
         self.render_viewer_screen(f, chunks[0], self.current_screen.is_viewer());
         self.render_query_screen(f, chunks[1], self.current_screen.is_query());
@@ -85,40 +1084,295 @@ impl<'a> App<'a> {
         if self.show_logs {
             self.logs.draw(f);
         }
+
+        self.string_popup.draw(f);
+        self.diagnostics.draw(f);
+        self.confirm_quit.draw(f);
     }
 
     fn render_viewer_screen(&mut self, f: &mut Frame<'_>, chunk: Rect, active: bool) {
         let chunk = inset_chunk(chunk);
-        f.render_widget(container_block("Explorer", active), chunk);
+        let mut title = "Explorer".to_string();
+        if self.projection.is_some() {
+            title += " (results)";
+        }
+        if self.history.is_modified() {
+            title += " [+]";
+        }
+        if !self.document.extensions().is_empty() {
+            let extensions = self.document.extensions().names().join(", ");
+            title += &format!(" (lenient: {extensions})");
+        }
+        match self.document.errors().len() {
+            0 => {}
+            1 => title += " (1 error)",
+            n => title += &format!(" ({n} errors)"),
+        }
+        f.render_widget(container_block(&title, active), chunk);
+        if let Some(window) = self.viewer.required_window(chunk) {
+            self.render_value(window, None);
+        }
         self.viewer.draw(f, chunk);
+
+        // the viewer leaves a row of padding around the lines
+        let inner = chunk.inner(&Margin {
+            vertical: 1,
+            horizontal: 1,
+        });
+        if let Some((target, editor)) = &self.editor {
+            let row = self.viewer.cursor_row().clamp(0, inner.height as i32 - 1);
+            let label = match target {
+                EditTarget::Value(_) => "value: ",
+                EditTarget::Key(_) => "key: ",
+                EditTarget::Insert(_) => "new: ",
+            };
+            let area = Rect {
+                y: inner.y + row as u16,
+                ..inner
+            };
+            editor.draw(f, area, label);
+        }
+        if let Some(command_line) = &self.command_line {
+            let area = Rect {
+                y: inner.bottom().saturating_sub(1),
+                ..inner
+            };
+            command_line.draw(f, area, ":");
+        }
     }
 
     fn render_query_screen(&mut self, f: &mut Frame<'_>, chunk: Rect, active: bool) {
         let chunk = inset_chunk(chunk);
-        f.render_widget(container_block("Query", active), chunk);
-        self.query.draw(f, chunk);
+        let title = format!("Query ({})", self.query.language().name());
+        f.render_widget(container_block(&title, active), chunk);
+        self.query.draw(f, chunk, active);
     }
 
     pub fn handle_event(&mut self, event: Option<Event>) {
-        match event {
-            Some(Event::Key(ke)) => match ke.code {
-                KeyCode::Char('q') => self.done = true,
+        if self.confirm_quit.is_visible() {
+            if let Some(answer) = self.confirm_quit.handle_input(event) {
+                self.done = answer;
+            }
+            return;
+        }
+        if self.string_popup.is_visible() {
+            self.string_popup.handle_input(event);
+            return;
+        }
+        if self.diagnostics.is_visible() {
+            if let Some(path) = self.diagnostics.handle_input(event) {
+                self.go_to(path);
+            }
+            return;
+        }
+
+        if self.editor.is_some() {
+            self.handle_editor_input(event);
+            return;
+        }
+        if self.command_line.is_some() {
+            self.handle_command_line_input(event);
+            return;
+        }
+
+        if let Some(Event::Key(ke)) = event {
+            match ke.code {
+                // the keys typed in the Query pane edit the query, `Tab` accepts a completion and
+                // doesn't leave the input, `Esc` does
+                _ if self.current_screen.is_query() && self.query.is_completing() => {}
+                KeyCode::Char(_) | KeyCode::Tab
+                    if self.current_screen.is_query() && self.query.is_editing() => {}
+                KeyCode::Char('q') => self.quit(),
                 KeyCode::Tab => self.toggle_screen(),
                 KeyCode::Char('I') => self.show_logs = !self.show_logs,
                 _ => {}
-            },
-            _ => {}
+            }
         }
 
         match self.current_screen {
             CurrentScreen::Viewer => {
-                self.viewer.handle_input(event);
+                if let Some(command) = self.viewer.handle_input(event) {
+                    self.process_viewer_command(command);
+                }
                 self.query.reset_input_state();
             }
             CurrentScreen::Query => {
                 self.viewer.reset_input_state();
-                self.query.handle_input(event);
+                match self.query.handle_input(event) {
+                    Some(QueryAction::JumpTo(path)) => {
+                        self.go_to(path);
+                        self.current_screen = CurrentScreen::Viewer;
+                    }
+                    Some(QueryAction::Leave) => self.current_screen = CurrentScreen::Viewer,
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyEvent, KeyModifiers};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use crate::value::parse;
+
+    /// The results of `.a[1], [.b], .a` on `{"a": [1, [2, 3]], "b": 4}`.
+    fn projection() -> Projection {
+        Projection::new(vec![
+            Output::Ref(vec![0, 1], &MISSING),
+            Output::Owned(parse("[4, [5]]").unwrap()),
+            Output::Ref(vec![0], &MISSING),
+        ])
+    }
+
+    #[test]
+    fn projection_paths() {
+        let document = parse(r#"{"a": [1, [2, 3]], "b": 4}"#).unwrap();
+        let projection = projection();
+        let values = projection.values(&document);
+        assert_eq!(jq::to_json(values[0]), "[2,3]");
+        assert_eq!(jq::to_json(values[1]), "[4,[5]]");
+        assert_eq!(projection.get(&document, &[0, 1]).unwrap().to_string(), "3");
+        assert_eq!(
+            projection.get(&document, &[1, 1, 0]).unwrap().to_string(),
+            "5"
+        );
+        assert!(projection.get(&document, &[]).is_none());
+
+        // from the projection to the document, built values aren't in it
+        assert_eq!(projection.document_path(&[0, 1]), Some(vec![0, 1, 1]));
+        assert_eq!(projection.document_path(&[2, 0]), Some(vec![0, 0]));
+        assert_eq!(projection.document_path(&[1, 0]), None);
+        assert_eq!(projection.document_path(&[3]), None);
+
+        // from the document to the first result that contains the value
+        assert_eq!(projection.projected_path(&[0, 1, 0]), Some(vec![0, 0]));
+        assert_eq!(projection.projected_path(&[0, 0]), Some(vec![2, 0]));
+        assert_eq!(projection.projected_path(&[0]), Some(vec![2]));
+        assert_eq!(projection.projected_path(&[1]), None);
+
+        let numbers = projection.find_paths(&document, Value::is_number);
+        assert_eq!(numbers[0], [0, 0]);
+        assert_eq!(numbers[2], [1, 0]);
+    }
+
+    #[test]
+    fn projection_after_edit() {
+        let mut document = Document::parse(r#"{"a": [1, [2, 3]], "b": 4}"#.into()).unwrap();
+        document.apply(Edit::remove(vec![0, 1])).unwrap();
+        let projection = projection();
+        let values = projection.values(document.get());
+        assert!(std::ptr::eq(values[0], &MISSING));
+        assert_eq!(jq::to_json(values[2]), "[1]");
+        assert!(projection.get(document.get(), &[0]).is_none());
+    }
+
+    #[test]
+    fn go_to_projected() {
+        let document = Document::parse(r#"{"a": [1, [2, 3]], "b": 4}"#.into()).unwrap();
+        let mut app = App::new(document, Config::default());
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|f| app.draw(f)).unwrap();
+        let mut projection = projection();
+        for depth in 1..=2 {
+            match projection.get_built_mut(&[1, 1][..depth]).unwrap() {
+                Value::Array(arr) => arr.collapse(),
+                _ => unreachable!(),
             }
         }
+        app.projection = Some(projection);
+
+        // the containers of a built value are expanded in the projection
+        app.go_to_projected(vec![1, 1, 0]);
+        let projection = app.projection.as_ref().unwrap();
+        let document = app.document.get();
+        let expanded = |value: &Value| matches!(value, Value::Array(arr) if !arr.collapse);
+        assert!(expanded(projection.get(document, &[1]).unwrap()));
+        assert!(expanded(projection.get(document, &[1, 1]).unwrap()));
+        assert_eq!(app.viewer.node_at_cursor(), Some(&[1, 1, 0][..]));
+
+        // and the ones of a value of the document in the document
+        app.document.toggle_collapse(&[0, 1]).unwrap();
+        app.go_to_projected(vec![0, 1]);
+        assert!(expanded(app.document.get().get_path(&[0, 1]).unwrap()));
+        assert_eq!(app.viewer.node_at_cursor(), Some(&[0, 1][..]));
+    }
+
+    fn screen(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|f| app.draw(f)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let mut screen = String::new();
+        for y in 0..20 {
+            for x in 0..80 {
+                screen.push_str(buffer.get(x, y).symbol());
+            }
+            screen.push('\n');
+        }
+        screen
+    }
+
+    #[test]
+    fn failing_query_keeps_results() {
+        let document = Document::parse(r#"{"a": [1, 2], "b": 3}"#.into()).unwrap();
+        let mut app = App::new(document, Config::default());
+        app.query.set_query(Language::Path, ".a[*]".to_string());
+        let evaluation = evaluate(app.document.get(), Language::Path, ".a[*]", false);
+        app.show_evaluation(evaluation);
+        assert_eq!(app.matches, [vec![0, 0], vec![0, 1]]);
+        assert!(screen(&mut app).contains("2 matches"));
+
+        app.query.set_query(Language::Path, ".a[".to_string());
+        let evaluation = evaluate(app.document.get(), Language::Path, ".a[", false);
+        assert!(evaluation.results.is_err());
+        app.show_evaluation(evaluation);
+        // the highlights and the results of `.a[*]` stay, under the error
+        assert_eq!(app.matches, [vec![0, 0], vec![0, 1]]);
+        let screen = screen(&mut app);
+        assert!(screen.contains("2 matches"));
+        assert!(screen.contains("unexpected end"));
+        assert!(screen.contains(".a[1]"));
+    }
+
+    #[test]
+    fn tab_in_query_input() {
+        let document = Document::parse(r#"{"a": 1}"#.into()).unwrap();
+        let mut app = App::new(document, Config::default());
+        let key = |app: &mut App, code| {
+            app.handle_event(Some(Event::Key(KeyEvent::new(code, KeyModifiers::NONE))));
+        };
+        key(&mut app, KeyCode::Char('.'));
+        key(&mut app, KeyCode::Tab);
+        assert!(app.current_screen.is_query());
+        assert_eq!(app.query.get(), ".");
+
+        key(&mut app, KeyCode::Esc);
+        assert!(app.current_screen.is_viewer());
+        key(&mut app, KeyCode::Tab);
+        assert!(app.current_screen.is_query());
+    }
+
+    #[test]
+    fn save_with_tabs() {
+        let dir = std::env::temp_dir().join(format!("tispect-app-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("doc.json");
+        std::fs::write(&path, "{\n\t\"a\": 1\n}\n").unwrap();
+
+        let mut config = Config::default();
+        config.layout.indent = Indent::TAB;
+        let mut app = App::new(Document::load(&path).unwrap(), config);
+        app.apply_edit(Edit::replace(
+            vec![0],
+            parse(r#"{"b": 1, "c": 2}"#).unwrap(),
+        ));
+        app.save();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // the Explorer shows tabs as spaces, the file keeps them
+        assert_eq!(text, "{\n\t\"a\": {\n\t\t\"b\": 1,\n\t\t\"c\": 2\n\t}\n}\n");
     }
 }
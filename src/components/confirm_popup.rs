@@ -0,0 +1,71 @@
+use crossterm::event::{Event, KeyCode};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::utils::centered_rect;
+
+/// Asks a yes or no question.
+pub struct ConfirmPopup {
+    message: Option<String>,
+}
+
+impl ConfirmPopup {
+    pub fn new() -> Self {
+        Self { message: None }
+    }
+
+    pub fn show(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.message.is_some()
+    }
+
+    /// Returns the answer once there is one, `Esc` is a no.
+    pub fn handle_input(&mut self, event: Option<Event>) -> Option<bool> {
+        let Some(Event::Key(ke)) = event else {
+            return None;
+        };
+        let answer = match ke.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => true,
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => false,
+            _ => return None,
+        };
+        self.message = None;
+        Some(answer)
+    }
+
+    pub fn draw(&self, f: &mut Frame<'_>) {
+        let Some(message) = &self.message else {
+            return;
+        };
+
+        let block = Block::default()
+            .title("Confirm")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain)
+            .style(Style::default().bg(Color::DarkGray));
+        let p = Paragraph::new(format!("{message} (y/n)"))
+            .wrap(Wrap { trim: false })
+            .block(block);
+
+        let area = centered_rect(50, 20, f.size());
+        let area = Rect {
+            height: area.height.min(4),
+            ..area
+        };
+        f.render_widget(Clear, area);
+        f.render_widget(p, area);
+    }
+}
+
+impl Default for ConfirmPopup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,98 @@
+use crossterm::event::{Event, KeyCode};
+use ratatui::{
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, Clear, List, ListState, Paragraph},
+    Frame,
+};
+
+use crate::utils::centered_rect;
+
+/// A problem found in the document, located at `path`.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub path: Vec<usize>,
+    pub message: String,
+}
+
+/// Lists the diagnostics of the document, selecting one jumps to it.
+pub struct DiagnosticsPopup {
+    diagnostics: Vec<Diagnostic>,
+    state: ListState,
+    visible: bool,
+}
+
+impl DiagnosticsPopup {
+    pub fn new() -> Self {
+        Self {
+            diagnostics: Vec::new(),
+            state: ListState::default(),
+            visible: false,
+        }
+    }
+
+    pub fn show(&mut self, diagnostics: Vec<Diagnostic>) {
+        self.state.select((!diagnostics.is_empty()).then_some(0));
+        self.diagnostics = diagnostics;
+        self.visible = true;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    fn select(&mut self, delta: isize) {
+        if let Some(selected) = self.state.selected() {
+            let last = self.diagnostics.len() - 1;
+            self.state
+                .select(Some(selected.saturating_add_signed(delta).min(last)));
+        }
+    }
+
+    /// Returns the path of the diagnostic to jump to once one is chosen.
+    pub fn handle_input(&mut self, event: Option<Event>) -> Option<Vec<usize>> {
+        if let Some(Event::Key(ke)) = event {
+            match ke.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('D') => self.visible = false,
+                KeyCode::Char('j') | KeyCode::Down => self.select(1),
+                KeyCode::Char('k') | KeyCode::Up => self.select(-1),
+                KeyCode::Enter => {
+                    let selected = self.state.selected()?;
+                    self.visible = false;
+                    return Some(self.diagnostics[selected].path.clone());
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    pub fn draw(&mut self, f: &mut Frame<'_>) {
+        if !self.visible {
+            return;
+        }
+
+        let block = Block::default()
+            .title(format!("Diagnostics ({})", self.diagnostics.len()))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain)
+            .style(Style::default().bg(Color::DarkGray));
+        let area = centered_rect(70, 60, f.size());
+        f.render_widget(Clear, area);
+
+        if self.diagnostics.is_empty() {
+            f.render_widget(Paragraph::new("no problems found").block(block), area);
+            return;
+        }
+
+        let list = List::new(self.diagnostics.iter().map(|d| d.message.as_str()))
+            .highlight_style(Style::default().fg(Color::Black).bg(Color::Gray))
+            .block(block);
+        f.render_stateful_widget(list, area, &mut self.state);
+    }
+}
+
+impl Default for DiagnosticsPopup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
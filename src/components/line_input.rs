@@ -0,0 +1,385 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Clear, Paragraph},
+    Frame,
+};
+
+use crate::vi::{
+    vimotions,
+    vistate::{ViCommand, ViState},
+};
+
+pub enum LineInputResult {
+    Submit(String),
+    Cancel,
+}
+
+/// Whether keys are typed, or are vi commands when the input has a vi mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Insert,
+    Normal,
+}
+
+/// A single line of text being edited, the cursor is counted in characters.
+///
+/// Besides moving with the arrows it supports `Ctrl-a` / `Ctrl-e` to go to the start or end,
+/// `Ctrl-Left` / `Ctrl-Right` (or `Alt-b` / `Alt-f`) to move by words, `Ctrl-w` / `Ctrl-u` to
+/// delete the word before the cursor or everything before it, and pasting. With a vi mode `Esc`
+/// switches to a normal mode, with the motions of the viewer and `x`, `D`, `dd`, `cw`, `i`, `a`,
+/// `I` and `A`.
+pub struct LineInput {
+    text: String,
+    cursor: usize,
+    /// The commands of the normal mode, `None` without a vi mode.
+    vi: Option<ViState>,
+    mode: Mode,
+}
+
+impl LineInput {
+    pub fn new(text: String, cursor: usize) -> Self {
+        let cursor = cursor.min(text.chars().count());
+        Self {
+            text,
+            cursor,
+            vi: None,
+            mode: Mode::Insert,
+        }
+    }
+
+    pub fn set_vi_mode(&mut self, enabled: bool) {
+        self.vi = enabled.then(ViState::new);
+        self.mode = Mode::Insert;
+    }
+
+    pub fn is_normal_mode(&self) -> bool {
+        self.mode == Mode::Normal
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, with the cursor at its end.
+    pub fn set_text(&mut self, text: String) {
+        self.cursor = text.chars().count();
+        self.text = text;
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The number of characters scrolled out on the left so that the cursor is visible in
+    /// `width` columns.
+    pub fn scroll(&self, width: usize) -> usize {
+        (self.cursor + 1).saturating_sub(width.max(1))
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn byte_offset(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    fn insert(&mut self, c: char) {
+        let offset = self.byte_offset(self.cursor);
+        self.text.insert(offset, c);
+        self.cursor += 1;
+    }
+
+    fn delete(&mut self, cursor: usize) {
+        if cursor < self.text.chars().count() {
+            let offset = self.byte_offset(cursor);
+            self.text.remove(offset);
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.delete(self.cursor);
+        }
+    }
+
+    /// Deletes the characters from `start` to `end`, and moves the cursor to `start`.
+    fn delete_range(&mut self, start: usize, end: usize) {
+        let range = self.byte_offset(start)..self.byte_offset(end);
+        self.text.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    /// Inserts pasted text, line breaks become spaces.
+    fn paste(&mut self, text: &str) {
+        for c in text.trim_end_matches(['\r', '\n']).chars() {
+            match c {
+                '\r' | '\n' => self.insert(' '),
+                c if c.is_control() => {}
+                c => self.insert(c),
+            }
+        }
+    }
+
+    fn chars(&self) -> Vec<char> {
+        self.text.chars().collect()
+    }
+
+    /// The start of the next word, or the end of the text.
+    fn next_word(&self) -> usize {
+        let chars = self.chars();
+        match vimotions::next_word(&chars[self.cursor.min(chars.len())..]) {
+            [0, delta] => self.cursor + delta as usize,
+            _ => chars.len(),
+        }
+    }
+
+    fn previous_word(&self) -> usize {
+        let chars = self.chars();
+        let before = chars[..self.cursor]
+            .iter()
+            .rev()
+            .copied()
+            .collect::<Vec<_>>();
+        self.cursor - vimotions::previous_word(&before)
+    }
+
+    /// The end of the word under the cursor, without the spaces after it.
+    fn word_end(&self) -> usize {
+        let chars = self.chars();
+        let mut end = self.next_word();
+        while end > self.cursor + 1 && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        end
+    }
+
+    pub fn handle_input(&mut self, event: Option<Event>) -> Option<LineInputResult> {
+        let ke = match event {
+            Some(Event::Key(ke)) => ke,
+            Some(Event::Paste(text)) => {
+                self.paste(&text);
+                return None;
+            }
+            _ => return None,
+        };
+        if self.mode == Mode::Normal {
+            return self.handle_normal_input(ke);
+        }
+
+        let ctrl = ke.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = ke.modifiers.contains(KeyModifiers::ALT);
+        match ke.code {
+            KeyCode::Enter => return Some(LineInputResult::Submit(self.text.clone())),
+            KeyCode::Esc if self.vi.is_some() => {
+                self.mode = Mode::Normal;
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            KeyCode::Esc => return Some(LineInputResult::Cancel),
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.len(),
+            KeyCode::Char('w') if ctrl => self.delete_range(self.previous_word(), self.cursor),
+            KeyCode::Char('u') if ctrl => self.delete_range(0, self.cursor),
+            KeyCode::Char('b') if alt => self.cursor = self.previous_word(),
+            KeyCode::Char('f') if alt => self.cursor = self.next_word(),
+            KeyCode::Left if ctrl => self.cursor = self.previous_word(),
+            KeyCode::Right if ctrl => self.cursor = self.next_word(),
+            // other shortcuts aren't text
+            KeyCode::Char(_) if ctrl || alt => {}
+            KeyCode::Char(c) => self.insert(c),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(self.cursor),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.chars().count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.chars().count(),
+            _ => {}
+        }
+        None
+    }
+
+    fn handle_normal_input(&mut self, ke: KeyEvent) -> Option<LineInputResult> {
+        let vi = self.vi.as_mut()?;
+        match ke.code {
+            KeyCode::Enter => {
+                vi.reset();
+                return Some(LineInputResult::Submit(self.text.clone()));
+            }
+            KeyCode::Esc => {
+                vi.reset();
+                return Some(LineInputResult::Cancel);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor += 1,
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.len(),
+            // keys that only make sense in a single line
+            KeyCode::Char(c) if !vi.is_pending() && "xDaAI".contains(c) => match c {
+                'x' => self.delete(self.cursor),
+                'D' => self.delete_range(self.cursor, self.len()),
+                'a' => {
+                    self.mode = Mode::Insert;
+                    self.cursor = (self.cursor + 1).min(self.len());
+                }
+                'A' => {
+                    self.mode = Mode::Insert;
+                    self.cursor = self.len();
+                }
+                _ => {
+                    self.mode = Mode::Insert;
+                    self.cursor = 0;
+                }
+            },
+            KeyCode::Char(c) => match vi.process(c) {
+                Some(ViCommand::Left) => self.cursor = self.cursor.saturating_sub(1),
+                Some(ViCommand::Right) => self.cursor += 1,
+                Some(ViCommand::MoveWordForward) => self.cursor = self.next_word(),
+                Some(ViCommand::MoveWordBackward) => self.cursor = self.previous_word(),
+                Some(ViCommand::FirstColumn) => self.cursor = 0,
+                Some(ViCommand::LastColumn) => self.cursor = self.len(),
+                Some(ViCommand::JumpNextChar(c)) => {
+                    let chars = self.chars();
+                    if self.cursor < chars.len() {
+                        let delta = vimotions::jump_next_char(&chars[self.cursor..], c);
+                        self.cursor += delta as usize;
+                    }
+                }
+                Some(ViCommand::JumpPreviousChar(c)) => {
+                    let chars = self.chars();
+                    if self.cursor < chars.len() {
+                        let before = chars[..=self.cursor]
+                            .iter()
+                            .rev()
+                            .copied()
+                            .collect::<Vec<_>>();
+                        self.cursor -= vimotions::jump_next_char(&before, c) as usize;
+                    }
+                }
+                Some(ViCommand::EditValue) => self.mode = Mode::Insert,
+                Some(ViCommand::ChangeValue) => {
+                    self.delete_range(self.cursor, self.word_end());
+                    self.mode = Mode::Insert;
+                }
+                Some(ViCommand::Delete) => self.delete_range(0, self.len()),
+                _ => {}
+            },
+            _ => {}
+        }
+        // in the normal mode the cursor is on a character
+        if self.mode == Mode::Normal {
+            self.cursor = self.cursor.min(self.len().saturating_sub(1));
+        }
+        None
+    }
+
+    /// Draws the text after `label` on the first row of `area`, scrolled so that the cursor is
+    /// visible.
+    pub fn draw(&self, f: &mut Frame<'_>, area: Rect, label: &str) {
+        let area = Rect { height: 1, ..area };
+        let label_width = label.chars().count();
+        let width = (area.width as usize).saturating_sub(label_width + 1).max(1);
+        let skip = self.scroll(width);
+        let visible = self.text.chars().skip(skip).take(width).collect::<String>();
+
+        let line = Line::from(vec![
+            Span::styled(label.to_string(), Style::default().fg(Color::Yellow)),
+            Span::raw(visible),
+        ]);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(line).style(Style::default().bg(Color::DarkGray)),
+            area,
+        );
+        f.set_cursor(area.x + (label_width + self.cursor - skip) as u16, area.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyEvent, KeyModifiers};
+
+    use super::*;
+
+    fn key(code: KeyCode) -> Option<Event> {
+        Some(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+    }
+
+    fn ctrl(c: char) -> Option<Event> {
+        Some(Event::Key(KeyEvent::new(
+            KeyCode::Char(c),
+            KeyModifiers::CONTROL,
+        )))
+    }
+
+    fn typed(input: &mut LineInput, keys: &str) {
+        for c in keys.chars() {
+            input.handle_input(key(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn edit() {
+        let mut input = LineInput::new("héllo".to_string(), 2);
+        input.handle_input(key(KeyCode::Backspace));
+        input.handle_input(key(KeyCode::Char('e')));
+        input.handle_input(key(KeyCode::End));
+        input.handle_input(key(KeyCode::Char('!')));
+        input.handle_input(key(KeyCode::Home));
+        input.handle_input(key(KeyCode::Delete));
+        assert_eq!(input.text(), "ello!");
+        assert!(matches!(
+            input.handle_input(key(KeyCode::Enter)),
+            Some(LineInputResult::Submit(text)) if text == "ello!"
+        ));
+    }
+
+    #[test]
+    fn shortcuts() {
+        let mut input = LineInput::new(".users[*].name".to_string(), 14);
+        input.handle_input(ctrl('w'));
+        assert_eq!(input.text(), ".users[*].");
+        input.handle_input(ctrl('w'));
+        assert_eq!(input.text(), ".users");
+        input.handle_input(ctrl('a'));
+        input.handle_input(Some(Event::Key(KeyEvent::new(
+            KeyCode::Right,
+            KeyModifiers::CONTROL,
+        ))));
+        assert_eq!(input.cursor(), 1);
+        input.handle_input(ctrl('e'));
+        input.handle_input(Some(Event::Paste("[*]\n.id\n".to_string())));
+        assert_eq!(input.text(), ".users[*] .id");
+        input.handle_input(key(KeyCode::Left));
+        input.handle_input(ctrl('u'));
+        assert_eq!(input.text(), "d");
+    }
+
+    #[test]
+    fn vi_mode() {
+        let mut input = LineInput::new(".a.bc.d".to_string(), 7);
+        input.set_vi_mode(true);
+        input.handle_input(key(KeyCode::Esc));
+        assert!(input.is_normal_mode());
+        typed(&mut input, "0fbcwxy");
+        assert_eq!(input.text(), ".a.xy.d");
+        assert!(!input.is_normal_mode());
+
+        input.handle_input(key(KeyCode::Esc));
+        typed(&mut input, "$xA!");
+        assert_eq!(input.text(), ".a.xy.!");
+        input.handle_input(key(KeyCode::Esc));
+        typed(&mut input, "dd");
+        assert_eq!(input.text(), "");
+        assert!(matches!(
+            input.handle_input(key(KeyCode::Esc)),
+            Some(LineInputResult::Cancel)
+        ));
+    }
+}
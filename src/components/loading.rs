@@ -0,0 +1,47 @@
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    widgets::{Block, Borders, Gauge},
+    Frame,
+};
+
+/// Shown while the document is being loaded.
+pub struct Loading {
+    label: String,
+}
+
+impl Loading {
+    pub fn new(label: impl ToString) -> Self {
+        Self {
+            label: label.to_string(),
+        }
+    }
+
+    pub fn draw(&self, f: &mut Frame<'_>, progress: Option<f64>) {
+        let area = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ])
+            .horizontal_margin(4)
+            .split(f.size())[1];
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(self.label.as_str());
+        let gauge = match progress {
+            Some(ratio) => Gauge::default()
+                .ratio(ratio)
+                .label(format!("{:.0}%", ratio * 100.0)),
+            None => Gauge::default().ratio(0.0).label("parsing..."),
+        };
+        f.render_widget(
+            gauge
+                .block(block)
+                .gauge_style(Style::default().fg(Color::Cyan)),
+            area,
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod confirm_popup;
+pub mod diagnostics;
+pub mod line_input;
+pub mod loading;
 pub mod log_popup;
 pub mod query;
+pub mod string_popup;
 pub mod viewer;
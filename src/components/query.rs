@@ -1,90 +1,742 @@
-use crossterm::event::{Event, KeyCode};
+use std::time::Instant;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Paragraph},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-use crate::logger::Logger;
+use crate::{
+    components::line_input::{LineInput, LineInputResult},
+    logger::Logger,
+    query::{
+        complete::Completion,
+        history::{HistoryEntry, QueryHistory},
+        jq, Language,
+    },
+    value::Value,
+};
+
+/// The frames of the spinner shown while the query runs.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// The width of the pane.
+pub const PANE_WIDTH: u16 = 30;
+
+/// How many characters of a result fit in the pane, inside of its margins. The text of the
+/// results is cut to it while it is written.
+pub const RESULT_WIDTH: usize = PANE_WIDTH as usize - 4;
+
+/// A result of the query as listed in the pane.
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    /// The path of a value of the document, `None` for values the query built.
+    pub path: Option<Vec<usize>>,
+    /// The formatted path, or the built value as JSON, cut to [`RESULT_WIDTH`].
+    pub text: String,
+    pub preview: String,
+}
+
+impl QueryResult {
+    pub fn new(path: Option<Vec<usize>>, text: String, value: &Value) -> Self {
+        let width = RESULT_WIDTH.saturating_sub(text.chars().count() + 1);
+        let preview = match path {
+            Some(_) if width > 0 => preview(value, width),
+            _ => String::new(),
+        };
+        Self {
+            path,
+            text,
+            preview,
+        }
+    }
+}
+
+/// A short, single line description of `value`: scalars as JSON, cut to `width` characters, and
+/// the size of containers.
+fn preview(value: &Value, width: usize) -> String {
+    match value {
+        Value::Array(arr) if arr.arr.len() == 1 => "[ 1 item ]".to_string(),
+        Value::Array(arr) => format!("[ {} items ]", arr.arr.len()),
+        Value::Object(object) if object.len() == 1 => "{ 1 key }".to_string(),
+        Value::Object(object) => format!("{{ {} keys }}", object.len()),
+        Value::Lazy(_) => "...".to_string(),
+        value => jq::to_json_preview(value, width),
+    }
+}
+
+/// Why the query couldn't run, shown under the input.
+#[derive(Debug, Clone)]
+pub struct QueryFailure {
+    pub message: String,
+    /// The byte offset in the query of the error, when it is known.
+    pub offset: Option<usize>,
+}
+
+/// What the App does after a key in the pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryAction {
+    /// Move the cursor of the Explorer to the value at the path and focus it.
+    JumpTo(Vec<usize>),
+    /// Go back to the Explorer.
+    Leave,
+}
+
+/// A reverse incremental search through the history, started with `Ctrl-r`.
+#[derive(Debug, Clone)]
+struct HistorySearch {
+    pattern: String,
+    /// The entry shown in the input.
+    found: Option<usize>,
+    /// Whether no entry contains the pattern, the last one that did is still shown.
+    failing: bool,
+    /// What was typed before the search, restored when it is cancelled.
+    draft: HistoryEntry,
+}
+
+/// What the keys act on: the query being typed or the list of its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Input,
+    Results,
+}
 
 pub struct Query {
     logger: Logger,
-    query: String,
+    input: LineInput,
     query_changed: bool,
+    language: Language,
+    results: Vec<QueryResult>,
+    /// The error of the current query, the results are those of the last query that ran.
+    failure: Option<QueryFailure>,
+    /// The query whose results or failure are shown, the input may have changed since.
+    evaluated: Option<HistoryEntry>,
+    /// The query submitted with `Enter` before it was evaluated, it is recorded once it is.
+    submitted: Option<HistoryEntry>,
+    state: ListState,
+    focus: Focus,
+    /// The keys that can complete the end of the query, hidden with `Esc` until it changes.
+    completion: Option<Completion>,
+    completion_state: ListState,
+    history: QueryHistory,
+    /// The entry of the history shown while browsing it with `Up` / `Down`, and what was typed
+    /// before.
+    browsing: Option<(usize, HistoryEntry)>,
+    search: Option<HistorySearch>,
+    /// Since when the query is running on another thread.
+    running: Option<Instant>,
 }
 
 impl Query {
     pub fn new(logger: Logger) -> Self {
         Self {
             logger,
-            query: String::new(),
+            input: LineInput::new(String::new(), 0),
             query_changed: false,
+            language: Language::default(),
+            results: Vec::new(),
+            failure: None,
+            evaluated: None,
+            submitted: None,
+            state: ListState::default(),
+            focus: Focus::Input,
+            completion: None,
+            completion_state: ListState::default(),
+            history: QueryHistory::default(),
+            browsing: None,
+            search: None,
+            running: None,
         }
     }
 
-    pub fn get_if_changed(&mut self) -> Option<&str> {
-        if self.query_changed {
-            self.query_changed = false;
-            Some(&self.query)
+    /// Shows a spinner next to the results while the query runs.
+    pub fn set_running(&mut self, running: bool) {
+        self.running = running.then(|| self.running.unwrap_or_else(Instant::now));
+    }
+
+    pub fn set_history(&mut self, history: QueryHistory) {
+        self.history = history;
+    }
+
+    /// Replaces the query and its language, e.g. with a saved query, and focuses the input.
+    pub fn set_query(&mut self, language: Language, query: String) {
+        self.search = None;
+        self.browsing = None;
+        self.focus = Focus::Input;
+        self.show_entry(HistoryEntry { language, query });
+    }
+
+    fn current_entry(&self) -> HistoryEntry {
+        HistoryEntry {
+            language: self.language,
+            query: self.input.text().to_string(),
+        }
+    }
+
+    fn show_entry(&mut self, entry: HistoryEntry) {
+        self.language = entry.language;
+        self.input.set_text(entry.query);
+        self.query_changed = true;
+    }
+
+    /// Shows the previous (or next) query of the history, after the newest one comes back what
+    /// was being typed.
+    fn browse_history(&mut self, older: bool) {
+        let len = self.history.entries().len();
+        if len == 0 {
+            return;
+        }
+        let (index, draft) = match self.browsing.take() {
+            Some(browsing) => browsing,
+            None => (len, self.current_entry()),
+        };
+        let index = if older {
+            index.saturating_sub(1)
         } else {
-            None
+            (index + 1).min(len)
+        };
+        if index == len {
+            self.show_entry(draft);
+        } else {
+            self.show_entry(self.history.entries()[index].clone());
+            self.browsing = Some((index, draft));
         }
     }
 
-    fn delete_query_char(&mut self) {
-        if !self.query.is_empty() {
-            self.query_changed = true;
-            self.query = self.query[..self.query.len() - 1].to_string();
+    fn start_search(&mut self) {
+        self.browsing = None;
+        self.search = Some(HistorySearch {
+            pattern: String::new(),
+            found: None,
+            failing: false,
+            draft: self.current_entry(),
+        });
+    }
+
+    /// Shows the newest entry before `before` that contains the pattern.
+    fn search_history(&mut self, before: usize) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        match self.history.search(&search.pattern, before) {
+            Some(found) => {
+                search.found = Some(found);
+                search.failing = false;
+                let entry = self.history.entries()[found].clone();
+                self.show_entry(entry);
+            }
+            None => search.failing = true,
+        }
+    }
+
+    /// Returns false when the key ends the search and should be handled by the input.
+    fn handle_search_input(&mut self, ke: KeyEvent) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+        let ctrl = ke.modifiers.contains(KeyModifiers::CONTROL);
+        let len = self.history.entries().len();
+        match ke.code {
+            KeyCode::Char('r') if ctrl => {
+                let before = search.found.unwrap_or(len);
+                self.search_history(before);
+            }
+            KeyCode::Char('g') if ctrl => self.cancel_search(),
+            KeyCode::Esc => self.cancel_search(),
+            KeyCode::Char(c) if !ctrl && !ke.modifiers.contains(KeyModifiers::ALT) => {
+                search.pattern.push(c);
+                // the shown entry stays while it contains the pattern
+                let before = search.found.map_or(len, |found| found + 1);
+                self.search_history(before);
+            }
+            KeyCode::Backspace => {
+                search.pattern.pop();
+                self.search_history(len);
+            }
+            KeyCode::Enter => self.search = None,
+            _ => {
+                self.search = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.show_entry(search.draft);
+        }
+    }
+
+    /// Adds the evaluated query to the history, unless it doesn't run, and focuses its results.
+    fn submit(&mut self) {
+        if let (Some(entry), None) = (&self.evaluated, &self.failure) {
+            if let Err(err) = self.history.record(entry.clone()) {
+                self.logger
+                    .log(format!("could not write the query history: {err}"));
+            }
+        }
+        if !self.results.is_empty() {
+            self.focus = Focus::Results;
+        }
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub fn set_language(&mut self, language: Language) {
+        self.language = language;
+        self.query_changed = true;
+    }
+
+    pub fn set_results(&mut self, results: Vec<QueryResult>) {
+        let selected = self.state.selected().unwrap_or(0);
+        self.state.select(match results.len() {
+            0 => None,
+            n => Some(selected.min(n - 1)),
+        });
+        self.results = results;
+        if self.results.is_empty() {
+            self.focus = Focus::Input;
         }
     }
 
-    fn add_to_query(&mut self, c: char) {
+    pub fn set_failure(&mut self, failure: Option<QueryFailure>) {
+        self.failure = failure;
+    }
+
+    /// Tells which query the results or failure that were just set belong to, a query that was
+    /// submitted before is submitted now.
+    pub fn set_evaluated(&mut self, language: Language, query: String) {
+        let entry = HistoryEntry { language, query };
+        self.evaluated = Some(entry);
+        if self.submitted.is_some() && self.submitted == self.evaluated {
+            self.submitted = None;
+            self.submit();
+        }
+    }
+
+    pub fn set_completion(&mut self, completion: Option<Completion>) {
+        self.completion_state.select(completion.as_ref().map(|_| 0));
+        self.completion = completion;
+    }
+
+    pub fn set_vi_mode(&mut self, enabled: bool) {
+        self.input.set_vi_mode(enabled);
+    }
+
+    /// Whether keys are typed into the query, rather than being commands.
+    pub fn is_typing(&self) -> bool {
+        self.focus == Focus::Input && (!self.input.is_normal_mode() || self.search.is_some())
+    }
+
+    /// Whether keys go to the query input, also in its normal mode where they are vi commands
+    /// like `I` or `fq`.
+    pub fn is_editing(&self) -> bool {
+        self.focus == Focus::Input
+    }
+
+    /// Whether the completions are shown, then `Tab` accepts one. They complete the end of the
+    /// query, so the cursor must be there.
+    pub fn is_completing(&self) -> bool {
+        self.is_typing()
+            && self.search.is_none()
+            && self.completion.is_some()
+            && self.input.cursor() == self.input.text().chars().count()
+    }
+
+    fn select_completion(&mut self, delta: isize) {
+        let (Some(completion), Some(selected)) =
+            (&self.completion, self.completion_state.selected())
+        else {
+            return;
+        };
+        let last = completion.candidates.len() - 1;
+        self.completion_state
+            .select(Some(selected.saturating_add_signed(delta).min(last)));
+    }
+
+    /// Replaces the end of the query with the selected completion.
+    fn accept_completion(&mut self) {
+        let (Some(completion), Some(selected)) =
+            (self.completion.take(), self.completion_state.selected())
+        else {
+            return;
+        };
+        let query = &self.input.text()[..completion.start];
+        let query = format!("{query}{}", completion.candidates[selected].text);
+        self.input.set_text(query);
         self.query_changed = true;
-        self.query = format!("{}{}", self.query, c);
+    }
+
+    pub fn get(&self) -> &str {
+        self.input.text()
+    }
+
+    pub fn get_if_changed(&mut self) -> Option<&str> {
+        if self.query_changed {
+            self.query_changed = false;
+            Some(self.input.text())
+        } else {
+            None
+        }
     }
 
     pub fn reset_input_state(&mut self) {}
 
-    pub fn handle_input(&mut self, event: Option<Event>) {
-        match event {
-            Some(Event::Key(ke)) => match ke.code {
-                KeyCode::Char(c) => {
-                    self.add_to_query(c);
+    fn select(&mut self, delta: isize) {
+        if let Some(selected) = self.state.selected() {
+            let last = self.results.len() - 1;
+            self.state
+                .select(Some(selected.saturating_add_signed(delta).min(last)));
+        }
+    }
+
+    pub fn handle_input(&mut self, event: Option<Event>) -> Option<QueryAction> {
+        if let Some(Event::Key(ke)) = event {
+            let ctrl = ke.modifiers.contains(KeyModifiers::CONTROL);
+            match ke.code {
+                KeyCode::Char('t') if ctrl => {
+                    self.set_language(self.language.next());
+                    return None;
+                }
+                _ if self.focus == Focus::Results => {
+                    match ke.code {
+                        KeyCode::Char('j') | KeyCode::Down => self.select(1),
+                        KeyCode::Char('k') | KeyCode::Up => self.select(-1),
+                        KeyCode::Char('i') | KeyCode::Esc => self.focus = Focus::Input,
+                        KeyCode::Enter => {
+                            let selected = self.state.selected()?;
+                            let path = self.results[selected].path.clone()?;
+                            self.focus = Focus::Input;
+                            return Some(QueryAction::JumpTo(path));
+                        }
+                        _ => {}
+                    }
+                    return None;
+                }
+                // the keys that end the search are handled as usual
+                _ if self.search.is_some() && self.handle_search_input(ke) => return None,
+                _ if self.is_completing() => {
+                    match ke.code {
+                        KeyCode::Tab => self.accept_completion(),
+                        KeyCode::Down => self.select_completion(1),
+                        KeyCode::Up => self.select_completion(-1),
+                        KeyCode::Char('n') if ctrl => self.select_completion(1),
+                        KeyCode::Char('p') if ctrl => self.select_completion(-1),
+                        KeyCode::Esc => self.completion = None,
+                        // any other key is typed, and hides the completions until they are
+                        // computed again
+                        _ => {
+                            self.completion = None;
+                            return self.handle_input(event);
+                        }
+                    }
+                    return None;
+                }
+                KeyCode::Char('r') if ctrl => {
+                    self.start_search();
+                    return None;
+                }
+                KeyCode::Up => {
+                    self.browse_history(true);
+                    return None;
+                }
+                KeyCode::Down => {
+                    self.browse_history(false);
+                    return None;
                 }
-                KeyCode::Backspace => self.delete_query_char(),
                 _ => {}
-            },
-            _ => {}
+            }
+        }
+
+        let before = self.input.text().to_string();
+        let result = self.input.handle_input(event);
+        if self.input.text() != before {
+            self.query_changed = true;
+            self.browsing = None;
+            self.submitted = None;
+        }
+        match result {
+            // the results shown may be those of the query before, then it is submitted once the
+            // current one is evaluated
+            Some(LineInputResult::Submit(_)) => {
+                let entry = self.current_entry();
+                match self.evaluated == Some(entry.clone()) && !self.query_changed {
+                    true => self.submit(),
+                    false => self.submitted = Some(entry),
+                }
+                None
+            }
+            Some(LineInputResult::Cancel) => Some(QueryAction::Leave),
+            _ => None,
+        }
+    }
+
+    /// The caret under the input at the column of the error, and the wrapped message.
+    fn failure_lines(&self, width: u16) -> Vec<Line<'_>> {
+        let Some(failure) = &self.failure else {
+            return Vec::new();
+        };
+        let style = Style::default().fg(Color::Red);
+        let mut lines = Vec::new();
+        // the caret points into the query that failed, not into the one typed since
+        let query = self.input.text();
+        let offset = failure
+            .offset
+            .filter(|_| self.evaluated.as_ref().is_some_and(|e| e.query == query));
+        if let Some(offset) = offset {
+            // the input is inside of a border, and scrolled like it
+            let column = query[..query.floor_char_boundary(offset)].chars().count();
+            let column = column.checked_sub(self.input.scroll((width as usize).saturating_sub(2)));
+            if let Some(column) = column.map(|column| column + 1) {
+                if column < width as usize {
+                    lines.push(Line::styled(format!("{}^", " ".repeat(column)), style));
+                }
+            }
         }
+        let chars = failure.message.chars().collect::<Vec<_>>();
+        for line in chars.chunks(width.max(1) as usize) {
+            lines.push(Line::styled(line.iter().collect::<String>(), style));
+        }
+        lines
     }
 
-    pub fn draw(&mut self, f: &mut Frame<'_>, chunk: Rect) {
+    pub fn draw(&mut self, f: &mut Frame<'_>, chunk: Rect, active: bool) {
+        let failure = match self.input.text().trim().is_empty() {
+            true => Vec::new(),
+            false => self.failure_lines(chunk.width.saturating_sub(2)),
+        };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(1)
-            .constraints([Constraint::Length(3), Constraint::Min(1)])
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(failure.len() as u16),
+                Constraint::Length(1),
+                Constraint::Min(1),
+            ])
             .split(chunk);
 
-        // query text area
-        let query_text = if self.query.is_empty() {
-            "enter query here..."
+        // query text area, scrolled so that the cursor is visible
+        let width = chunks[0].width.saturating_sub(2) as usize;
+        let skip = self.input.scroll(width);
+        let query_text = if self.input.text().is_empty() {
+            "enter query here...".to_string()
         } else {
-            &self.query
+            self.input.text().chars().skip(skip).take(width).collect()
         };
+        let mut block = Block::default().borders(Borders::ALL);
+        if let Some(search) = &self.search {
+            block = block.title(match search.failing {
+                true => format!("search: {} (not found)", search.pattern),
+                false => format!("search: {}", search.pattern),
+            });
+        } else if self.input.is_normal_mode() {
+            block = block.title("normal");
+        }
         let base = Paragraph::new(query_text)
             .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-            .block(Block::default().borders(Borders::ALL));
+            .block(block);
+        f.render_widget(base, chunks[0]);
+        if active && self.focus == Focus::Input {
+            let column = (self.input.cursor() - skip) as u16;
+            f.set_cursor(chunks[0].x + 1 + column, chunks[0].y + 1);
+        }
+
+        // results
+        if self.input.text().trim().is_empty() {
+            return;
+        }
+        f.render_widget(Paragraph::new(failure), chunks[1]);
+
+        let mut count = match (self.language, self.results.len()) {
+            (Language::Path | Language::JsonPath, 1) => "1 match".to_string(),
+            (Language::Path | Language::JsonPath, n) => format!("{n} matches"),
+            (_, 1) => "1 result".to_string(),
+            (_, n) => format!("{n} results"),
+        };
+        if let Some(since) = self.running {
+            let frame = since.elapsed().as_millis() / 80;
+            count = format!("{} {count}", SPINNER[frame as usize % SPINNER.len()]);
+        }
         f.render_widget(
-            base,
-            // if self.query.is_empty() {
-            //     base.gray()
-            // } else {
-            //     base.gray()
-            // },
-            chunks[0],
+            Paragraph::new(count).style(Style::default().fg(Color::DarkGray)),
+            chunks[2],
+        );
+
+        let items = self.results.iter().map(|result| {
+            ListItem::new(Line::from(vec![
+                Span::raw(result.text.as_str()),
+                Span::raw(" "),
+                Span::styled(
+                    result.preview.as_str(),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+        });
+        let mut list = List::new(items);
+        if self.focus == Focus::Results {
+            list = list.highlight_style(Style::default().fg(Color::Black).bg(Color::Gray));
+        }
+        f.render_stateful_widget(list, chunks[3], &mut self.state);
+
+        // completions, over the results
+        if let Some(completion) = self
+            .completion
+            .as_ref()
+            .filter(|_| self.focus == Focus::Input && self.search.is_none())
+        {
+            let below = chunks[1].union(chunks[2]).union(chunks[3]);
+            let area = Rect {
+                height: (completion.candidates.len() as u16 + 2).min(below.height),
+                ..below
+            };
+            let list = List::new(
+                completion
+                    .candidates
+                    .iter()
+                    .map(|candidate| candidate.label.as_str()),
+            )
+            .highlight_style(Style::default().fg(Color::Black).bg(Color::Gray))
+            .block(Block::default().title("Tab").borders(Borders::ALL));
+            f.render_widget(Clear, area);
+            f.render_stateful_widget(list, area, &mut self.completion_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(pane: &mut Query, code: KeyCode) -> Option<QueryAction> {
+        pane.handle_input(Some(Event::Key(KeyEvent::new(code, KeyModifiers::NONE))))
+    }
+
+    #[test]
+    fn browse_results() {
+        let value = crate::value::parse("[1, 2]").unwrap();
+        let mut pane = Query::new(Logger::new());
+        pane.set_query(Language::Path, "[*]".to_string());
+        // the App takes the query before it runs it
+        assert_eq!(pane.get_if_changed(), Some("[*]"));
+        pane.set_results(vec![
+            QueryResult::new(
+                Some(vec![0]),
+                "[0]".to_string(),
+                value.get_path(&[0]).unwrap(),
+            ),
+            QueryResult::new(None, "3".to_string(), &value),
+            QueryResult::new(
+                Some(vec![1]),
+                "[1]".to_string(),
+                value.get_path(&[1]).unwrap(),
+            ),
+        ]);
+        pane.set_evaluated(Language::Path, "[*]".to_string());
+        assert_eq!(pane.results[0].preview, "1");
+
+        // Enter submits the query and focuses the results, j and k aren't typed then
+        assert_eq!(key(&mut pane, KeyCode::Enter), None);
+        assert_eq!(key(&mut pane, KeyCode::Char('j')), None);
+        assert_eq!(key(&mut pane, KeyCode::Char('j')), None);
+        assert_eq!(key(&mut pane, KeyCode::Char('j')), None);
+        assert_eq!(pane.get(), "[*]");
+        assert_eq!(
+            key(&mut pane, KeyCode::Enter),
+            Some(QueryAction::JumpTo(vec![1]))
         );
+        // the input is focused again after the jump
+        assert_eq!(key(&mut pane, KeyCode::Char('x')), None);
+        assert_eq!(pane.get(), "[*]x");
+
+        // a value built by the query isn't in the document
+        pane.focus = Focus::Results;
+        assert_eq!(key(&mut pane, KeyCode::Char('k')), None);
+        assert_eq!(key(&mut pane, KeyCode::Enter), None);
+        assert_eq!(key(&mut pane, KeyCode::Char('k')), None);
+        assert_eq!(
+            key(&mut pane, KeyCode::Enter),
+            Some(QueryAction::JumpTo(vec![0]))
+        );
+    }
+
+    fn failing(query: &str, offset: usize) -> Query {
+        let mut pane = Query::new(Logger::new());
+        pane.set_query(Language::Path, query.to_string());
+        pane.set_failure(Some(QueryFailure {
+            message: "unexpected end".to_string(),
+            offset: Some(offset),
+        }));
+        pane.set_evaluated(Language::Path, query.to_string());
+        pane
+    }
+
+    fn caret(pane: &Query, width: u16) -> Option<usize> {
+        let lines = pane.failure_lines(width);
+        let first = lines[0].spans[0].content.as_ref();
+        first.strip_suffix('^').map(|spaces| spaces.len())
+    }
+
+    #[test]
+    fn failure_caret() {
+        // under the character at the offset, one column to the right for the border
+        let pane = failing(".a[", 2);
+        assert_eq!(caret(&pane, 20), Some(3));
+        assert_eq!(pane.failure_lines(20).len(), 2);
+
+        // the offset is in bytes, the caret in characters
+        let pane = failing(".é[", 3);
+        assert_eq!(caret(&pane, 20), Some(3));
+
+        // the input shows the end of the query with the cursor after it: 18 columns inside of
+        // the border, 24 characters scrolled out
+        let query = format!(".{}[", "a".repeat(39));
+        let pane = failing(&query, 40);
+        assert_eq!(pane.input.scroll(18), 24);
+        assert_eq!(caret(&pane, 20), Some(40 - 24 + 1));
+        // scrolled out of the input
+        let pane = failing(&query, 10);
+        assert_eq!(caret(&pane, 20), None);
+        assert_eq!(pane.failure_lines(20).len(), 1);
+    }
+
+    /// The columns of `bracket` in the drawn input and of the caret under it.
+    fn drawn_columns(pane: &mut Query) -> (Option<u16>, Option<u16>) {
+        use ratatui::{backend::TestBackend, Terminal};
+
+        let mut terminal = Terminal::new(TestBackend::new(PANE_WIDTH, 12)).unwrap();
+        terminal.draw(|f| pane.draw(f, f.size(), true)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let find = |y, symbol| (0..PANE_WIDTH).find(|&x| buffer.get(x, y).symbol() == symbol);
+        // the input is on the second line inside of the pane's margin, the caret below its border
+        (find(2, "["), find(4, "^"))
+    }
+
+    #[test]
+    fn failure_caret_drawn() {
+        let mut pane = failing(".a[", 2);
+        let (bracket, caret) = drawn_columns(&mut pane);
+        assert!(bracket.is_some());
+        assert_eq!(bracket, caret);
+
+        let mut pane = failing(&format!(".{}[0", "a".repeat(39)), 40);
+        let (bracket, caret) = drawn_columns(&mut pane);
+        assert!(bracket.is_some());
+        assert_eq!(bracket, caret);
+    }
 
-        // f.render_widget(Paragraph::new("TODO: bottom").white(), chunks[1]);
+    #[test]
+    fn failure_caret_after_edit() {
+        let mut pane = failing(".a[", 2);
+        pane.set_query(Language::Path, ".a[0".to_string());
+        // the message stays until the new query is evaluated, without the caret
+        assert_eq!(caret(&pane, 20), None);
+        assert_eq!(pane.failure_lines(20).len(), 1);
     }
 }
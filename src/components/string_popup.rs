@@ -0,0 +1,70 @@
+use crossterm::event::{Event, KeyCode};
+use ratatui::{
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::utils::centered_rect;
+
+/// Shows a single string value, fully decoded and wrapped.
+pub struct StringPopup {
+    content: Option<String>,
+    scroll: u16,
+}
+
+impl StringPopup {
+    pub fn new() -> Self {
+        Self {
+            content: None,
+            scroll: 0,
+        }
+    }
+
+    pub fn show(&mut self, content: String) {
+        self.content = Some(content);
+        self.scroll = 0;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.content.is_some()
+    }
+
+    pub fn handle_input(&mut self, event: Option<Event>) {
+        if let Some(Event::Key(ke)) = event {
+            match ke.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('K') => self.content = None,
+                KeyCode::Char('j') | KeyCode::Down => self.scroll = self.scroll.saturating_add(1),
+                KeyCode::Char('k') | KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::Char('g') => self.scroll = 0,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn draw(&self, f: &mut Frame<'_>) {
+        let Some(content) = &self.content else {
+            return;
+        };
+
+        let block = Block::default()
+            .title("String")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain)
+            .style(Style::default().bg(Color::DarkGray));
+        let p = Paragraph::new(content.as_str())
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .block(block);
+
+        let area = centered_rect(70, 60, f.size());
+        f.render_widget(Clear, area);
+        f.render_widget(p, area);
+    }
+}
+
+impl Default for StringPopup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
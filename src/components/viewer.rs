@@ -1,11 +1,10 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
 
-use itertools::Itertools;
-use crossterm::event::{Event, KeyCode, MouseEventKind};
+use crossterm::event::{Event, KeyCode, KeyModifiers, MouseEventKind};
 use ratatui::{
     layout::{Margin, Rect},
     style::{Color, Style},
-    text::Span,
+    text::{Line, Span},
     widgets::{Block, Padding, Paragraph, Scrollbar, ScrollbarState},
     Frame,
 };
@@ -13,13 +12,16 @@ use ratatui::{
 use crate::{
     logger::Logger,
     utils::element_type_to_color,
-    value::writer::Element,
+    value::writer::{Element, ElementType, NodeSpan, Rendered},
     vi::{
         vimotions,
         vistate::{ViCommand, ViState},
     },
 };
 
+/// Drawn at the start of every soft-wrapped continuation row.
+const CONTINUATION_MARKER: &str = "↪ ";
+
 #[derive(Debug, Clone, Copy)]
 struct Highlight {
     pub col: i32,
@@ -29,135 +31,450 @@ struct Highlight {
 pub struct Viewer {
     logger: Logger,
     scroll: i32,
+    h_scroll: i32,
+    width: i32,
+    /// Soft-wrap the lines whose string literals run past the width, the other lines scroll.
+    wrap: bool,
     curosr: [i32; 2],
     lines: Vec<Vec<Element>>,
+    first_line: i32,
+    line_count: i32,
+    nodes: Vec<NodeSpan>,
     vistate: ViState,
     selections: BTreeMap<i32, Vec<Highlight>>,
 }
 
+fn line_len(elements: &[Element]) -> i32 {
+    elements
+        .iter()
+        .map(|e| e.content.chars().count() as i32)
+        .sum()
+}
+
+fn indent_len(elements: &[Element]) -> i32 {
+    elements
+        .iter()
+        .take_while(|e| e.ty == ElementType::Whtiespace)
+        .map(|e| e.content.chars().count() as i32)
+        .sum()
+}
+
+/// Whether a string literal of the line runs past `width` columns.
+fn overflows_in_string(elements: &[Element], width: i32) -> bool {
+    let mut end = 0;
+    elements.iter().any(|e| {
+        end += e.content.chars().count() as i32;
+        e.ty == ElementType::StringLiteral && end > width
+    })
+}
+
+/// Splits a line at a column, cutting the element under it in two if needed.
+fn split_line(elements: &[Element], col: i32) -> (Vec<Element>, Vec<Element>) {
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    let mut pos = 0;
+    for e in elements {
+        let len = e.content.chars().count() as i32;
+        if pos + len <= col {
+            before.push(e.clone());
+        } else if pos >= col {
+            after.push(e.clone());
+        } else {
+            let (split, _) = e.content.char_indices().nth((col - pos) as usize).unwrap();
+            let (left, right) = e.content.split_at(split);
+            before.push(Element {
+                ty: e.ty,
+                content: left.to_string(),
+            });
+            after.push(Element {
+                ty: e.ty,
+                content: right.to_string(),
+            });
+        }
+        pos += len;
+    }
+    (before, after)
+}
+
+/// Splits a line of `len` characters into the `[start, end)` ranges of its display rows. The first
+/// row is `width` characters wide, continuation rows lose the indentation plus the marker. Returns the
+/// indentation actually used by the continuation rows alongside the ranges.
+fn wrap_segments(len: i32, width: i32, indent: i32) -> (i32, Vec<(i32, i32)>) {
+    let width = width.max(1);
+    if len <= width {
+        return (0, vec![(0, len)]);
+    }
+
+    let marker_len = CONTINUATION_MARKER.chars().count() as i32;
+    let indent = if width - indent - marker_len < width / 2 {
+        0
+    } else {
+        indent
+    };
+    let continuation_width = (width - indent - marker_len).max(1);
+
+    let mut segments = vec![(0, width)];
+    let mut start = width;
+    while start < len {
+        let end = (start + continuation_width).min(len);
+        segments.push((start, end));
+        start = end;
+    }
+    (indent, segments)
+}
+
+/// Resolves the style of every character in the line, `highlights` must contain the cursor.
+fn styled_chars(elements: &[Element], highlights: &[Highlight]) -> Vec<(char, Style)> {
+    let mut out = Vec::new();
+    let mut col = 0;
+    for e in elements {
+        let ty_c = element_type_to_color(e.ty);
+        for c in e.content.chars() {
+            let num_highlights = highlights
+                .iter()
+                .filter(|h| col >= h.col && col < h.col + h.length)
+                .count();
+            let style = match num_highlights {
+                0 => Style::default().fg(ty_c),
+                1 => Style::default().fg(Color::Black).bg(ty_c),
+                _ => Style::default().fg(Color::White).bg(Color::Black),
+            };
+            out.push((c, style));
+            col += 1;
+        }
+    }
+
+    // the cursor can be placed on an empty line, make sure it is still visible
+    if highlights.iter().any(|h| h.col >= col) {
+        out.push((' ', Style::default().fg(Color::Black).bg(Color::Gray)));
+    }
+
+    out
+}
+
+/// Groups consecutive characters that share a style into spans.
+fn build_spans(chars: &[(char, Style)]) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut current_style = None;
+    for &(c, style) in chars {
+        if current_style.is_some_and(|s| s != style) {
+            spans.push(Span::styled(
+                std::mem::take(&mut current),
+                current_style.unwrap(),
+            ));
+        }
+        current_style = Some(style);
+        current.push(c);
+    }
+    if let Some(style) = current_style {
+        spans.push(Span::styled(current, style));
+    }
+    spans
+}
+
 impl Viewer {
     pub fn new(logger: Logger) -> Self {
         Self {
             logger,
             scroll: 0,
+            h_scroll: 0,
+            width: 1,
+            wrap: false,
             curosr: [0, 0],
             lines: Vec::new(),
+            first_line: 0,
+            line_count: 0,
+            nodes: Vec::new(),
             vistate: ViState::new(),
             selections: BTreeMap::new(),
         }
     }
 
-    pub fn set_value_elemnets(&mut self, value_elements: Vec<Vec<Element>>) {
-        self.lines = value_elements;
+    pub fn set_rendered(&mut self, rendered: Rendered) {
+        self.lines = rendered.lines;
+        self.first_line = rendered.first_line;
+        self.line_count = rendered.line_count;
+        self.nodes = rendered.nodes;
     }
 
-    fn build_lines(&self) -> Vec<Vec<Span>> {
-        todo!()
+    /// The location of the value at `path`, if it is rendered.
+    pub fn node(&self, path: &[usize]) -> Option<&NodeSpan> {
+        self.nodes.iter().find(|node| node.path == path)
     }
 
-    pub fn draw(&mut self, f: &mut Frame<'_>, chunk: Rect) {
-        let container_h = (chunk.height as i32) - 2;
+    /// The indentation of a value that can be rendered on its own and spliced back with
+    /// [`Viewer::splice`]: it has to be fully inside the window and start its line.
+    pub fn splice_indent(&self, node: &NodeSpan) -> Option<i32> {
+        let [first, end] = self.window();
+        let [row, col] = node.key.unwrap_or(node.start);
+        let line = self.line(row);
+        let starts_line = first <= row && node.end[0] < end && indent_len(line) == col;
+        starts_line.then_some(col)
+    }
 
-        self.curosr[0] = self.curosr[0].max(0).min(self.lines.len() as i32 - 1);
+    /// Replaces the value at `old` by `rendered`, a render of the same value that starts at the
+    /// same position and whose target is the value. The lines after it are moved accordingly.
+    pub fn splice(&mut self, old: &NodeSpan, rendered: Rendered) {
+        let new_end = rendered
+            .target
+            .as_ref()
+            .expect("the value is the target")
+            .end;
+        let first = (old.start[0] - self.first_line) as usize;
+        let last = (old.end[0] - self.first_line) as usize;
+        let (prefix, _) = split_line(&self.lines[first], old.start[1]);
+        let (_, suffix) = split_line(&self.lines[last], old.end[1]);
+        let rest = self.lines.split_off(last + 1);
+        self.lines.truncate(first);
+
+        let mut lines = rendered.lines;
+        lines[0].splice(0..0, prefix);
+        // the new value may not end inside of the window
+        let complete = rendered.first_line + lines.len() as i32 > new_end[0];
+        if complete {
+            lines.last_mut().unwrap().extend(suffix);
+        }
+        self.lines.extend(lines);
+        if complete {
+            self.lines.extend(rest);
+        }
 
-        let current_line_len = self.lines[self.curosr[0] as usize]
+        let delta = new_end[0] - old.end[0];
+        self.line_count += delta;
+        let shift = |pos: [i32; 2]| {
+            if pos < old.end {
+                pos
+            } else if pos[0] == old.end[0] {
+                [new_end[0], pos[1] - old.end[1] + new_end[1]]
+            } else {
+                [pos[0] + delta, pos[1]]
+            }
+        };
+        let window_end = self.window()[1];
+        self.nodes.retain_mut(|node| {
+            node.key = node.key.map(shift);
+            node.start = shift(node.start);
+            node.end = shift(node.end);
+            !node.path.starts_with(&old.path) && node.start[0] < window_end
+        });
+        self.nodes.extend(rendered.nodes);
+    }
+
+    /// Highlights the rendered values whose path is selected, from their key up to their end or
+    /// the end of their first line.
+    pub fn highlight_nodes(&mut self, selected: impl Fn(&[usize]) -> bool) {
+        self.selections.clear();
+        for node in self.nodes.iter().filter(|node| selected(&node.path)) {
+            let [row, col] = node.key.unwrap_or(node.start);
+            let end = if node.end[0] == row {
+                node.end[1]
+            } else {
+                line_len(self.line(row))
+            };
+            self.selections.entry(row).or_default().push(Highlight {
+                col,
+                length: end - col,
+            });
+        }
+    }
+
+    /// The range of lines currently held by the viewer.
+    pub fn window(&self) -> [i32; 2] {
+        [self.first_line, self.first_line + self.lines.len() as i32]
+    }
+
+    /// Returns the range of lines that should be rendered if the current window doesn't cover
+    /// everything that the next draw could show.
+    pub fn required_window(&self, chunk: Rect) -> Option<[i32; 2]> {
+        let container_h = ((chunk.height as i32) - 2).max(1);
+        // drawing scrolls until the cursor is on the screen
+        let low = self
+            .scroll
+            .max(self.curosr[0] - container_h)
+            .min(self.curosr[0])
+            .max(0);
+        let high = low + container_h + 1;
+
+        let [start, end] = self.window();
+        let covered =
+            self.line_count > 0 && start <= low && (high <= end || end >= self.line_count);
+        if covered {
+            return None;
+        }
+
+        let margin = (container_h * 2).max(50);
+        Some([(low - margin).max(0), high + margin])
+    }
+
+    /// Lines outside of the window are empty.
+    fn line(&self, idx: i32) -> &[Element] {
+        usize::try_from(idx - self.first_line)
+            .ok()
+            .and_then(|i| self.lines.get(i))
+            .map(|line| line.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn cursor(&self) -> [i32; 2] {
+        self.curosr
+    }
+
+    pub fn set_cursor(&mut self, pos: [i32; 2]) {
+        self.curosr = pos;
+    }
+
+    /// The display row of the cursor's line, counted from the first row that was drawn.
+    pub fn cursor_row(&self) -> i32 {
+        (self.scroll..self.curosr[0])
+            .map(|line| self.display_rows(line))
+            .sum()
+    }
+
+    /// The path of the innermost value under the cursor. When the cursor isn't on a value (e.g. on
+    /// the indentation) the outermost value that starts on the cursor's line is used.
+    pub fn node_at_cursor(&self) -> Option<&[usize]> {
+        let innermost = self
+            .nodes
             .iter()
-            .map(|e| e.content.len() as i32)
-            .sum::<i32>();
+            .filter(|node| node.contains(self.curosr))
+            .max_by_key(|node| node.path.len());
+        let on_line = self
+            .nodes
+            .iter()
+            .filter(|node| node.key.unwrap_or(node.start)[0] == self.curosr[0])
+            .min_by_key(|node| node.path.len());
 
-        self.curosr[1] = self.curosr[1].max(0).min(current_line_len - 1);
+        match (innermost, on_line) {
+            (Some(innermost), Some(on_line)) if on_line.path.len() > innermost.path.len() => {
+                Some(on_line)
+            }
+            (None, on_line) => on_line,
+            (innermost, _) => innermost,
+        }
+        .map(|node| node.path.as_slice())
+    }
 
-        if self.curosr[0] < self.scroll {
-            self.scroll = self.curosr[0]
-        };
+    /// Whether the line is soft-wrapped rather than scrolled.
+    fn wraps(&self, line: &[Element]) -> bool {
+        self.wrap && overflows_in_string(line, self.width)
+    }
 
-        while self.scroll + container_h <= self.curosr[0] {
-            self.scroll += 1;
+    /// Whether the cursor's line is soft-wrapped, it is shown whole and doesn't scroll then.
+    fn cursor_wraps(&self) -> bool {
+        self.wraps(self.line(self.curosr[0]))
+    }
+
+    /// The number of display rows the line will take.
+    fn display_rows(&self, line: i32) -> i32 {
+        let line = self.line(line);
+        if !self.wraps(line) {
+            return 1;
         }
+        wrap_segments(line_len(line), self.width, indent_len(line))
+            .1
+            .len() as i32
+    }
 
-        self.scroll = self.scroll.min(self.lines.len() as i32 - 1).max(0);
-
-
-        fn build_line<'a>( elements: &'a[Element], highlights: &[Highlight]) -> ratatui::text::Line<'a>{
-            let elemnets_bounds = elements.iter().scan(0, |state, e| {
-                let start = *state;
-                let end = start + e.content.len();
-                *state = end;
-                Some((start, end))
-            }).collect_vec();
-
-            let mut spans = Vec::new();
-            let mut element_i = 0;
-            // let mut highlight_i = 0;
-            let mut col = 0;
-            while element_i < elements.len() {
-                let e = &elements[element_i];
-                let s = &e.content[col - elemnets_bounds[element_i].0..];
-
-                let mut consume_len = s.len();
-                let mut num_highlights = 0;
-
-                // check elements that are applied at this colomun
-                for h in highlights.iter() {
-                    if col >= h.col as usize && col < (h.col + h.length)  as usize{
-                        num_highlights += 1;
-                        consume_len = consume_len.min((h.col + h.length) as usize - col);
-                    }
-                }
+    fn line_highlights(&self, line_idx: i32) -> Vec<Highlight> {
+        let mut highlights = self.selections.get(&line_idx).cloned().unwrap_or_default();
+        if self.curosr[0] == line_idx {
+            highlights.push(Highlight {
+                col: self.curosr[1],
+                length: 1,
+            });
+        }
+        highlights
+    }
 
-                // check elemnets that are not applied, but still limit how many chars can be
-                // consumed
-                for h in highlights.iter() {
-                    if h.col as usize > col && (h.col as usize) < col + s.len() {
-                        consume_len = consume_len.min((h.col) as usize - col);
+    fn build_display_lines(&self, line_start: i32, container_h: i32) -> Vec<Line<'static>> {
+        let mut out = Vec::new();
+        let mut line_idx = line_start;
+        while (out.len() as i32) < container_h && line_idx < self.line_count {
+            let line = self.line(line_idx);
+            let chars = styled_chars(line, &self.line_highlights(line_idx));
+
+            if self.wraps(line) {
+                let (indent, segments) =
+                    wrap_segments(chars.len() as i32, self.width, indent_len(line));
+                for (i, (start, end)) in segments.into_iter().enumerate() {
+                    let mut spans = Vec::new();
+                    if i > 0 {
+                        spans.push(Span::raw(" ".repeat(indent as usize)));
+                        spans.push(Span::styled(
+                            CONTINUATION_MARKER,
+                            Style::default().fg(Color::DarkGray),
+                        ));
                     }
+                    spans.extend(build_spans(&chars[start as usize..end as usize]));
+                    out.push(Line::from(spans));
                 }
+            } else {
+                let start = (self.h_scroll as usize).min(chars.len());
+                let end = (start + self.width.max(0) as usize).min(chars.len());
+                out.push(Line::from(build_spans(&chars[start..end])));
+            }
 
+            line_idx += 1;
+        }
+        out.truncate(container_h.max(0) as usize);
+        out
+    }
+
+    pub fn draw(&mut self, f: &mut Frame<'_>, chunk: Rect) {
+        if self.line_count == 0 {
+            return;
+        }
+
+        let container_h = (chunk.height as i32) - 2;
+        self.width = ((chunk.width as i32) - 2).max(1);
+
+        self.curosr[0] = self.curosr[0].min(self.line_count - 1).max(0);
+
+        let current_line_len = line_len(self.line(self.curosr[0]));
+        self.curosr[1] = self.curosr[1].min(current_line_len - 1).max(0);
 
-                let ty_c = element_type_to_color(e.ty);
-                let style = match num_highlights  {
-                    0 => Style::default().fg(ty_c),
-                    1 => Style::default().fg(ratatui::style::Color::Black).bg(ty_c),
-                    _ => Style::default().fg(ratatui::style::Color::White).bg(ratatui::style::Color::Black),
-                };
+        if self.curosr[0] < self.scroll {
+            self.scroll = self.curosr[0]
+        };
 
-                col += consume_len;
-                if s.len() == consume_len as usize{
-                    element_i += 1;
+        if self.wrap {
+            // the first line from which the cursor's line is still shown, walking up from it
+            let mut first = self.curosr[0];
+            let mut rows = self.display_rows(first);
+            while first > self.scroll {
+                rows += self.display_rows(first - 1);
+                if rows > container_h {
+                    break;
                 }
-                spans.push(Span::from(&s[..consume_len]).style(style));
+                first -= 1;
             }
+            self.scroll = first;
+        } else {
+            self.scroll = self.scroll.max(self.curosr[0] - container_h + 1);
+        }
 
-            ratatui::text::Line::from(spans)
+        if !self.cursor_wraps() {
+            if self.curosr[1] < self.h_scroll {
+                self.h_scroll = self.curosr[1];
+            }
+            if self.curosr[1] >= self.h_scroll + self.width {
+                self.h_scroll = self.curosr[1] - self.width + 1;
+            }
+            self.h_scroll = self.h_scroll.max(0);
         }
 
+        self.scroll = self.scroll.min(self.line_count - 1).max(0);
 
-        let line_start = self.scroll;
-        let line_end = (self.scroll + container_h).min(self.lines.len() as i32);
-        let lines = &self.lines[(line_start as usize)..(line_end as usize)];
-        let lines = lines
-            .into_iter()
-            .enumerate()
-            .map(|(i, line)| {
-                let line_idx = line_start + i as i32;
-                let mut highlights = self.selections.get(&line_idx).cloned().unwrap_or_default();
-                if self.curosr[0] == line_idx {
-                    highlights.push(Highlight{col: self.curosr[1], length: 1});
-                    highlights.sort_by_key(|h|h.col);
-                }
-                build_line( line, &highlights)
-            })
-            .collect::<Vec<_>>();
+        let lines = self.build_display_lines(self.scroll, container_h);
 
         f.render_widget(
-            Paragraph::new(lines)
-                .block(Block::default().padding(Padding::new(1, 1, 1, 1)))
-                .scroll((0, 0)),
+            Paragraph::new(lines).block(Block::default().padding(Padding::new(1, 1, 1, 1))),
             chunk,
         );
 
         let mut scrollbar_state =
-            ScrollbarState::new((self.lines.len() - (container_h as usize)).max(0))
+            ScrollbarState::new((self.line_count - container_h).max(0) as usize)
                 .position(self.scroll as _);
         f.render_stateful_widget(
             Scrollbar::new(ratatui::widgets::ScrollbarOrientation::VerticalRight),
@@ -174,7 +491,7 @@ impl Viewer {
     }
 
     fn current_line_char_vec(&self) -> Vec<char> {
-        self.lines[self.curosr[0] as usize]
+        self.line(self.curosr[0])
             .iter()
             .flat_map(|e| e.content.chars())
             .collect::<Vec<_>>()
@@ -219,7 +536,7 @@ impl Viewer {
         }
         let i = (self.curosr[1] as usize).min(line.len() - 1);
         let line = &line[i..];
-        let delta = vimotions::jump_next_char(&line, c, self.logger.clone());
+        let delta = vimotions::jump_next_char(line, c);
         self.curosr[1] += delta;
     }
 
@@ -231,7 +548,7 @@ impl Viewer {
         }
         let i = (self.curosr[1] as usize).min(line.len() - 1);
         let line = line[..=i].iter().rev().copied().collect::<Vec<_>>();
-        let delta = vimotions::jump_next_char(line.as_slice(), c, self.logger.clone());
+        let delta = vimotions::jump_next_char(line.as_slice(), c);
         self.curosr[1] -= delta;
     }
 
@@ -240,7 +557,7 @@ impl Viewer {
     }
 
     fn last_line(&mut self) {
-        self.curosr[0] = (self.lines.len() - 1) as _;
+        self.curosr[0] = self.line_count - 1;
     }
 
     fn first_column(&mut self) {
@@ -248,31 +565,51 @@ impl Viewer {
     }
 
     fn last_column(&mut self) {
-        let line_len = self.current_line_char_vec().len();
-        self.curosr[1] = (line_len - 1).max(0) as i32;
-    }
-
-    fn next(&mut self) {
-        if let Some((&row, selections)) = self.selections.range(self.curosr[0]..).next() {
-            todo!()
-            // let range_start = if self.curosr[0] == row {
-            //     self.curosr[1]
-            // } else {
-            //     0
-            // };
-            //
-            // if let Some(&col) = selections.range(range_start..).next() {
-            //     self.curosr[0] = row;
-            //     self.curosr[1] = col;
-            // }
+        let line_len = self.current_line_char_vec().len() as i32;
+        self.curosr[1] = (line_len - 1).max(0);
+    }
+
+    fn scroll_left(&mut self) {
+        if self.cursor_wraps() {
+            return;
+        }
+        self.h_scroll = (self.h_scroll - 1).max(0);
+        if self.curosr[1] >= self.h_scroll + self.width {
+            self.curosr[1] = self.h_scroll + self.width - 1;
         }
     }
 
-    fn previous(&mut self) {
-        todo!()
+    fn scroll_right(&mut self) {
+        if self.cursor_wraps() {
+            return;
+        }
+        let line_len = self.current_line_char_vec().len() as i32;
+        self.h_scroll = (self.h_scroll + 1).min(line_len - 1).max(0);
+        if self.curosr[1] < self.h_scroll {
+            self.curosr[1] = self.h_scroll;
+        }
+    }
+
+    fn scroll_cursor_start(&mut self) {
+        if !self.cursor_wraps() {
+            self.h_scroll = self.curosr[1];
+        }
     }
 
-    fn process_command(&mut self, command: ViCommand) {
+    fn scroll_cursor_end(&mut self) {
+        if !self.cursor_wraps() {
+            self.h_scroll = (self.curosr[1] - self.width + 1).max(0);
+        }
+    }
+
+    fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.logger.log(format!("soft wrap: {}", self.wrap));
+    }
+
+    /// Executes the command, commands that concern the document rather than the view are returned
+    /// to the caller.
+    fn process_command(&mut self, command: ViCommand) -> Option<ViCommand> {
         type C = ViCommand;
         match command {
             // simple navigation
@@ -290,30 +627,225 @@ impl Viewer {
             C::LastLine => self.last_line(),
             C::FirstColumn => self.first_column(),
             C::LastColumn => self.last_column(),
+            // horizontal scrolling
+            C::ScrollLeft => self.scroll_left(),
+            C::ScrollRight => self.scroll_right(),
+            C::ScrollCursorStart => self.scroll_cursor_start(),
+            C::ScrollCursorEnd => self.scroll_cursor_end(),
+            C::ToggleWrap => self.toggle_wrap(),
+            // value display
+            C::ToggleStringDecoding | C::ShowString | C::ToggleFold | C::ToggleProjection => {
+                return Some(command)
+            }
             // search results
-            C::Next => self.next(),
-            C::Previous => self.previous(),
+            C::Next | C::Previous => return Some(command),
+            // diagnostics
+            C::NextError | C::PreviousError | C::ShowDiagnostics => return Some(command),
+            // editing
+            C::EditValue | C::ChangeValue | C::Delete | C::InsertSibling | C::CommandLine => {
+                return Some(command)
+            }
+            C::Undo | C::Redo => return Some(command),
         }
+        None
     }
 
-    pub fn handle_input(&mut self, event: Option<Event>) {
+    pub fn handle_input(&mut self, event: Option<Event>) -> Option<ViCommand> {
         match event {
-            Some(Event::Key(ke)) => match ke.code {
-                KeyCode::Char(c) => {
+            Some(Event::Key(ke)) if ke.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.vistate.reset();
+                match ke.code {
+                    KeyCode::Char('r') => self.process_command(ViCommand::Redo),
+                    _ => None,
+                }
+            }
+            Some(Event::Key(ke)) => {
+                if let KeyCode::Char(c) = ke.code {
                     if let Some(command) = self.vistate.process(c) {
-                        self.process_command(command);
+                        return self.process_command(command);
                     }
                 }
-                _ => {}
-            },
+                None
+            }
             Some(Event::Mouse(me)) => match me.kind {
                 MouseEventKind::ScrollUp => self.process_command(ViCommand::Up),
                 MouseEventKind::ScrollDown => self.process_command(ViCommand::Down),
-                MouseEventKind::ScrollLeft => self.process_command(ViCommand::Left),
-                MouseEventKind::ScrollRight => self.process_command(ViCommand::Right),
-                _ => {}
+                MouseEventKind::ScrollLeft => self.process_command(ViCommand::ScrollLeft),
+                MouseEventKind::ScrollRight => self.process_command(ViCommand::ScrollRight),
+                _ => None,
             },
-            _ => {}
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::*;
+    use crate::value::{parse, writer::Writer, Value};
+
+    fn render(value: &Value, window: [i32; 2]) -> Rendered {
+        let mut writer = Writer::new();
+        writer.with_window(window);
+        writer.write_value(value);
+        writer.finish()
+    }
+
+    fn text(lines: &[Vec<Element>]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| line.iter().map(|e| e.content.as_str()).collect())
+            .collect()
+    }
+
+    fn sorted(nodes: &[NodeSpan]) -> Vec<NodeSpan> {
+        let mut nodes = nodes.to_vec();
+        nodes.sort_by(|a, b| a.path.cmp(&b.path));
+        nodes
+    }
+
+    /// Toggles the container at `path` and splices it into a viewer showing `window`.
+    fn toggle_and_splice(value: &mut Value, path: &[usize], window: [i32; 2]) -> Viewer {
+        let mut viewer = Viewer::new(Logger::new());
+        viewer.set_rendered(render(value, window));
+        let old = viewer.node(path).unwrap().clone();
+        match value.get_path_mut(path).unwrap() {
+            Value::Array(arr) => arr.toggle_collapse(),
+            Value::Object(object) => object.toggle_collapse(),
+            _ => unreachable!(),
         }
+
+        let indent = viewer.splice_indent(&old).unwrap();
+        let mut writer = Writer::new();
+        writer.with_window(viewer.window());
+        writer.with_start(&old, writer.indent_levels(indent));
+        writer.with_target(path.to_vec());
+        writer.write_value(value.get_path(path).unwrap());
+        viewer.splice(&old, writer.finish());
+        viewer
+    }
+
+    #[test]
+    fn splice() {
+        let source =
+            r#"{"a": [1, 2, {"b": [3, 4, 5, 6, 7]}], "c": {"d": 1, "e": 2}, "f": [[1], [2]]}"#;
+        let mut value = parse(source).unwrap();
+        for path in [vec![0], vec![0, 2, 0], vec![1], vec![2, 1], vec![]] {
+            // collapse, then expand again
+            for _ in 0..2 {
+                let viewer = toggle_and_splice(&mut value, &path, [0, 100]);
+                // expanding can push the end of the document out of the window
+                let expected = render(&value, viewer.window());
+                assert_eq!(text(&viewer.lines), text(&expected.lines), "{path:?}");
+                assert_eq!(viewer.line_count, expected.line_count);
+                assert_eq!(sorted(&viewer.nodes), sorted(&expected.nodes));
+            }
+        }
+
+        // the expanded value doesn't fit in the window
+        match value.get_path_mut(&[0]).unwrap() {
+            Value::Array(arr) => arr.collapse(),
+            _ => unreachable!(),
+        }
+        let viewer = toggle_and_splice(&mut value, &[0], [0, 4]);
+        let expected = render(&value, [0, 100]);
+        assert_eq!(text(&viewer.lines), text(&expected.lines[..4]));
+        assert_eq!(viewer.line_count, expected.line_count);
+    }
+
+    #[test]
+    fn jump_window() {
+        let value = parse(&format!("[{}]", vec!["[1, 2]"; 1000].join(","))).unwrap();
+        let mut viewer = Viewer::new(Logger::new());
+        viewer.set_rendered(render(&value, [0, 100]));
+        let chunk = Rect::new(0, 0, 80, 22);
+        assert_eq!(viewer.required_window(chunk), None);
+
+        // only the lines around the cursor are needed, not the ones it jumped over
+        viewer.last_line();
+        let [start, end] = viewer.required_window(chunk).unwrap();
+        assert!(start > 900 && end > 1001 && end - start < 200);
+        viewer.set_rendered(render(&value, [start, end]));
+        viewer.first_line();
+        assert_eq!(viewer.required_window(chunk), Some([0, 71]));
+    }
+
+    #[test]
+    fn wrap_rows() {
+        let source = r#"{"a": "a string that runs past the width", "b": [12345678901234, 12345678901234, 1, 2], "c": 1}"#;
+        let value = parse(source).unwrap();
+        let mut viewer = Viewer::new(Logger::new());
+        viewer.set_rendered(render(&value, [0, 100]));
+        viewer.width = 20;
+        viewer.set_cursor([3, 4]);
+        assert_eq!(viewer.cursor_row(), 3);
+
+        // only the line with the string is wrapped, the wide array scrolls
+        viewer.toggle_wrap();
+        let string_rows = wrap_segments(line_len(viewer.line(1)), 20, 4).1.len() as i32;
+        assert!(string_rows > 1);
+        assert_eq!(viewer.display_rows(1), string_rows);
+        assert_eq!(viewer.display_rows(2), 1);
+        assert_eq!(viewer.cursor_row(), 1 + string_rows + 1);
+        assert_eq!(
+            viewer.build_display_lines(0, 100).len() as i32,
+            4 + string_rows
+        );
+
+        // the rows are counted from the first line drawn
+        viewer.scroll = 1;
+        assert_eq!(viewer.cursor_row(), string_rows + 1);
+        viewer.scroll = 2;
+        assert_eq!(viewer.cursor_row(), 1);
+    }
+
+    #[test]
+    fn wrap_scroll() {
+        let source = r#"{"a": "a string that runs past the width", "b": [12345678901234, 12345678901234, 1, 2]}"#;
+        let value = parse(source).unwrap();
+        let mut viewer = Viewer::new(Logger::new());
+        viewer.set_rendered(render(&value, [0, 100]));
+        viewer.toggle_wrap();
+        let mut terminal = Terminal::new(TestBackend::new(22, 12)).unwrap();
+
+        // the cursor's line scrolls when it isn't wrapped
+        viewer.set_cursor([2, 40]);
+        terminal.draw(|f| viewer.draw(f, f.size())).unwrap();
+        assert_eq!(viewer.h_scroll, 40 - 20 + 1);
+        viewer.scroll_left();
+        assert_eq!(viewer.h_scroll, 40 - 20);
+
+        // a wrapped line is shown whole
+        viewer.set_cursor([1, 30]);
+        terminal.draw(|f| viewer.draw(f, f.size())).unwrap();
+        viewer.scroll_right();
+        assert_eq!(viewer.h_scroll, 40 - 20);
+        let lines = viewer.build_display_lines(1, 100);
+        let first = |line: &Line| {
+            line.spans
+                .iter()
+                .map(|s| s.content.as_ref())
+                .collect::<String>()
+        };
+        assert_eq!(first(&lines[0]), r#"    "a": "a string t"#);
+        assert!(first(&lines[1])
+            .trim_start()
+            .starts_with(CONTINUATION_MARKER));
+    }
+
+    #[test]
+    fn wrap() {
+        assert_eq!(wrap_segments(5, 10, 0), (0, vec![(0, 5)]));
+        assert_eq!(wrap_segments(10, 10, 4), (0, vec![(0, 10)]));
+        assert_eq!(
+            wrap_segments(20, 10, 0),
+            (0, vec![(0, 10), (10, 18), (18, 20)])
+        );
+        assert_eq!(wrap_segments(20, 20, 4), (0, vec![(0, 20)]));
+        assert_eq!(wrap_segments(30, 20, 4), (4, vec![(0, 20), (20, 30)]));
+        // the indentation is dropped when it leaves too little room
+        assert_eq!(wrap_segments(14, 10, 8), (0, vec![(0, 10), (10, 14)]));
     }
 }
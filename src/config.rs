@@ -0,0 +1,196 @@
+//! The config file, `$XDG_CONFIG_HOME/tispect/config.toml`:
+//!
+//! ```toml
+//! [layout]
+//! indent = 2          # or "tab"
+//! max_width = 80
+//! sort_keys = true
+//! space_after_colon = false
+//! compact = false
+//!
+//! [query]
+//! vi_mode = true      # Esc switches the query input to a vi normal mode
+//!
+//! [queries]           # saved with `:save NAME`
+//! names = { query = ".users[*].name", language = "path" }
+//! ```
+
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+
+use serde::Deserialize;
+use toml_edit::{DocumentMut, InlineTable};
+
+use crate::{query::Language, value::writer::LayoutConfig};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Could not read the config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Could not parse the config file: {0}")]
+    Edit(#[from] toml_edit::TomlError),
+    #[error("`queries` in the config file isn't a table")]
+    QueriesNotATable,
+    #[error("Could not find the config directory, HOME isn't set")]
+    NoConfigHome,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub layout: LayoutConfig,
+    pub query: QueryConfig,
+    /// The queries saved with `:save`, by their names.
+    pub queries: BTreeMap<String, SavedQuery>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    /// `Esc` in the query input switches to a normal mode with vi motions, instead of going
+    /// back to the Explorer.
+    pub vi_mode: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedQuery {
+    pub query: String,
+    #[serde(default)]
+    pub language: Language,
+}
+
+/// The directory in the environment variable `var`, or `default` in the home directory when it
+/// isn't set.
+fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(default)))
+}
+
+/// `$XDG_CONFIG_HOME`, or `~/.config` when it isn't set.
+fn config_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME`, or `~/.local/state` when it isn't set.
+pub fn state_home() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// `text` with the query added to its `[queries]` table, or replacing the one with the same name.
+/// Everything else, including the comments, is kept as it is.
+fn insert_query(text: &str, name: &str, query: &SavedQuery) -> Result<String, ConfigError> {
+    let mut document = text.parse::<DocumentMut>()?;
+    let queries = document
+        .entry("queries")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or(ConfigError::QueriesNotATable)?;
+    let mut entry = InlineTable::new();
+    entry.insert("query", query.query.as_str().into());
+    entry.insert("language", query.language.name().into());
+    queries.insert(name, toml_edit::value(entry));
+    Ok(document.to_string())
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        config_home().map(|dir| dir.join("tispect").join("config.toml"))
+    }
+
+    pub fn parse(s: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Loads the config file, or the default config if there isn't one.
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::parse(&std::fs::read_to_string(path)?),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the query under `name` in the config file, which is created if needed.
+    pub fn save_query(name: &str, query: &SavedQuery) -> Result<(), ConfigError> {
+        let path = Self::path().ok_or(ConfigError::NoConfigHome)?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let text = insert_query(&text, name, query)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::writer::Indent;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+
+        let config =
+            Config::parse("[layout]\nindent = \"tab\"\nmax_width = 80\nsort_keys = true").unwrap();
+        assert_eq!(config.layout.indent, Indent::TAB);
+        assert_eq!(config.layout.max_width, Some(80));
+        assert!(config.layout.sort_keys);
+        assert!(config.layout.space_after_colon);
+
+        let config = Config::parse("[layout]\nindent = 2").unwrap();
+        assert_eq!(config.layout.indent, Indent::Spaces(2));
+
+        assert!(
+            Config::parse("[query]\nvi_mode = true")
+                .unwrap()
+                .query
+                .vi_mode
+        );
+
+        assert!(Config::parse("[layout]\nindent = \"tabs\"").is_err());
+        assert!(Config::parse("[layout]\nwidth = 2").is_err());
+
+        let config = Config::parse(
+            "[queries]\nnames = { query = \".users[*].name\" }\nadults = { query = \"$.users[?@.age >= 18]\", language = \"jsonpath\" }",
+        )
+        .unwrap();
+        assert_eq!(config.queries["names"].language, Language::Path);
+        assert_eq!(config.queries["adults"].language, Language::JsonPath);
+        assert!(Config::parse("[queries]\nnames = { query = \".\", language = \"sql\" }").is_err());
+    }
+
+    #[test]
+    fn save_query() {
+        let query = SavedQuery {
+            query: ".users | map(.name)".to_string(),
+            language: Language::Jq,
+        };
+        let text = insert_query("", "names", &query).unwrap();
+        assert_eq!(Config::parse(&text).unwrap().queries["names"], query);
+
+        let text =
+            "# my layout\n[layout]\nindent = 2 # spaces\n\n[queries]\nold = { query = \".a\" }\n";
+        let text = insert_query(text, "names", &query).unwrap();
+        assert!(text.starts_with("# my layout\n[layout]\nindent = 2 # spaces\n"));
+        let config = Config::parse(&text).unwrap();
+        assert_eq!(config.layout.indent, Indent::Spaces(2));
+        assert_eq!(config.queries.len(), 2);
+
+        let other = SavedQuery {
+            query: ".b".to_string(),
+            language: Language::Path,
+        };
+        let text = insert_query(&text, "old", &other).unwrap();
+        assert_eq!(Config::parse(&text).unwrap().queries["old"], other);
+        assert!(insert_query("queries = 1", "names", &query).is_err());
+    }
+}
@@ -0,0 +1,11 @@
+pub mod app;
+pub mod components;
+pub mod config;
+pub mod loader;
+pub mod logger;
+pub mod output;
+pub mod query;
+pub mod simple_matcher;
+pub mod utils;
+pub mod value;
+pub mod vi;
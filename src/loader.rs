@@ -0,0 +1,66 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use crate::value::{document::DocumentError, Document, ParseOptions};
+
+/// Files larger than this are opened lazily even without `--lazy`.
+pub const LAZY_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// Loads a document on a background thread so that the UI can show the progress.
+pub struct Loader {
+    progress: Arc<AtomicUsize>,
+    total: usize,
+    lazy: bool,
+    handle: JoinHandle<Result<Document, DocumentError>>,
+}
+
+impl Loader {
+    /// Lenient and recovering loads are never lazy, the structural index only understands valid
+    /// strict JSON.
+    pub fn spawn(file: PathBuf, lazy: bool, options: ParseOptions) -> Self {
+        let total = std::fs::metadata(&file).map(|m| m.len()).unwrap_or(0);
+        let lazy = options == ParseOptions::default() && (lazy || total > LAZY_THRESHOLD);
+        let progress = Arc::new(AtomicUsize::new(0));
+
+        let handle = {
+            let progress = progress.clone();
+            std::thread::spawn(move || {
+                if lazy {
+                    Document::load_lazy(file, &progress)
+                } else {
+                    Document::load_with(file, options)
+                }
+            })
+        };
+
+        Self {
+            progress,
+            total: total as usize,
+            lazy,
+            handle,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// The fraction of the file that was indexed, only known when loading lazily.
+    pub fn progress(&self) -> Option<f64> {
+        if !self.lazy {
+            return None;
+        }
+        let progress = self.progress.load(Ordering::Relaxed);
+        Some((progress as f64 / self.total.max(1) as f64).min(1.0))
+    }
+
+    pub fn join(self) -> Result<Document, DocumentError> {
+        self.handle.join().expect("the loader thread panicked")
+    }
+}
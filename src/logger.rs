@@ -16,3 +16,9 @@ impl Logger {
         self.0.borrow().clone()
     }
 }
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,41 +1,264 @@
-use std::{io::stdout, path::PathBuf};
+use std::{
+    io::{stdout, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
 
-use app::App;
 use clap::Parser;
 use crossterm::{
-    event::{self, EnableMouseCapture},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyModifiers,
+    },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
 };
 use ratatui::prelude::{CrosstermBackend, Terminal};
-
-mod app;
-mod components;
-mod logger;
-mod simple_matcher;
-mod utils;
-mod value;
-mod vi;
+use tispect::{
+    app::App,
+    components::loading::Loading,
+    config::Config,
+    loader::Loader,
+    output::{OutputError, OutputFormat},
+    query::{self, Language, Output, RunError},
+    value::{
+        document::DocumentError,
+        writer::{Indent, LayoutConfig},
+        Document, ParseOptions,
+    },
+};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     /// The file to load.
     file: PathBuf,
+
+    /// Memory map the file and only parse containers once they are expanded. Enabled
+    /// automatically for very large files.
+    #[arg(long)]
+    lazy: bool,
+
+    /// Accept JSONC and JSON5: comments, trailing commas, single quoted strings, unquoted keys,
+    /// hexadecimal numbers, NaN and Infinity. Enabled automatically for `.jsonc` and `.json5`
+    /// files.
+    #[arg(long, conflicts_with = "lazy")]
+    lenient: bool,
+
+    /// Keep going after parse errors and show what could be parsed, with error markers in place
+    /// of the broken parts. Jump between them with `]e` and `[e`.
+    #[arg(long, conflicts_with = "lazy")]
+    recover: bool,
+
+    /// Indent with this many spaces.
+    #[arg(long, value_name = "WIDTH", help_heading = "Layout")]
+    indent: Option<usize>,
+
+    /// Indent with tabs.
+    #[arg(long, conflicts_with = "indent", help_heading = "Layout")]
+    tabs: bool,
+
+    /// Write containers on a single line when they fit in this many columns.
+    #[arg(long, value_name = "COLUMNS", help_heading = "Layout")]
+    max_width: Option<usize>,
+
+    /// Order the entries of objects by their keys.
+    #[arg(long, help_heading = "Layout")]
+    sort_keys: bool,
+
+    /// Don't put a space after the colon of keys.
+    #[arg(long, help_heading = "Layout")]
+    no_space_after_colon: bool,
+
+    /// Write everything on a single line.
+    #[arg(long, help_heading = "Layout")]
+    compact: bool,
+
+    /// Print the results of this query instead of opening the viewer, e.g. `.users[*].name`,
+    /// `.users | map(.name)` with `--language jq` or `$.users[*].name` for JSONPath.
+    #[arg(long, short, value_name = "QUERY", help_heading = "Output")]
+    query: Option<String>,
+
+    /// Print the results of a query saved with `:save NAME`, in its language.
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "query",
+        help_heading = "Output"
+    )]
+    saved: Option<String>,
+
+    /// The language of the query, also the one the Query pane starts in.
+    #[arg(
+        long,
+        short,
+        value_enum,
+        default_value_t,
+        value_name = "LANGUAGE",
+        help_heading = "Output"
+    )]
+    language: Language,
+
+    /// The format to print the values in, JSON by default. Prints the whole document when there
+    /// is no query.
+    #[arg(
+        long,
+        short,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "Output"
+    )]
+    output: Option<OutputFormat>,
+}
+
+impl Cli {
+    /// Overrides the layout of the config file with the flags that were given.
+    fn apply_layout(&self, layout: &mut LayoutConfig) {
+        if let Some(width) = self.indent {
+            layout.indent = Indent::Spaces(width);
+        }
+        if self.tabs {
+            layout.indent = Indent::TAB;
+        }
+        if self.max_width.is_some() {
+            layout.max_width = self.max_width;
+        }
+        layout.sort_keys |= self.sort_keys;
+        layout.space_after_colon &= !self.no_space_after_colon;
+        layout.compact |= self.compact;
+    }
+}
+
+fn exit_with_document_error(file: &Path, err: DocumentError) -> ! {
+    eprintln!("{}: {err}", file.display());
+    if matches!(err, DocumentError::Parse(_)) {
+        eprintln!("use --recover to inspect the parts that could be parsed");
+    }
+    std::process::exit(1);
+}
+
+/// Prints the values selected by the query, without the TUI.
+fn print_query(
+    cli: &Cli,
+    language: Language,
+    query: &str,
+    options: ParseOptions,
+    layout: &LayoutConfig,
+) {
+    let document = Document::load_with(&cli.file, options)
+        .unwrap_or_else(|err| exit_with_document_error(&cli.file, err));
+
+    let outputs = match query::run(language, query, document.get()) {
+        Ok(outputs) => outputs,
+        Err(RunError::Query(err)) => {
+            eprintln!("invalid query: {err}");
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(5);
+        }
+    };
+    let values = outputs.iter().map(Output::value).collect::<Vec<_>>();
+    let serializer = cli.output.unwrap_or(OutputFormat::Json).serializer(layout);
+    let mut out = BufWriter::new(stdout().lock());
+    match serializer
+        .write(&mut out, &values)
+        .and_then(|_| out.flush().map_err(OutputError::from))
+    {
+        Ok(()) => {}
+        // the reader went away, e.g. `| head`
+        Err(OutputError::Io(err)) if err.kind() == ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Undoes the setup of the terminal, on stderr like the UI is drawn.
+fn restore_terminal() -> std::io::Result<()> {
+    crossterm::execute!(
+        std::io::stderr(),
+        DisableBracketedPaste,
+        DisableMouseCapture,
+        LeaveAlternateScreen
+    )?;
+    disable_raw_mode()
 }
 
 fn main() -> std::io::Result<()> {
     let cli = Cli::parse();
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            let path = Config::path().unwrap_or_default();
+            eprintln!("{}: {err}", path.display());
+            std::process::exit(1);
+        }
+    };
+    cli.apply_layout(&mut config.layout);
+
+    let lenient = cli.lenient
+        || cli
+            .file
+            .extension()
+            .is_some_and(|ext| ext == "jsonc" || ext == "json5");
+    let options = ParseOptions {
+        lenient,
+        recover: cli.recover,
+    };
+    if let Some(name) = &cli.saved {
+        let Some(saved) = config.queries.get(name) else {
+            eprintln!("no saved query named {name}");
+            std::process::exit(1);
+        };
+        print_query(&cli, saved.language, &saved.query, options, &config.layout);
+        return Ok(());
+    }
+    if cli.query.is_some() || cli.output.is_some() {
+        let query = cli.query.as_deref().unwrap_or(".");
+        print_query(&cli, cli.language, query, options, &config.layout);
+        return Ok(());
+    }
 
     // setup the terminal
     enable_raw_mode()?;
     let mut stderr = std::io::stderr();
-    crossterm::execute!(stderr, EnterAlternateScreen, EnableMouseCapture)?;
+    crossterm::execute!(
+        stderr,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
     let backend = CrosstermBackend::new(stderr);
     let mut terminal = Terminal::new(backend)?;
 
+    // load the document
+    let loader = Loader::spawn(cli.file.clone(), cli.lazy, options);
+    let loading = Loading::new(format!("Loading {}", cli.file.display()));
+    while !loader.is_finished() {
+        terminal.draw(|frame| loading.draw(frame, loader.progress()))?;
+
+        if event::poll(std::time::Duration::from_millis(16))? {
+            if let Event::Key(ke) = event::read()? {
+                let ctrl_c =
+                    ke.code == KeyCode::Char('c') && ke.modifiers.contains(KeyModifiers::CONTROL);
+                if ke.code == KeyCode::Char('q') || ctrl_c {
+                    return restore_terminal();
+                }
+            }
+        }
+    }
+    let document = match loader.join() {
+        Ok(document) => document,
+        Err(err) => {
+            restore_terminal()?;
+            exit_with_document_error(&cli.file, err);
+        }
+    };
+
     // create the app
-    let mut app = App::new(cli.file);
+    let mut app = App::new(document, config);
+    app.set_query_language(cli.language);
 
     // main loop
     while !app.done() {
@@ -51,8 +274,5 @@ fn main() -> std::io::Result<()> {
     }
 
     // restore terminal
-    stdout().execute(LeaveAlternateScreen)?;
-    disable_raw_mode()?;
-
-    Ok(())
+    restore_terminal()
 }
@@ -0,0 +1,125 @@
+use std::{borrow::Cow, collections::HashMap, io::Write};
+
+use crate::value::{unescape, Value};
+
+use super::{OutputError, Serializer};
+
+/// CSV with a header line. Every result is a row, or every item when there is a single array.
+/// Containers are flattened: `{"a": {"b": 1}, "tags": ["x"]}` has the columns `a.b` and `tags.0`,
+/// the columns of all the rows are merged in the order they appear in.
+pub struct Csv;
+
+/// The columns of a row and their cells. A row that isn't a container has a single column named
+/// `value`. Nested values are walked with a stack, the column of a value is its parent's followed by
+/// its key or index.
+fn flatten(value: &Value, row: &mut Vec<(String, String)>) {
+    let mut column = String::new();
+    // (length of the column of the parent, key or index, value), the root has no key
+    let mut stack: Vec<(usize, Option<Cow<str>>, &Value)> = vec![(0, None, value)];
+    while let Some((parent, name, value)) = stack.pop() {
+        column.truncate(parent);
+        if let Some(name) = name {
+            if !column.is_empty() {
+                column.push('.');
+            }
+            column.push_str(&name);
+        }
+        let parent = column.len();
+        match value {
+            Value::Array(arr) if !arr.arr.is_empty() => {
+                let items = arr.arr.iter().enumerate().rev();
+                stack
+                    .extend(items.map(|(i, item)| (parent, Some(Cow::Owned(i.to_string())), item)));
+            }
+            Value::Object(object) if !object.is_empty() => {
+                let entries = object.entries().iter().rev();
+                stack.extend(entries.map(|(key, value)| (parent, Some(unescape(key)), value)));
+            }
+            _ => {
+                let cell = match value {
+                    Value::Null | Value::Error(_) | Value::Lazy(_) => String::new(),
+                    Value::Bool(x) => x.to_string(),
+                    Value::Number(x) => x.to_json().unwrap_or(x.lexeme().into()).into_owned(),
+                    Value::String(x) => unescape(x).into_owned(),
+                    Value::Array(_) => "[]".to_string(),
+                    Value::Object(_) => "{}".to_string(),
+                };
+                let name = if column.is_empty() { "value" } else { &column };
+                row.push((name.to_string(), cell));
+            }
+        }
+    }
+}
+
+/// Quotes a field when it contains a separator, a quote or a line break.
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl Serializer for Csv {
+    fn write(&self, out: &mut dyn Write, values: &[&Value]) -> Result<(), OutputError> {
+        let rows = match values {
+            [Value::Array(arr)] => arr.arr.iter().collect(),
+            _ => values.to_vec(),
+        };
+
+        let mut columns = Vec::new();
+        let mut positions = HashMap::new();
+        let mut cells = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut flat = Vec::new();
+            flatten(row, &mut flat);
+            let mut row = Vec::new();
+            for (column, cell) in flat {
+                let position = *positions.entry(column.clone()).or_insert_with(|| {
+                    columns.push(column);
+                    columns.len() - 1
+                });
+                if row.len() <= position {
+                    row.resize(position + 1, String::new());
+                }
+                // the last entry wins when a key is repeated
+                row[position] = cell;
+            }
+            cells.push(row);
+        }
+
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let header = columns.iter().map(|c| field(c)).collect::<Vec<_>>();
+        writeln!(out, "{}", header.join(","))?;
+        for mut row in cells {
+            row.resize(columns.len(), String::new());
+            let row = row.iter().map(|c| field(c)).collect::<Vec<_>>();
+            writeln!(out, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::write;
+
+    #[test]
+    fn csv() {
+        let source = r#"[[
+            {"name": "a, b", "age": 30, "tags": ["x"]},
+            {"name": "say \"hi\"", "address": {"city": "c"}, "age": null}
+        ]]"#;
+        assert_eq!(
+            write(&Csv, source).unwrap(),
+            "name,age,tags.0,address.city\n\
+             \"a, b\",30,x,\n\
+             \"say \"\"hi\"\"\",,,c\n"
+        );
+        assert_eq!(write(&Csv, "[1, 'x']").unwrap(), "value\n1\nx\n");
+        assert_eq!(write(&Csv, "[]").unwrap(), "");
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::Write;
+
+use crate::value::{
+    unescape,
+    writer::{LayoutConfig, Writer},
+    Value,
+};
+
+use super::{OutputError, Serializer};
+
+/// Writes `value` as standard JSON, even if it was parsed leniently.
+pub(super) fn to_json(value: &Value, layout: &LayoutConfig) -> String {
+    let mut writer = Writer::new();
+    writer.with_layout(layout);
+    writer.with_valid_json(true);
+    writer.write_value(value);
+    writer.to_string()
+}
+
+/// One JSON value per result.
+pub struct Json {
+    layout: LayoutConfig,
+}
+
+impl Json {
+    pub fn new(layout: LayoutConfig) -> Self {
+        Self { layout }
+    }
+}
+
+impl Serializer for Json {
+    fn write(&self, out: &mut dyn Write, values: &[&Value]) -> Result<(), OutputError> {
+        for value in values {
+            writeln!(out, "{}", to_json(value, &self.layout))?;
+        }
+        Ok(())
+    }
+}
+
+/// Like [`Json`], but strings are written decoded and without quotes, like `jq -r`.
+pub struct Raw {
+    layout: LayoutConfig,
+}
+
+impl Raw {
+    pub fn new(layout: LayoutConfig) -> Self {
+        Self { layout }
+    }
+}
+
+impl Serializer for Raw {
+    fn write(&self, out: &mut dyn Write, values: &[&Value]) -> Result<(), OutputError> {
+        for value in values {
+            match value {
+                Value::String(x) => writeln!(out, "{}", unescape(x))?,
+                _ => writeln!(out, "{}", to_json(value, &self.layout))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::write;
+
+    #[test]
+    fn valid_json() {
+        let compact = LayoutConfig {
+            compact: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            write(
+                &Json::new(compact.clone()),
+                "[{a: 0x10, /* c */ 'b': [NaN, +1]}, 'x\"']"
+            )
+            .unwrap(),
+            "{\"a\":16,\"b\":[null,1]}\n\"x\\\"\"\n"
+        );
+        assert_eq!(
+            write(&Json::new(compact), "[[1, 2, }]").unwrap(),
+            "[1,2,null]\n"
+        );
+    }
+
+    #[test]
+    fn raw() {
+        assert_eq!(
+            write(
+                &Raw::new(LayoutConfig::default()),
+                r#"["a\tb", 1, null, ["c"]]"#
+            )
+            .unwrap(),
+            "a\tb\n1\nnull\n[\"c\"]\n"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Serializers for the results of queries executed without the TUI, see `--output`.
+
+mod csv;
+mod json;
+mod toml;
+mod yaml;
+
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use crate::value::{writer::LayoutConfig, Value};
+
+pub use self::{
+    csv::Csv,
+    json::{Json, Raw},
+    toml::Toml,
+    yaml::Yaml,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// JSON, laid out like in the viewer.
+    Json,
+    /// JSON, every result on a single line.
+    JsonCompact,
+    /// YAML, every result is a document.
+    Yaml,
+    /// TOML, the result must be a single object.
+    Toml,
+    /// CSV with a row per result, or per item when the result is a single array. Nested values
+    /// are flattened into columns like `a.b` and `tags.0`.
+    Csv,
+    /// Strings without quotes, everything else as JSON.
+    Raw,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OutputError {
+    #[error("Could not write the output: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{format} can't represent {what}")]
+    Unsupported { format: &'static str, what: String },
+    #[error("Could not write TOML: {0}")]
+    Toml(#[from] ::toml::ser::Error),
+}
+
+impl OutputError {
+    fn unsupported(format: &'static str, what: impl ToString) -> Self {
+        Self::Unsupported {
+            format,
+            what: what.to_string(),
+        }
+    }
+}
+
+/// Writes a list of values in some format. Containers must be fully parsed.
+pub trait Serializer {
+    fn write(&self, out: &mut dyn Write, values: &[&Value]) -> Result<(), OutputError>;
+}
+
+impl OutputFormat {
+    /// The serializer for this format, JSON is written with `layout`.
+    pub fn serializer(self, layout: &LayoutConfig) -> Box<dyn Serializer> {
+        match self {
+            Self::Json => Box::new(Json::new(layout.clone())),
+            Self::JsonCompact => Box::new(Json::new(LayoutConfig {
+                compact: true,
+                ..layout.clone()
+            })),
+            Self::Yaml => Box::new(Yaml),
+            Self::Toml => Box::new(Toml),
+            Self::Csv => Box::new(Csv),
+            Self::Raw => Box::new(Raw::new(layout.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{parse_with, ParseOptions};
+
+    /// Writes the items of the array in `source` as the results, parsed leniently and recovering
+    /// from errors.
+    pub(super) fn write(serializer: &dyn Serializer, source: &str) -> Result<String, OutputError> {
+        let options = ParseOptions {
+            lenient: true,
+            recover: true,
+        };
+        let value = parse_with(source, options).unwrap().value;
+        let Value::Array(arr) = &value else {
+            panic!("the results must be in an array");
+        };
+        let mut out = Vec::new();
+        serializer.write(&mut out, &arr.arr.iter().collect::<Vec<_>>())?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 100_000;
+        let source = format!("[{}1{}]", "[".repeat(depth), "]".repeat(depth));
+        let layout = LayoutConfig::default();
+        let written = |format: OutputFormat| write(&*format.serializer(&layout), &source).unwrap();
+        assert_eq!(
+            written(OutputFormat::JsonCompact),
+            format!("{}1{}\n", "[".repeat(depth), "]".repeat(depth))
+        );
+        assert_eq!(
+            written(OutputFormat::Yaml),
+            format!("{}1\n", "- ".repeat(depth))
+        );
+        let column = vec!["0"; depth - 1].join(".");
+        assert_eq!(written(OutputFormat::Csv), format!("{column}\n1\n"));
+    }
+}
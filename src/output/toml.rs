@@ -0,0 +1,111 @@
+use std::io::Write;
+
+use crate::value::{number::NumberValue, unescape, Value};
+
+use super::{OutputError, Serializer};
+
+/// A TOML document, only a single object can be written.
+pub struct Toml;
+
+fn to_toml(value: &Value) -> Result<::toml::Value, OutputError> {
+    let unsupported = |what: &str| Err(OutputError::unsupported("TOML", what));
+    Ok(match value {
+        Value::Null => return unsupported("null"),
+        Value::Bool(x) => ::toml::Value::Boolean(*x),
+        Value::Number(x) => match (x.as_i64(), x.value()) {
+            (Some(x), _) => ::toml::Value::Integer(x),
+            // the integers that don't fit in 64 bits would lose digits as floats
+            (None, NumberValue::Integer(_)) => return unsupported("integers outside of 64 bits"),
+            (None, NumberValue::BigDecimal(_)) if !x.lexeme().contains(['.', 'e', 'E']) => {
+                return unsupported("integers outside of 64 bits")
+            }
+            (None, _) => ::toml::Value::Float(x.as_f64()),
+        },
+        Value::String(x) => ::toml::Value::String(unescape(x).into_owned()),
+        Value::Array(arr) => {
+            ::toml::Value::Array(arr.arr.iter().map(to_toml).collect::<Result<_, _>>()?)
+        }
+        Value::Object(object) => ::toml::Value::Table(to_table(object.entries())?),
+        Value::Lazy(_) => return unsupported("containers that weren't parsed"),
+        Value::Error(_) => return unsupported("parse errors"),
+    })
+}
+
+fn to_table(entries: &[(String, Value)]) -> Result<::toml::Table, OutputError> {
+    let mut table = ::toml::Table::new();
+    for (key, value) in entries {
+        let key = unescape(key).into_owned();
+        if table.contains_key(&key) {
+            return Err(OutputError::unsupported(
+                "TOML",
+                format!("the repeated key {key:?}"),
+            ));
+        }
+        table.insert(key, to_toml(value)?);
+    }
+    Ok(table)
+}
+
+impl Serializer for Toml {
+    fn write(&self, out: &mut dyn Write, values: &[&Value]) -> Result<(), OutputError> {
+        let table = match values {
+            [Value::Object(object)] => to_table(object.entries())?,
+            [_] => {
+                return Err(OutputError::unsupported(
+                    "TOML",
+                    "a value that isn't an object",
+                ))
+            }
+            _ => {
+                return Err(OutputError::unsupported(
+                    "TOML",
+                    format!("{} results, only a single object", values.len()),
+                ))
+            }
+        };
+        write!(out, "{}", ::toml::to_string(&table)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::write;
+
+    #[test]
+    fn toml() {
+        let source = r#"[{"b": 1, "a": [1.5, "x"], "t": {"c": true}}]"#;
+        assert_eq!(
+            write(&Toml, source).unwrap(),
+            "b = 1\na = [1.5, \"x\"]\n\n[t]\nc = true\n"
+        );
+        assert_eq!(
+            write(&Toml, r#"[{"a": null}]"#).unwrap_err().to_string(),
+            "TOML can't represent null"
+        );
+        assert_eq!(
+            write(&Toml, r#"[{"a": 1, "a": 2}]"#)
+                .unwrap_err()
+                .to_string(),
+            "TOML can't represent the repeated key \"a\""
+        );
+        for big in [
+            "9223372036854775808",
+            "-1000000000000000000000000000000000000000000",
+        ] {
+            assert_eq!(
+                write(&Toml, &format!(r#"[{{"a": {big}}}]"#))
+                    .unwrap_err()
+                    .to_string(),
+                "TOML can't represent integers outside of 64 bits"
+            );
+        }
+        assert_eq!(
+            write(&Toml, r#"[{"a": 1e30}]"#).unwrap(),
+            "a = 1000000000000000000000000000000.0\n"
+        );
+        assert!(write(&Toml, "[1]").is_err());
+        assert!(write(&Toml, "[{}, {}]").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{io::Write, iter::Enumerate, slice::Iter};
+
+use crate::value::{escape_control, number::NumberValue, unescape, Number, Value};
+
+use super::{OutputError, Serializer};
+
+/// YAML in block style, every result is a document of its own.
+pub struct Yaml;
+
+/// Whether `s` can be written without quotes and still be read back as the same string.
+fn is_plain(s: &str) -> bool {
+    const RESERVED: [&str; 14] = [
+        "", "~", "null", "true", "false", "yes", "no", "on", "off", "y", "n", ".inf", "-.inf",
+        ".nan",
+    ];
+    !RESERVED.iter().any(|r| s.eq_ignore_ascii_case(r))
+        && s.parse::<f64>().is_err()
+        && !s.starts_with(|c: char| {
+            c.is_ascii_digit() || c.is_whitespace() || "-+.?:,[]{}#&*!|>'\"%@`=".contains(c)
+        })
+        && !s.ends_with([' ', ':'])
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.chars().any(char::is_control)
+}
+
+fn write_string(out: &mut String, s: &str) {
+    if is_plain(s) {
+        out.push_str(s);
+    } else {
+        let escaped = s.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push('"');
+        out.push_str(&escape_control(&escaped));
+        out.push('"');
+    }
+}
+
+fn write_number(out: &mut String, x: &Number) {
+    match (x.to_json(), x.value()) {
+        (Some(json), _) => out.push_str(&json),
+        (None, NumberValue::Float(x)) if x.is_nan() => out.push_str(".nan"),
+        (None, _) if x.as_f64() < 0.0 => out.push_str("-.inf"),
+        (None, _) => out.push_str(".inf"),
+    }
+}
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+/// A container whose items are being written, with the indentation of its following lines.
+enum Frame<'a> {
+    Array(Enumerate<Iter<'a, Value>>, usize),
+    Object(Enumerate<Iter<'a, (String, Value)>>, usize),
+}
+
+/// Writes `value` at the current position, its following lines are indented by `indent`. The
+/// containers being written are kept on a stack, so that nesting doesn't grow the call stack.
+fn write_value(out: &mut String, value: &Value, indent: usize) {
+    let mut stack = Vec::new();
+    let mut next = Some((value, indent));
+    loop {
+        if let Some((value, indent)) = next.take() {
+            match value {
+                Value::Array(arr) if !arr.arr.is_empty() => {
+                    stack.push(Frame::Array(arr.arr.iter().enumerate(), indent))
+                }
+                Value::Object(object) if !object.is_empty() => {
+                    stack.push(Frame::Object(object.entries().iter().enumerate(), indent))
+                }
+                Value::Array(_) => out.push_str("[]"),
+                Value::Object(_) => out.push_str("{}"),
+                Value::Null | Value::Error(_) | Value::Lazy(_) => out.push_str("null"),
+                Value::Bool(x) => out.push_str(&x.to_string()),
+                Value::Number(x) => write_number(out, x),
+                Value::String(x) => write_string(out, &unescape(x)),
+            }
+        }
+
+        let Some(frame) = stack.last_mut() else {
+            return;
+        };
+        match frame {
+            Frame::Array(items, indent) => {
+                let Some((i, item)) = items.next() else {
+                    stack.pop();
+                    continue;
+                };
+                if i > 0 {
+                    newline(out, *indent);
+                }
+                out.push_str("- ");
+                next = Some((item, *indent + 2));
+            }
+            Frame::Object(entries, indent) => {
+                let Some((i, (key, value))) = entries.next() else {
+                    stack.pop();
+                    continue;
+                };
+                if i > 0 {
+                    newline(out, *indent);
+                }
+                write_string(out, &unescape(key));
+                out.push(':');
+                let nested = match value {
+                    Value::Array(arr) => !arr.arr.is_empty(),
+                    Value::Object(object) => !object.is_empty(),
+                    _ => false,
+                };
+                if nested {
+                    newline(out, *indent + 2);
+                    next = Some((value, *indent + 2));
+                } else {
+                    out.push(' ');
+                    next = Some((value, *indent));
+                }
+            }
+        }
+    }
+}
+
+impl Serializer for Yaml {
+    fn write(&self, out: &mut dyn Write, values: &[&Value]) -> Result<(), OutputError> {
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                writeln!(out, "---")?;
+            }
+            let mut document = String::new();
+            write_value(&mut document, value, 0);
+            writeln!(out, "{document}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::write;
+
+    #[test]
+    fn yaml() {
+        let source = r#"[
+            {"a": [1, {"b": null, "c": []}, [true, 2]], "d": {"e": "f g"}, "h": {}},
+            ["yes", "1.5", "a: b", "x\ny", "-", "", "café", NaN, -Infinity, 0x10],
+            "plain"
+        ]"#;
+        let expected = r#"a:
+  - 1
+  - b: null
+    c: []
+  - - true
+    - 2
+d:
+  e: f g
+h: {}
+---
+- "yes"
+- "1.5"
+- "a: b"
+- "x\ny"
+- "-"
+- ""
+- café
+- .nan
+- -.inf
+- 16
+---
+plain
+"#;
+        assert_eq!(write(&Yaml, source).unwrap(), expected);
+    }
+}
@@ -0,0 +1,203 @@
+//! Completions of the key being typed at the end of a query.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use crate::value::{escape, unescape, Value};
+
+use super::{run, Language};
+
+/// A suggestion for the end of a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// The text that replaces the end of the query from [`Completion::start`].
+    pub text: String,
+    /// What is shown in the list, e.g. the key or `[0..9]`.
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The byte offset in the query where the completed segment starts.
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+/// Whether `key` can be written after a `.` without quotes.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The segment that selects the raw `key`, in `language`.
+fn key_segment(language: Language, key: &str) -> String {
+    let decoded = unescape(key);
+    match language {
+        _ if is_identifier(&decoded) => format!(".{decoded}"),
+        Language::JsonPath => format!("['{}']", decoded.replace('\\', "\\\\").replace('\'', "\\'")),
+        Language::Path | Language::Jq => format!(".\"{}\"", escape(&decoded)),
+    }
+}
+
+/// The completions of the segment at the end of `query`, a `.` optionally followed by the start
+/// of a key, or a `[`. Objects complete to their keys, the ones found in the most values first,
+/// so that arrays of objects with different keys complete sensibly. Arrays complete to `[*]` and
+/// a hint of their indices.
+pub fn complete(language: Language, query: &str, value: &Value) -> Option<Completion> {
+    let partial_len = query.len()
+        - query
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            .len();
+    let partial = &query[query.len() - partial_len..];
+    let start = (query.len() - partial_len).checked_sub(1)?;
+    let bracket = match query[..start + 1].chars().next_back()? {
+        '.' => false,
+        '[' if partial.is_empty() => true,
+        _ => return None,
+    };
+    // a second `.` is a descendant segment
+    if query[..start].ends_with('.') {
+        return None;
+    }
+
+    let parent = match (language, query[..start].trim()) {
+        (Language::JsonPath, "") => return None,
+        (_, "") => ".",
+        (_, parent) => parent,
+    };
+    let outputs = run(language, parent, value).ok()?;
+
+    // the keys and how many of the values have them, in the order they are first found
+    let mut keys: Vec<(&str, usize)> = Vec::new();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    let mut max_len = None;
+    for output in &outputs {
+        match output.value() {
+            Value::Object(object) => {
+                let mut seen = HashSet::new();
+                for (key, _) in object.entries() {
+                    if !seen.insert(key.as_str()) {
+                        continue;
+                    }
+                    match positions.get(key.as_str()) {
+                        Some(&i) => keys[i].1 += 1,
+                        None => {
+                            positions.insert(key.as_str(), keys.len());
+                            keys.push((key, 1));
+                        }
+                    }
+                }
+            }
+            Value::Array(arr) => {
+                max_len = Some(max_len.unwrap_or(0).max(arr.arr.len()));
+            }
+            _ => {}
+        }
+    }
+    // a stable sort keeps the order of the document among keys found as often
+    keys.sort_by_key(|&(_, count)| Reverse(count));
+
+    // `.` then the start of a key completes only to keys, and `[` only to indices
+    let mut candidates = Vec::new();
+    if let Some(len) = max_len.filter(|_| partial.is_empty()) {
+        if len > 0 {
+            candidates.push(Candidate {
+                text: "[0]".to_string(),
+                label: format!("[0..{}]", len - 1),
+            });
+        }
+        let wildcard = match language {
+            Language::Jq => "[]",
+            Language::Path | Language::JsonPath => "[*]",
+        };
+        candidates.push(Candidate {
+            text: wildcard.to_string(),
+            label: wildcard.to_string(),
+        });
+    }
+    // a key that is typed completely isn't suggested anymore
+    let prefix = partial.to_lowercase();
+    candidates.extend(
+        keys.into_iter()
+            .filter(|_| !bracket)
+            .filter(|(key, _)| {
+                let key = unescape(key);
+                key != partial && key.to_lowercase().starts_with(&prefix)
+            })
+            .map(|(key, _)| Candidate {
+                text: key_segment(language, key),
+                label: unescape(key).into_owned(),
+            }),
+    );
+    (!candidates.is_empty()).then_some(Completion { start, candidates })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::parse;
+
+    fn labels(language: Language, query: &str, value: &Value) -> Vec<String> {
+        complete(language, query, value)
+            .map(|completion| {
+                completion
+                    .candidates
+                    .into_iter()
+                    .map(|candidate| candidate.label)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn keys_by_frequency() {
+        let value = parse(
+            r#"{"data": {"items": [{"id": 1}, {"name": "a", "id": 2}, {"name": "b", "tag": 1}],
+                "info": 1, "other key": 2}}"#,
+        )
+        .unwrap();
+        assert_eq!(labels(Language::Path, ".", &value), ["data"]);
+        assert_eq!(labels(Language::Path, ".data.i", &value), ["items", "info"]);
+        assert_eq!(
+            labels(Language::Path, ".data.items[*].", &value),
+            ["id", "name", "tag"]
+        );
+        assert_eq!(
+            labels(Language::Path, ".data.items", &value),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            labels(Language::Path, ".data.items.", &value),
+            ["[0..2]", "[*]"]
+        );
+        assert_eq!(
+            labels(Language::Jq, ".data.items[", &value),
+            ["[0..2]", "[]"]
+        );
+        assert_eq!(
+            labels(Language::JsonPath, "$.data.items[*].n", &value),
+            ["name"]
+        );
+
+        let completion = complete(Language::Path, ".data.o", &value).unwrap();
+        assert_eq!(completion.start, 5);
+        assert_eq!(completion.candidates[0].text, ".\"other key\"");
+        let completion = complete(Language::JsonPath, "$.data.o", &value).unwrap();
+        assert_eq!(completion.candidates[0].text, "['other key']");
+    }
+
+    #[test]
+    fn nothing_to_complete() {
+        let value = parse(r#"{"a": {"b": 1}}"#).unwrap();
+        assert_eq!(complete(Language::Path, ".a.x", &value), None);
+        assert_eq!(complete(Language::Path, ".a.b.", &value), None);
+        assert_eq!(complete(Language::Path, "..", &value), None);
+        assert_eq!(complete(Language::Path, ".a[0", &value), None);
+        assert_eq!(complete(Language::Path, ".nope.", &value), None);
+    }
+}
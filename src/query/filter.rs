@@ -0,0 +1,527 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+use regex::Regex;
+
+use crate::value::{unescape, Number, Value};
+
+use super::{
+    path::{Path, PathParser},
+    QueryError, QueryErrorKind,
+};
+
+/// A constant in a filter, strings are decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+}
+
+/// A side of a comparison. A path is relative to the value being filtered and may select
+/// several values, or none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Path(Path),
+    Literal(Literal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl ValueType {
+    fn of(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Bool,
+            Value::Number(_) => Self::Number,
+            Value::String(_) => Self::String,
+            Value::Array(_) => Self::Array,
+            Value::Object(_) => Self::Object,
+            Value::Lazy(_) | Value::Error(_) => return None,
+        })
+    }
+}
+
+/// A compiled regex, compared by its pattern.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+/// A predicate over the children of a container, written inside of `[?(...)]`:
+///
+/// - `.a.b` (or `@.a.b`) is true when it selects a value that isn't `null` or `false`, `.` is
+///   the child itself
+/// - `==`, `!=`, `<`, `<=`, `>` and `>=` compare paths and literals (`1`, `"text"`, `true`,
+///   `null`), numbers by their exact value and strings decoded
+/// - `&&`, `||`, `!` and parentheses combine predicates
+/// - `startswith(.a, "x")`, `endswith(.a, "x")`, `contains(.a, "x")` (a substring or an array
+///   item), `test(.a, "regex")`, `exists(.a)` and the type checks `is_null(.a)`, `is_bool`,
+///   `is_number`, `is_string`, `is_array` and `is_object`
+///
+/// A path that selects several values matches when any of them does, `!=` is the negation of
+/// `==`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Or(Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Truthy(Operand),
+    Compare(Operand, CompareOp, Operand),
+    StartsWith(Path, String),
+    EndsWith(Path, String),
+    Contains(Path, Literal),
+    Test(Path, Pattern),
+    Exists(Path),
+    Type(Path, ValueType),
+}
+
+/// A value or a literal, as compared by a filter.
+enum Term<'a> {
+    Null,
+    Bool(bool),
+    Number(&'a Number),
+    String(Cow<'a, str>),
+    Container(&'a Value),
+}
+
+impl<'a> Term<'a> {
+    fn of_value(value: &'a Value) -> Option<Self> {
+        Some(match value {
+            Value::Null => Self::Null,
+            Value::Bool(x) => Self::Bool(*x),
+            Value::Number(x) => Self::Number(x),
+            Value::String(x) => Self::String(unescape(x)),
+            Value::Array(_) | Value::Object(_) => Self::Container(value),
+            Value::Lazy(_) | Value::Error(_) => return None,
+        })
+    }
+
+    fn of_literal(literal: &'a Literal) -> Self {
+        match literal {
+            Literal::Null => Self::Null,
+            Literal::Bool(x) => Self::Bool(*x),
+            Literal::Number(x) => Self::Number(x),
+            Literal::String(x) => Self::String(Cow::Borrowed(x)),
+        }
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Null, Self::Null) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Container(a), Self::Container(b)) => values_equal(a, b),
+            _ => false,
+        }
+    }
+
+    /// Only numbers and strings are ordered.
+    fn order(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => Some(a.cmp(b)),
+            (Self::String(a), Self::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// Deep equality, with numbers compared by value and keys and strings decoded. The pairs of
+/// values left to compare are kept on a stack, nesting doesn't grow the call stack.
+fn values_equal(a: &Value, b: &Value) -> bool {
+    let mut pairs = vec![(a, b)];
+    while let Some(pair) = pairs.pop() {
+        let equal = match pair {
+            (Value::Array(a), Value::Array(b)) => {
+                pairs.extend(a.arr.iter().zip(&b.arr));
+                a.arr.len() == b.arr.len()
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                pairs.extend(
+                    a.entries()
+                        .iter()
+                        .zip(b.entries())
+                        .map(|((_, a), (_, b))| (a, b)),
+                );
+                a.len() == b.len()
+                    && a.entries()
+                        .iter()
+                        .zip(b.entries())
+                        .all(|((a, _), (b, _))| unescape(a) == unescape(b))
+            }
+            (a, b) => match (Term::of_value(a), Term::of_value(b)) {
+                (Some(a), Some(b)) => a.equals(&b),
+                _ => false,
+            },
+        };
+        if !equal {
+            return false;
+        }
+    }
+    true
+}
+
+impl Operand {
+    fn terms<'a>(&'a self, value: &'a Value) -> Vec<Term<'a>> {
+        match self {
+            Self::Path(path) => path
+                .select(value)
+                .into_iter()
+                .filter_map(|(_, v)| Term::of_value(v))
+                .collect(),
+            Self::Literal(literal) => vec![Term::of_literal(literal)],
+        }
+    }
+}
+
+impl CompareOp {
+    fn holds(self, a: &Term, b: &Term) -> bool {
+        match self {
+            Self::Eq => a.equals(b),
+            Self::Ne => !a.equals(b),
+            Self::Lt => a.order(b) == Some(Ordering::Less),
+            Self::Le => matches!(a.order(b), Some(Ordering::Less | Ordering::Equal)),
+            Self::Gt => a.order(b) == Some(Ordering::Greater),
+            Self::Ge => matches!(a.order(b), Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+/// The decoded strings that `path` selects.
+fn strings<'a>(path: &Path, value: &'a Value) -> impl Iterator<Item = Cow<'a, str>> {
+    path.select(value)
+        .into_iter()
+        .filter_map(|(_, v)| v.get_string().map(unescape))
+}
+
+impl Filter {
+    /// Whether `value`, a child of the filtered container, matches.
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::Or(a, b) => a.matches(value) || b.matches(value),
+            Self::And(a, b) => a.matches(value) && b.matches(value),
+            Self::Not(filter) => !filter.matches(value),
+            Self::Truthy(operand) => operand
+                .terms(value)
+                .iter()
+                .any(|term| !matches!(term, Term::Null | Term::Bool(false))),
+            Self::Compare(a, op, b) => {
+                let (a, b) = (a.terms(value), b.terms(value));
+                let any = |op: CompareOp| a.iter().any(|a| b.iter().any(|b| op.holds(a, b)));
+                match op {
+                    CompareOp::Ne => !any(CompareOp::Eq),
+                    op => any(*op),
+                }
+            }
+            Self::StartsWith(path, prefix) => {
+                strings(path, value).any(|s| s.starts_with(prefix.as_str()))
+            }
+            Self::EndsWith(path, suffix) => {
+                strings(path, value).any(|s| s.ends_with(suffix.as_str()))
+            }
+            Self::Contains(path, needle) => {
+                let needle = Term::of_literal(needle);
+                path.select(value)
+                    .into_iter()
+                    .any(|(_, v)| match (v, &needle) {
+                        (Value::String(s), Term::String(needle)) => {
+                            unescape(s).contains(needle.as_ref())
+                        }
+                        (Value::Array(arr), needle) => arr.arr.iter().any(|item| {
+                            Term::of_value(item).is_some_and(|item| item.equals(needle))
+                        }),
+                        _ => false,
+                    })
+            }
+            Self::Test(path, pattern) => strings(path, value).any(|s| pattern.0.is_match(&s)),
+            Self::Exists(path) => !path.select(value).is_empty(),
+            Self::Type(path, ty) => path
+                .select(value)
+                .into_iter()
+                .any(|(_, v)| ValueType::of(v) == Some(*ty)),
+        }
+    }
+}
+
+impl PathParser<'_> {
+    /// A filter, after the `?` of `[?`.
+    pub fn filter(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = self.and()?;
+        while self.operator("||") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    /// Consumes `op` if it comes next.
+    fn operator(&mut self, op: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(op);
+        if found {
+            self.pos += op.len();
+        }
+        found
+    }
+
+    fn and(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = self.unary()?;
+        while self.operator("&&") {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, QueryError> {
+        self.skip_whitespace();
+        if self.rest().starts_with('!') && !self.rest().starts_with("!=") {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            let filter = self.filter()?;
+            self.expect(')')?;
+            return Ok(filter);
+        }
+
+        let start = self.pos;
+        if let Some(name) = self.identifier() {
+            if !matches!(name.as_str(), "true" | "false" | "null") {
+                return self.function(name, start);
+            }
+            self.pos = start;
+        }
+        let left = self.operand()?;
+        let op = [
+            ("==", CompareOp::Eq),
+            ("!=", CompareOp::Ne),
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt),
+        ]
+        .into_iter()
+        .find(|(s, _)| self.operator(s));
+        Ok(match op {
+            Some((_, op)) => Filter::Compare(left, op, self.operand()?),
+            None => Filter::Truthy(left),
+        })
+    }
+
+    fn function(&mut self, name: String, start: usize) -> Result<Filter, QueryError> {
+        let arguments = |n| QueryError::new(QueryErrorKind::Arguments(name.clone(), n), start);
+        self.expect('(')?;
+        let path = match self.operand()? {
+            Operand::Path(path) => path,
+            Operand::Literal(_) => return Err(arguments("a path first")),
+        };
+        let mut literal = None;
+        if self.operator(",") {
+            literal = Some((self.pos, self.literal()?));
+        }
+        self.expect(')')?;
+
+        let ty = |ty| match literal {
+            None => Ok(Filter::Type(path.clone(), ty)),
+            Some(_) => Err(arguments("a single path")),
+        };
+        let string = |literal: Option<(usize, Literal)>| match literal {
+            Some((_, Literal::String(s))) => Ok(s),
+            _ => Err(arguments("a path and a string")),
+        };
+        match name.as_str() {
+            "startswith" => Ok(Filter::StartsWith(path, string(literal)?)),
+            "endswith" => Ok(Filter::EndsWith(path, string(literal)?)),
+            "contains" => match literal {
+                Some((_, literal)) => Ok(Filter::Contains(path, literal)),
+                None => Err(arguments("a path and a value")),
+            },
+            "test" => {
+                let offset = literal.as_ref().map_or(start, |(offset, _)| *offset);
+                let regex = Regex::new(&string(literal)?).map_err(|err| {
+                    QueryError::new(QueryErrorKind::InvalidRegex(err.to_string()), offset)
+                })?;
+                Ok(Filter::Test(path, Pattern(regex)))
+            }
+            "exists" => match literal {
+                None => Ok(Filter::Exists(path)),
+                Some(_) => Err(arguments("a single path")),
+            },
+            "is_null" => ty(ValueType::Null),
+            "is_bool" => ty(ValueType::Bool),
+            "is_number" => ty(ValueType::Number),
+            "is_string" => ty(ValueType::String),
+            "is_array" => ty(ValueType::Array),
+            "is_object" => ty(ValueType::Object),
+            _ => Err(QueryError::new(
+                QueryErrorKind::UnknownFunction(name),
+                start,
+            )),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, QueryError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('.' | '@' | '[') => Ok(Operand::Path(self.relative_path()?)),
+            _ => Ok(Operand::Literal(self.literal()?)),
+        }
+    }
+
+    /// A path from the filtered value, `.` and `@` are the value itself.
+    fn relative_path(&mut self) -> Result<Path, QueryError> {
+        let mut segments = Vec::new();
+        match self.peek() {
+            Some('@') => self.pos += 1,
+            Some('.') => {
+                self.pos += 1;
+                let continues = self
+                    .peek()
+                    .is_some_and(|c| c.is_alphabetic() || matches!(c, '_' | '"' | '*'));
+                if continues {
+                    segments.push(self.dotted()?);
+                }
+            }
+            _ => {}
+        }
+        self.segments(&mut segments)?;
+        Ok(Path { segments })
+    }
+
+    fn literal(&mut self) -> Result<Literal, QueryError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            Some('"' | '\'') => Ok(Literal::String(unescape(&self.raw_string()?).into_owned())),
+            Some(c) if c == '-' || c == '+' || c == '.' || c.is_ascii_digit() => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+')))
+                    .unwrap_or(self.rest().len());
+                self.pos += len;
+                Number::parse_lenient(&self.query[start..self.pos])
+                    .map(Literal::Number)
+                    .ok_or_else(|| QueryError::new(QueryErrorKind::InvalidNumber, start))
+            }
+            _ => match self.identifier().as_deref() {
+                Some("true") => Ok(Literal::Bool(true)),
+                Some("false") => Ok(Literal::Bool(false)),
+                Some("null") => Ok(Literal::Null),
+                _ => {
+                    self.pos = start;
+                    Err(self.unexpected())
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        query::{Path, QueryError, QueryErrorKind},
+        value::parse_lenient,
+    };
+
+    fn select(source: &str, query: &str) -> Vec<String> {
+        let value = parse_lenient(source).unwrap().value;
+        let path: Path = query.parse().unwrap();
+        path.evaluate(&value)
+            .iter()
+            .map(|path| value.get_path(path).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn filters() {
+        let users = r#"{"users": [
+            {"name": "Ann", "age": 31, "active": true, "tags": ["a"]},
+            {"name": "Bob", "age": 30.0, "active": false, "email": null},
+            {"name": "Cid", "age": 4.5e1, "tags": ["b", "a"]},
+            {"name": "D\u00e9", "age": "old"}
+        ]}"#;
+        let names = |query: &str| {
+            select(users, &format!(".users[?({query})].name"))
+                .into_iter()
+                .map(|name| name.trim_matches('"').to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(".age > 30 && .active"), vec!["Ann"]);
+        assert_eq!(
+            names(".age >= 30 || .name == 'Dé'"),
+            vec!["Ann", "Bob", "Cid", "D\\u00e9"]
+        );
+        assert_eq!(names(".age == 30"), vec!["Bob"]);
+        assert_eq!(names(".age != 30"), vec!["Ann", "Cid", "D\\u00e9"]);
+        assert_eq!(names("!.active"), vec!["Bob", "Cid", "D\\u00e9"]);
+        assert_eq!(names("startswith(.name, \"D\")"), vec!["D\\u00e9"]);
+        assert_eq!(names("endswith(.name, \"é\")"), vec!["D\\u00e9"]);
+        assert_eq!(
+            names("contains(.tags, \"b\") || contains(.name, 'nn')"),
+            vec!["Ann", "Cid"]
+        );
+        assert_eq!(names("test(.name, \"^[AB]\")"), vec!["Ann", "Bob"]);
+        assert_eq!(
+            names("exists(.email) || is_string(.age)"),
+            vec!["Bob", "D\\u00e9"]
+        );
+        assert_eq!(names("(.age < 31) && !(.tags[1] == 'a')"), vec!["Bob"]);
+
+        assert_eq!(select("[1, 5, 10, 'x']", "[?(. > 4)]"), vec!["5", "10"]);
+        assert_eq!(select("{a: 1, b: 2}", ".[?(@ == 2)]"), vec!["2"]);
+
+        let error = |s: &str| s.parse::<Path>().unwrap_err();
+        assert_eq!(
+            error(".a[?(size(.b))]"),
+            QueryError::new(QueryErrorKind::UnknownFunction("size".into()), 5)
+        );
+        assert_eq!(
+            error(".a[?(.b > 1"),
+            QueryError::new(QueryErrorKind::UnexpectedEnd, 11)
+        );
+        assert!(matches!(
+            error(".a[?(test(.b, '('))]").kind,
+            QueryErrorKind::InvalidRegex(_)
+        ));
+        assert_eq!(
+            error(".a[?(exists(1))]").to_string(),
+            "`exists` takes a path first at column 6"
+        );
+    }
+
+    #[test]
+    fn deep_equality() {
+        let depth = 100_000;
+        let deep = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        let value = parse_lenient(&format!("[{{a: {deep}, b: {deep}}}, {{a: {deep}, b: []}}]"))
+            .unwrap()
+            .value;
+        let path: Path = "[?(.a == .b)]".parse().unwrap();
+        assert_eq!(path.evaluate(&value), vec![vec![0]]);
+    }
+}
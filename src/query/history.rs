@@ -0,0 +1,176 @@
+//! The queries that were run, kept per file type in `$XDG_STATE_HOME/tispect/history`.
+
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+
+use crate::config::state_home;
+
+use super::Language;
+
+/// How many queries are kept, the oldest ones are forgotten first.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub language: Language,
+    pub query: String,
+}
+
+impl HistoryEntry {
+    /// The line of the history file, the name of the language, a tab and the query. Queries are
+    /// typed on a single line, but may have been pasted with tabs.
+    fn line(&self) -> String {
+        let query = self.query.replace(['\t', '\n', '\r'], " ");
+        format!("{}\t{query}\n", self.language.name())
+    }
+}
+
+/// The entries in the lines of a history file, oldest first and without repeated ones. Lines
+/// that can't be read are skipped.
+fn parse(text: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    for line in text.lines() {
+        let Some((language, query)) = line.split_once('\t') else {
+            continue;
+        };
+        let Ok(language) = Language::from_str(language, false) else {
+            continue;
+        };
+        let entry = HistoryEntry {
+            language,
+            query: query.to_string(),
+        };
+        entries.retain(|e| *e != entry);
+        entries.push(entry);
+    }
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    entries.drain(..excess);
+    entries
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryHistory {
+    /// The file the entries are appended to, `None` to only keep them in memory.
+    path: Option<PathBuf>,
+    entries: Vec<HistoryEntry>,
+}
+
+impl QueryHistory {
+    /// The history file of the files with the extension `file_type`.
+    pub fn path(file_type: &str) -> Option<PathBuf> {
+        let name = file_type.to_lowercase().replace(['/', '\\'], "_");
+        state_home().map(|dir| dir.join("tispect").join("history").join(name))
+    }
+
+    /// Reads the history of the file type, an empty one when nothing was recorded yet. The file
+    /// only grows when queries are recorded, it is rewritten once it holds many repeated or
+    /// forgotten entries.
+    pub fn load(file_type: &str) -> std::io::Result<Self> {
+        let Some(path) = Self::path(file_type) else {
+            return Ok(Self::default());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let entries = parse(&text);
+        if text.lines().count() > 2 * MAX_ENTRIES {
+            let text = entries.iter().map(HistoryEntry::line).collect::<String>();
+            std::fs::write(&path, text)?;
+        }
+        Ok(Self {
+            path: Some(path),
+            entries,
+        })
+    }
+
+    /// The entries, oldest first.
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// The newest entry before `before` whose query contains `pattern`.
+    pub fn search(&self, pattern: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.query.contains(pattern))
+    }
+
+    /// Adds the entry as the newest one, and appends it to the file.
+    pub fn record(&mut self, entry: HistoryEntry) -> std::io::Result<()> {
+        if entry.query.trim().is_empty() || self.entries.last() == Some(&entry) {
+            return Ok(());
+        }
+        self.entries.retain(|e| *e != entry);
+        self.entries.push(entry);
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(self.entries.last().unwrap().line().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(language: Language, query: &str) -> HistoryEntry {
+        HistoryEntry {
+            language,
+            query: query.to_string(),
+        }
+    }
+
+    #[test]
+    fn file_format() {
+        let entries = parse("path\t.a\njq\t.a | keys\nnonsense\nsql\tselect\npath\t.a\n\n");
+        assert_eq!(
+            entries,
+            [
+                entry(Language::Jq, ".a | keys"),
+                entry(Language::Path, ".a")
+            ]
+        );
+        assert_eq!(
+            entry(Language::JsonPath, "$[?@.a == 'x\ty']").line(),
+            "jsonpath\t$[?@.a == 'x y']\n"
+        );
+        let text = (0..MAX_ENTRIES + 10)
+            .map(|i| entry(Language::Path, &format!("[{i}]")).line())
+            .collect::<String>();
+        let entries = parse(&text);
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].query, "[10]");
+    }
+
+    #[test]
+    fn record_and_search() {
+        let mut history = QueryHistory::default();
+        for query in [".a", ".b.c", ".a", ".c", " "] {
+            history.record(entry(Language::Path, query)).unwrap();
+        }
+        let queries = history
+            .entries()
+            .iter()
+            .map(|entry| entry.query.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(queries, [".b.c", ".a", ".c"]);
+        assert_eq!(history.search("c", 3), Some(2));
+        assert_eq!(history.search("c", 2), Some(0));
+        assert_eq!(history.search("c", 0), None);
+        assert_eq!(history.search("x", 3), None);
+    }
+}
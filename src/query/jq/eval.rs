@@ -0,0 +1,1138 @@
+use std::{borrow::Cow, cmp::Ordering, collections::HashMap};
+
+use regex::Regex;
+
+use crate::value::{
+    escape, parse, unescape,
+    writer::{LayoutConfig, Writer},
+    Number, Value, ValueArray, ValueObject,
+};
+
+use super::{
+    super::{task::is_cancelled, Output},
+    parser::{BinaryOp, Expr, Format, StringPart},
+    JqError,
+};
+
+type Outputs<'v> = Result<Vec<Output<'v>>, JqError>;
+
+/// The longest string, in bytes, that repeating a string builds. Longer ones fail rather than
+/// exhaust the memory.
+const MAX_REPEAT_LEN: usize = 1 << 28;
+
+/// `value` as JSON on a single line.
+pub(super) fn to_json(value: &Value) -> String {
+    let mut writer = json_writer();
+    writer.write_value(value);
+    writer.to_string()
+}
+
+/// The first `width` characters of [`to_json`].
+pub(super) fn to_json_preview(value: &Value, width: usize) -> String {
+    let mut writer = json_writer();
+    writer.with_preview(width);
+    writer.write_value(value);
+    writer.to_string()
+}
+
+fn json_writer() -> Writer {
+    let mut writer = Writer::new();
+    writer.with_layout(&LayoutConfig {
+        compact: true,
+        ..LayoutConfig::default()
+    });
+    writer.with_valid_json(true);
+    writer
+}
+
+fn string(s: &str) -> Value {
+    Value::String(escape(s).into_owned())
+}
+
+fn number(n: usize) -> Value {
+    Value::Number(Number::from(n as i64))
+}
+
+fn array(items: Vec<Value>) -> Value {
+    Value::Array(ValueArray::new(items))
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+        Value::Lazy(_) => "unloaded container",
+        Value::Error(_) => "invalid value",
+    }
+}
+
+/// The type and the start of the JSON of a value, for error messages.
+fn describe(value: &Value) -> String {
+    let json = to_json(value);
+    let json = match json.char_indices().nth(11) {
+        Some((i, _)) => format!("{}...", &json[..i]),
+        None => json,
+    };
+    format!("{} ({json})", type_name(value))
+}
+
+/// The text of a value inserted into a string, raw: strings as they are and the rest as JSON.
+fn raw_text(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::String(raw) => Cow::Borrowed(raw),
+        _ => Cow::Owned(escape(&to_json(value)).into_owned()),
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(raw) => unescape(raw).into_owned(),
+        _ => to_json(value),
+    }
+}
+
+/// The entries of an object like jq sees them: a repeated key keeps the position of its first
+/// entry and the value of its last one. The positions are the indices of the values.
+fn entries(object: &ValueObject) -> Vec<(Cow<'_, str>, usize)> {
+    let all = object.entries().iter().enumerate();
+    if !object.has_duplicate_keys() {
+        return all.map(|(i, (key, _))| (unescape(key), i)).collect();
+    }
+    let mut out: Vec<(Cow<'_, str>, usize)> = Vec::new();
+    let mut positions: HashMap<Cow<'_, str>, usize> = HashMap::new();
+    for (i, (key, _)) in all {
+        let key = unescape(key);
+        match positions.get(&key) {
+            Some(&position) => out[position].1 = i,
+            None => {
+                positions.insert(key.clone(), out.len());
+                out.push((key, i));
+            }
+        }
+    }
+    out
+}
+
+/// Builds an object where setting a key that is already there replaces its value.
+#[derive(Debug, Clone, Default)]
+struct ObjectBuilder {
+    entries: Vec<(String, Value)>,
+    index: HashMap<String, usize>,
+}
+
+impl ObjectBuilder {
+    fn from_object(object: &ValueObject) -> Self {
+        let mut builder = Self::default();
+        for (key, i) in entries(object) {
+            builder.set(&key, object.entries()[i].1.clone());
+        }
+        builder
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.index.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    /// Sets the value of a decoded key.
+    fn set(&mut self, key: &str, value: Value) {
+        match self.index.get(key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(key.to_string(), self.entries.len());
+                self.entries.push((escape(key).into_owned(), value));
+            }
+        }
+    }
+
+    fn build(self) -> Value {
+        Value::Object(ValueObject::from_entries(self.entries))
+    }
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+        Value::Lazy(_) | Value::Error(_) => 7,
+    }
+}
+
+/// jq's order of values: `null`, `false`, `true`, numbers, strings, arrays and objects. Objects
+/// are compared by their sorted keys first, then by their values in the order of the keys.
+pub(super) fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.cmp(y),
+        (Value::String(x), Value::String(y)) => unescape(x).cmp(&unescape(y)),
+        (Value::Array(x), Value::Array(y)) => x
+            .arr
+            .iter()
+            .zip(&y.arr)
+            .map(|(x, y)| compare(x, y))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| x.arr.len().cmp(&y.arr.len())),
+        (Value::Object(x), Value::Object(y)) => {
+            let sorted = |object| {
+                let mut entries = entries(object);
+                entries.sort();
+                entries
+            };
+            let (xs, ys) = (sorted(x), sorted(y));
+            let keys = xs.iter().map(|(k, _)| k).cmp(ys.iter().map(|(k, _)| k));
+            if keys.is_ne() {
+                return keys;
+            }
+            xs.iter()
+                .zip(&ys)
+                .map(|((_, i), (_, j))| compare(&x.entries()[*i].1, &y.entries()[*j].1))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// The child at `i` (the index of an item, or of an entry for objects).
+fn child<'v>(parent: &Output<'v>, i: usize) -> Output<'v> {
+    fn get(value: &Value, i: usize) -> &Value {
+        match value {
+            Value::Array(arr) => &arr.arr[i],
+            Value::Object(object) => &object.entries()[i].1,
+            _ => unreachable!("only containers have children"),
+        }
+    }
+    match parent {
+        Output::Ref(path, value) => {
+            let mut path = path.clone();
+            path.push(i);
+            Output::Ref(path, get(value, i))
+        }
+        Output::Owned(value) => Output::Owned(get(value, i).clone()),
+    }
+}
+
+fn children<'v>(parent: &Output<'v>) -> Outputs<'v> {
+    match parent.value() {
+        Value::Array(arr) => Ok((0..arr.arr.len()).map(|i| child(parent, i)).collect()),
+        Value::Object(object) => Ok(entries(object)
+            .into_iter()
+            .map(|(_, i)| child(parent, i))
+            .collect()),
+        value => Err(JqError(format!("Cannot iterate over {}", describe(value)))),
+    }
+}
+
+fn recurse<'v>(value: &Output<'v>, out: &mut Vec<Output<'v>>) {
+    out.push(value.clone());
+    // unloaded containers are skipped, like when selecting paths
+    if let Value::Array(_) | Value::Object(_) = value.value() {
+        for child in children(value).unwrap_or_default() {
+            recurse(&child, out);
+        }
+    }
+}
+
+fn index<'v>(target: &Output<'v>, index: &Value) -> Result<Output<'v>, JqError> {
+    match (target.value(), index) {
+        (Value::Null, Value::String(_) | Value::Number(_)) => Ok(Output::Owned(Value::Null)),
+        (Value::Object(object), Value::String(key)) => {
+            match object.positions(&unescape(key)).last() {
+                Some(&i) => Ok(child(target, i)),
+                None => Ok(Output::Owned(Value::Null)),
+            }
+        }
+        (Value::Array(arr), Value::Number(n)) => {
+            let i = n.as_f64().floor() as i64;
+            let i = if i < 0 { i + arr.arr.len() as i64 } else { i };
+            match usize::try_from(i) {
+                Ok(i) if i < arr.arr.len() => Ok(child(target, i)),
+                _ => Ok(Output::Owned(Value::Null)),
+            }
+        }
+        (value, Value::String(key)) => Err(JqError(format!(
+            "Cannot index {} with \"{}\"",
+            type_name(value),
+            unescape(key)
+        ))),
+        (value, index) => Err(JqError(format!(
+            "Cannot index {} with {}",
+            type_name(value),
+            type_name(index)
+        ))),
+    }
+}
+
+fn slice<'v>(target: &Output<'v>, from: &Value, to: &Value) -> Result<Output<'v>, JqError> {
+    let len = match target.value() {
+        Value::Null => return Ok(Output::Owned(Value::Null)),
+        Value::Array(arr) => arr.arr.len(),
+        Value::String(raw) => unescape(raw).chars().count(),
+        value => {
+            return Err(JqError(format!(
+                "Cannot index {} with object",
+                type_name(value)
+            )))
+        }
+    };
+    let bound = |value: &Value, default: usize, round: fn(f64) -> f64| match value {
+        Value::Null => Ok(default),
+        Value::Number(n) => {
+            let i = round(n.as_f64());
+            let i = if i < 0.0 { i + len as f64 } else { i };
+            Ok(i.clamp(0.0, len as f64) as usize)
+        }
+        _ => Err(JqError(
+            "Start and end indices of an array slice must be numbers".to_string(),
+        )),
+    };
+    let from = bound(from, 0, f64::floor)?;
+    let to = bound(to, len, f64::ceil)?.max(from);
+    Ok(Output::Owned(match target.value() {
+        Value::Array(arr) => array(arr.arr[from..to].to_vec()),
+        Value::String(raw) => string(
+            &unescape(raw)
+                .chars()
+                .skip(from)
+                .take(to - from)
+                .collect::<String>(),
+        ),
+        _ => unreachable!(),
+    }))
+}
+
+fn cannot(a: &Value, b: &Value, what: &str) -> JqError {
+    JqError(format!(
+        "{} and {} cannot be {what}",
+        describe(a),
+        describe(b)
+    ))
+}
+
+/// Integers stay exact as long as they fit in an `i64`, the rest is computed with floats.
+fn arithmetic(
+    a: &Number,
+    b: &Number,
+    integer: fn(i128, i128) -> Option<i128>,
+    float: fn(f64, f64) -> f64,
+) -> Value {
+    let exact = a
+        .as_i128()
+        .zip(b.as_i128())
+        .and_then(|(a, b)| integer(a, b))
+        .and_then(|x| i64::try_from(x).ok());
+    Value::Number(match exact {
+        Some(x) => Number::from(x),
+        None => Number::from_f64(float(a.as_f64(), b.as_f64())),
+    })
+}
+
+fn add(a: &Value, b: &Value) -> Result<Value, JqError> {
+    Ok(match (a, b) {
+        (Value::Null, _) => b.clone(),
+        (_, Value::Null) => a.clone(),
+        (Value::Number(x), Value::Number(y)) => arithmetic(x, y, i128::checked_add, |x, y| x + y),
+        (Value::String(x), Value::String(y)) => Value::String(format!("{x}{y}")),
+        (Value::Array(x), Value::Array(y)) => array(x.arr.iter().chain(&y.arr).cloned().collect()),
+        (Value::Object(x), Value::Object(y)) => {
+            let mut builder = ObjectBuilder::from_object(x);
+            for (key, i) in entries(y) {
+                builder.set(&key, y.entries()[i].1.clone());
+            }
+            builder.build()
+        }
+        _ => return Err(cannot(a, b, "added")),
+    })
+}
+
+/// Merges `b` into `a`, recursively for the keys whose values are objects in both.
+fn merge(a: &ValueObject, b: &ValueObject) -> Value {
+    let mut builder = ObjectBuilder::from_object(a);
+    for (key, i) in entries(b) {
+        let value = &b.entries()[i].1;
+        let merged = match (builder.get(&key), value) {
+            (Some(Value::Object(x)), Value::Object(y)) => merge(x, y),
+            _ => value.clone(),
+        };
+        builder.set(&key, merged);
+    }
+    builder.build()
+}
+
+fn binary(a: &Value, op: BinaryOp, b: &Value) -> Result<Value, JqError> {
+    let zero_divisor = || {
+        JqError(format!(
+            "{} and {} cannot be divided because the divisor is zero",
+            describe(a),
+            describe(b)
+        ))
+    };
+    Ok(match (op, a, b) {
+        (BinaryOp::Eq, _, _) => Value::Bool(compare(a, b).is_eq()),
+        (BinaryOp::Ne, _, _) => Value::Bool(compare(a, b).is_ne()),
+        (BinaryOp::Lt, _, _) => Value::Bool(compare(a, b).is_lt()),
+        (BinaryOp::Le, _, _) => Value::Bool(compare(a, b).is_le()),
+        (BinaryOp::Gt, _, _) => Value::Bool(compare(a, b).is_gt()),
+        (BinaryOp::Ge, _, _) => Value::Bool(compare(a, b).is_ge()),
+        (BinaryOp::Add, _, _) => add(a, b)?,
+
+        (BinaryOp::Sub, Value::Number(x), Value::Number(y)) => {
+            arithmetic(x, y, i128::checked_sub, |x, y| x - y)
+        }
+        (BinaryOp::Sub, Value::Array(x), Value::Array(y)) => array(
+            x.arr
+                .iter()
+                .filter(|x| !y.arr.iter().any(|y| compare(x, y).is_eq()))
+                .cloned()
+                .collect(),
+        ),
+        (BinaryOp::Sub, _, _) => return Err(cannot(a, b, "subtracted")),
+
+        (BinaryOp::Mul, Value::Number(x), Value::Number(y)) => {
+            arithmetic(x, y, i128::checked_mul, |x, y| x * y)
+        }
+        (BinaryOp::Mul, Value::String(s), Value::Number(n))
+        | (BinaryOp::Mul, Value::Number(n), Value::String(s)) => {
+            let times = n.as_f64().ceil();
+            if times <= 0.0 {
+                Value::Null
+            } else if times * s.len() as f64 > MAX_REPEAT_LEN as f64 {
+                return Err(JqError("Repeat string result too long".to_string()));
+            } else {
+                Value::String(s.repeat(times as usize))
+            }
+        }
+        (BinaryOp::Mul, Value::Object(x), Value::Object(y)) => merge(x, y),
+        (BinaryOp::Mul, _, _) => return Err(cannot(a, b, "multiplied")),
+
+        (BinaryOp::Div, Value::Number(x), Value::Number(y)) => {
+            if y.as_f64() == 0.0 {
+                return Err(zero_divisor());
+            }
+            Value::Number(Number::from_f64(x.as_f64() / y.as_f64()))
+        }
+        (BinaryOp::Div, Value::String(x), Value::String(y)) => split(x, y),
+        (BinaryOp::Div, _, _) => return Err(cannot(a, b, "divided")),
+
+        (BinaryOp::Mod, Value::Number(x), Value::Number(y)) => {
+            let (x, y) = (x.as_f64() as i128, y.as_f64() as i128);
+            if y == 0 {
+                return Err(zero_divisor());
+            }
+            Value::Number(Number::from((x % y) as i64))
+        }
+        (BinaryOp::Mod, _, _) => return Err(cannot(a, b, "divided")),
+    })
+}
+
+/// Splits the raw string `s` on the raw `separator`.
+fn split(s: &str, separator: &str) -> Value {
+    let (s, separator) = (unescape(s), unescape(separator));
+    if s.is_empty() {
+        return array(Vec::new());
+    }
+    let parts = if separator.is_empty() {
+        s.chars()
+            .map(|c| string(c.encode_utf8(&mut [0; 4])))
+            .collect()
+    } else {
+        s.split(separator.as_ref()).map(string).collect()
+    };
+    array(parts)
+}
+
+fn length(value: &Value) -> Result<Value, JqError> {
+    Ok(match value {
+        Value::Null => number(0),
+        Value::Number(n) if n.as_f64() < 0.0 => {
+            arithmetic(&Number::from(0), n, i128::checked_sub, |x, y| x - y)
+        }
+        Value::Number(_) => value.clone(),
+        Value::String(raw) => number(unescape(raw).chars().count()),
+        Value::Array(arr) => number(arr.arr.len()),
+        Value::Object(object) => number(entries(object).len()),
+        _ => return Err(JqError(format!("{} has no length", describe(value)))),
+    })
+}
+
+fn keys(value: &Value, sorted: bool) -> Result<Value, JqError> {
+    match value {
+        Value::Array(arr) => Ok(array((0..arr.arr.len()).map(number).collect())),
+        Value::Object(object) => {
+            let mut keys = entries(object)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>();
+            if sorted {
+                keys.sort();
+            }
+            Ok(array(keys.iter().map(|key| string(key)).collect()))
+        }
+        _ => Err(JqError(format!("{} has no keys", describe(value)))),
+    }
+}
+
+fn has(value: &Value, key: &Value) -> Result<bool, JqError> {
+    match (value, key) {
+        (Value::Object(object), Value::String(key)) => {
+            Ok(object.position(&unescape(key)).is_some())
+        }
+        (Value::Array(arr), Value::Number(n)) => {
+            Ok(n.as_f64() >= 0.0 && n.as_f64() < arr.arr.len() as f64)
+        }
+        _ => Err(JqError(format!(
+            "Cannot check whether {} has a {} key",
+            type_name(value),
+            type_name(key)
+        ))),
+    }
+}
+
+/// The entries of an object, or of an array with the indices as keys.
+fn to_entries(value: &Value) -> Result<Vec<Value>, JqError> {
+    let entry = |key: Value, value: &Value| {
+        let mut entry = ObjectBuilder::default();
+        entry.set("key", key);
+        entry.set("value", value.clone());
+        entry.build()
+    };
+    match value {
+        Value::Object(object) => Ok(entries(object)
+            .into_iter()
+            .map(|(key, i)| entry(string(&key), &object.entries()[i].1))
+            .collect()),
+        Value::Array(arr) => Ok(arr
+            .arr
+            .iter()
+            .enumerate()
+            .map(|(i, value)| entry(number(i), value))
+            .collect()),
+        _ => Err(JqError(format!("{} has no keys", describe(value)))),
+    }
+}
+
+fn from_entries(items: &[Value]) -> Result<Value, JqError> {
+    let mut builder = ObjectBuilder::default();
+    for item in items {
+        let Value::Object(entry) = item else {
+            return Err(JqError(format!(
+                "Cannot index {} with \"key\"",
+                type_name(item)
+            )));
+        };
+        let get = |key: &str| entry.positions(key).last().map(|&i| &entry.entries()[i].1);
+        let key = match get("key") {
+            Some(key) if !matches!(key, Value::Null) => Some(key),
+            _ => ["k", "name", "Name", "K", "Key"]
+                .into_iter()
+                .filter_map(get)
+                .find(|key| truthy(key)),
+        };
+        let key = key.map_or_else(|| "null".to_string(), to_text);
+        let value = match entry.position("value") {
+            Some(_) => get("value"),
+            None => get("v"),
+        };
+        builder.set(&key, value.cloned().unwrap_or(Value::Null));
+    }
+    Ok(builder.build())
+}
+
+fn flatten(items: &[Value], out: &mut Vec<Value>) {
+    for item in items {
+        match item {
+            Value::Array(arr) => flatten(&arr.arr, out),
+            _ => out.push(item.clone()),
+        }
+    }
+}
+
+/// The items of the input array paired with their sort key (the outputs of `f` collected in an
+/// array, or the item itself without `f`), sorted by it.
+fn sorted_by<'v>(
+    input: &Output<'v>,
+    f: Option<&Expr>,
+) -> Result<Vec<(Option<Value>, Output<'v>)>, JqError> {
+    let Value::Array(arr) = input.value() else {
+        return Err(JqError(format!(
+            "{} cannot be sorted, as it is not an array",
+            describe(input.value())
+        )));
+    };
+    let mut keyed = Vec::with_capacity(arr.arr.len());
+    for i in 0..arr.arr.len() {
+        let item = child(input, i);
+        let key = match f {
+            Some(f) => Some(array(
+                eval(f, &item)?
+                    .into_iter()
+                    .map(Output::into_value)
+                    .collect(),
+            )),
+            None => None,
+        };
+        keyed.push((key, item));
+    }
+    keyed.sort_by(|(x, a), (y, b)| {
+        compare(
+            x.as_ref().unwrap_or(a.value()),
+            y.as_ref().unwrap_or(b.value()),
+        )
+    });
+    Ok(keyed)
+}
+
+/// The sorted items grouped by their keys.
+fn groups<'v>(input: &Output<'v>, f: Option<&Expr>) -> Result<Vec<Vec<Output<'v>>>, JqError> {
+    let mut groups: Vec<Vec<Output<'v>>> = Vec::new();
+    let mut last_key = None;
+    for (key, item) in sorted_by(input, f)? {
+        let key = key.unwrap_or_else(|| item.value().clone());
+        match (&last_key, groups.last_mut()) {
+            (Some(last), Some(group)) if compare(last, &key).is_eq() => group.push(item),
+            _ => groups.push(vec![item]),
+        }
+        last_key = Some(key);
+    }
+    Ok(groups)
+}
+
+fn string_arg<'v>(name: &str, arg: &Expr, input: &Output<'v>) -> Result<Vec<String>, JqError> {
+    eval(arg, input)?
+        .iter()
+        .map(|arg| match arg.value() {
+            Value::String(raw) => Ok(unescape(raw).into_owned()),
+            value => Err(JqError(format!(
+                "{name}() requires a string argument, not {}",
+                describe(value)
+            ))),
+        })
+        .collect()
+}
+
+fn input_string(name: &str, value: &Value) -> Result<String, JqError> {
+    match value {
+        Value::String(raw) => Ok(unescape(raw).into_owned()),
+        _ => Err(JqError(format!(
+            "{name}() requires a string input, not {}",
+            describe(value)
+        ))),
+    }
+}
+
+fn format(format: Format, value: &Value) -> Result<String, JqError> {
+    let (name, separator) = match format {
+        Format::Text => return Ok(to_text(value)),
+        Format::Json => return Ok(to_json(value)),
+        Format::Html => {
+            return Ok(to_text(value)
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('\'', "&#39;")
+                .replace('"', "&quot;"))
+        }
+        Format::Csv => ("csv", ","),
+        Format::Tsv => ("tsv", "\t"),
+    };
+    let Value::Array(arr) = value else {
+        return Err(JqError(format!(
+            "{} cannot be {name}-formatted, only an array can be",
+            describe(value)
+        )));
+    };
+    let fields = arr
+        .arr
+        .iter()
+        .map(|field| match field {
+            Value::Null => Ok(String::new()),
+            Value::Bool(x) => Ok(x.to_string()),
+            Value::Number(_) => Ok(to_json(field)),
+            Value::String(raw) if format == Format::Csv => {
+                Ok(format!("\"{}\"", unescape(raw).replace('"', "\"\"")))
+            }
+            Value::String(raw) => Ok(unescape(raw)
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")),
+            _ => Err(JqError(format!(
+                "{} is not valid in a {name} row",
+                describe(field)
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(fields.join(separator))
+}
+
+fn conditional<'v>(
+    branches: &[(Expr, Expr)],
+    otherwise: Option<&Expr>,
+    input: &Output<'v>,
+) -> Outputs<'v> {
+    let Some(((condition, then), rest)) = branches.split_first() else {
+        return match otherwise {
+            Some(otherwise) => eval(otherwise, input),
+            None => Ok(vec![input.clone()]),
+        };
+    };
+    let mut out = Vec::new();
+    for value in eval(condition, input)? {
+        if truthy(value.value()) {
+            out.extend(eval(then, input)?);
+        } else {
+            out.extend(conditional(rest, otherwise, input)?);
+        }
+    }
+    Ok(out)
+}
+
+fn construct_object<'v>(entries: &[(Expr, Expr)], input: &Output<'v>) -> Outputs<'v> {
+    let mut objects = vec![ObjectBuilder::default()];
+    for (key, value) in entries {
+        let keys = eval(key, input)?
+            .iter()
+            .map(|key| match key.value() {
+                Value::String(raw) => Ok(unescape(raw).into_owned()),
+                key => Err(JqError(format!(
+                    "Object keys must be strings, not {}",
+                    describe(key)
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let values = eval(value, input)?;
+        if let ([key], [value]) = (keys.as_slice(), values.as_slice()) {
+            for object in &mut objects {
+                object.set(key, value.value().clone());
+            }
+            continue;
+        }
+        // every combination of the keys and values makes an object
+        let mut next = Vec::with_capacity(objects.len() * keys.len() * values.len());
+        for object in &objects {
+            for key in &keys {
+                for value in &values {
+                    let mut object = object.clone();
+                    object.set(key, value.value().clone());
+                    next.push(object);
+                }
+            }
+        }
+        objects = next;
+    }
+    Ok(objects
+        .into_iter()
+        .map(|object| Output::Owned(object.build()))
+        .collect())
+}
+
+fn interpolate<'v>(parts: &[StringPart], input: &Output<'v>) -> Outputs<'v> {
+    let mut strings = vec![String::new()];
+    for part in parts {
+        match part {
+            StringPart::Raw(raw) => strings.iter_mut().for_each(|s| s.push_str(raw)),
+            StringPart::Interpolated(expr) => {
+                let values = eval(expr, input)?;
+                strings = values
+                    .iter()
+                    .flat_map(|value| {
+                        let text = raw_text(value.value());
+                        strings.iter().map(move |s| format!("{s}{text}"))
+                    })
+                    .collect();
+            }
+        }
+    }
+    Ok(strings
+        .into_iter()
+        .map(|s| Output::Owned(Value::String(s)))
+        .collect())
+}
+
+fn call<'v>(name: &str, args: &[Expr], input: &Output<'v>) -> Outputs<'v> {
+    let value = input.value();
+    let owned = |value: Value| Ok(vec![Output::Owned(value)]);
+    let items = || -> Result<Vec<Value>, JqError> {
+        Ok(children(input)?
+            .into_iter()
+            .map(Output::into_value)
+            .collect())
+    };
+    match (name, args) {
+        ("empty", []) => Ok(Vec::new()),
+        ("not", []) => owned(Value::Bool(!truthy(value))),
+        ("length", []) => owned(length(value)?),
+        ("utf8bytelength", []) => owned(number(input_string(name, value)?.len())),
+        ("keys", []) => owned(keys(value, true)?),
+        ("keys_unsorted", []) => owned(keys(value, false)?),
+        ("has", [key]) => eval(key, input)?
+            .iter()
+            .map(|key| Ok(Output::Owned(Value::Bool(has(value, key.value())?))))
+            .collect(),
+        ("map", [f]) => {
+            let mut out = Vec::new();
+            for item in children(input)? {
+                out.extend(eval(f, &item)?.into_iter().map(Output::into_value));
+            }
+            owned(array(out))
+        }
+        ("map_values", [f]) => {
+            // the first output of `f` replaces a value, the entries without one are removed
+            let first = |item: &Output<'v>| -> Result<Option<Value>, JqError> {
+                Ok(eval(f, item)?.into_iter().next().map(Output::into_value))
+            };
+            match value {
+                Value::Object(object) => {
+                    let mut builder = ObjectBuilder::default();
+                    for (key, i) in entries(object) {
+                        if let Some(value) = first(&child(input, i))? {
+                            builder.set(&key, value);
+                        }
+                    }
+                    owned(builder.build())
+                }
+                _ => {
+                    let mut out = Vec::new();
+                    for item in children(input)? {
+                        out.extend(first(&item)?);
+                    }
+                    owned(array(out))
+                }
+            }
+        }
+        ("select", [f]) => Ok(eval(f, input)?
+            .iter()
+            .filter(|condition| truthy(condition.value()))
+            .map(|_| input.clone())
+            .collect()),
+        ("recurse", []) => eval(&Expr::Recurse, input),
+        ("to_entries", []) => owned(array(to_entries(value)?)),
+        ("from_entries", []) => owned(from_entries(&items()?)?),
+        ("with_entries", [f]) => {
+            let mut out = Vec::new();
+            for entry in to_entries(value)? {
+                out.extend(
+                    eval(f, &Output::Owned(entry))?
+                        .into_iter()
+                        .map(Output::into_value),
+                );
+            }
+            owned(from_entries(&out)?)
+        }
+        ("add", []) => {
+            if matches!(value, Value::Null) {
+                return owned(Value::Null);
+            }
+            let mut sum = Value::Null;
+            for item in items()? {
+                sum = add(&sum, &item)?;
+            }
+            owned(sum)
+        }
+        ("any", []) => owned(Value::Bool(items()?.iter().any(truthy))),
+        ("all", []) => owned(Value::Bool(items()?.iter().all(truthy))),
+        ("flatten", []) => {
+            let Value::Array(arr) = value else {
+                return Err(JqError(format!("Cannot flatten {}", describe(value))));
+            };
+            let mut out = Vec::new();
+            flatten(&arr.arr, &mut out);
+            owned(array(out))
+        }
+        ("range", [to]) => {
+            let from = Expr::Literal(number(0));
+            call(name, &[from, to.clone()], input)
+        }
+        ("range", [from, to]) => {
+            let mut out = Vec::new();
+            for to in eval(to, input)? {
+                for from in eval(from, input)? {
+                    let (Value::Number(from), Value::Number(to)) = (from.value(), to.value())
+                    else {
+                        return Err(JqError("Range bounds must be numeric".to_string()));
+                    };
+                    let (mut x, to) = (from.as_f64(), to.as_f64());
+                    while x < to {
+                        out.push(Output::Owned(Value::Number(Number::from_f64(x))));
+                        x += 1.0;
+                    }
+                }
+            }
+            Ok(out)
+        }
+        ("floor" | "sqrt", []) => {
+            let Value::Number(n) = value else {
+                return Err(JqError(format!("{} number required", describe(value))));
+            };
+            let x = n.as_f64();
+            owned(Value::Number(Number::from_f64(match name {
+                "floor" => x.floor(),
+                _ => x.sqrt(),
+            })))
+        }
+        ("tostring", []) => owned(string(&to_text(value))),
+        ("tonumber", []) => match value {
+            Value::Number(_) => owned(value.clone()),
+            Value::String(raw) => match Number::parse(unescape(raw).trim()) {
+                Some(n) => owned(Value::Number(n)),
+                None => Err(JqError(format!(
+                    "Cannot parse '{}' as a number",
+                    unescape(raw)
+                ))),
+            },
+            _ => Err(JqError(format!(
+                "{} cannot be parsed as a number",
+                describe(value)
+            ))),
+        },
+        ("tojson", []) => owned(string(&to_json(value))),
+        ("fromjson", []) => {
+            let text = input_string(name, value)?;
+            match parse(&text) {
+                Ok(parsed) => owned(parsed),
+                Err(err) => Err(JqError(format!("{err} (while parsing '{text}')"))),
+            }
+        }
+        ("type", []) => owned(string(type_name(value))),
+        ("sort", []) => owned(array(
+            sorted_by(input, None)?
+                .into_iter()
+                .map(|(_, item)| item.into_value())
+                .collect(),
+        )),
+        ("sort_by", [f]) => owned(array(
+            sorted_by(input, Some(f))?
+                .into_iter()
+                .map(|(_, item)| item.into_value())
+                .collect(),
+        )),
+        ("group_by", [f]) => owned(array(
+            groups(input, Some(f))?
+                .into_iter()
+                .map(|group| array(group.into_iter().map(Output::into_value).collect()))
+                .collect(),
+        )),
+        ("unique", []) | ("unique_by", [_]) => owned(array(
+            groups(input, args.first())?
+                .into_iter()
+                .filter_map(|group| group.into_iter().next())
+                .map(Output::into_value)
+                .collect(),
+        )),
+        ("min" | "max", []) | ("min_by" | "max_by", [_]) => {
+            let sorted = sorted_by(input, args.first())?
+                .into_iter()
+                .map(|(_, item)| item);
+            let extreme = match name {
+                "min" | "min_by" => sorted.into_iter().next(),
+                _ => sorted.last(),
+            };
+            Ok(vec![extreme.unwrap_or(Output::Owned(Value::Null))])
+        }
+        ("reverse", []) => match value {
+            Value::Null => owned(array(Vec::new())),
+            Value::String(raw) => owned(string(&unescape(raw).chars().rev().collect::<String>())),
+            Value::Array(arr) => owned(array(arr.arr.iter().rev().cloned().collect())),
+            _ => Err(JqError(format!("Cannot reverse {}", describe(value)))),
+        },
+        ("first", []) => Ok(vec![index(input, &number(0))?]),
+        ("last", []) => Ok(vec![index(input, &Value::Number(Number::from(-1)))?]),
+        ("startswith" | "endswith", [arg]) => {
+            let mut out = Vec::new();
+            for affix in string_arg(name, arg, input)? {
+                let s = input_string(name, value)?;
+                out.push(Output::Owned(Value::Bool(match name {
+                    "startswith" => s.starts_with(&affix),
+                    _ => s.ends_with(&affix),
+                })));
+            }
+            Ok(out)
+        }
+        ("ltrimstr" | "rtrimstr", [arg]) => {
+            let mut out = Vec::new();
+            for affix in eval(arg, input)? {
+                // only strings are trimmed, and only by strings
+                let (Value::String(raw), Value::String(affix)) = (value, affix.value()) else {
+                    out.push(input.clone());
+                    continue;
+                };
+                let (s, affix) = (unescape(raw), unescape(affix));
+                out.push(Output::Owned(string(match name {
+                    "ltrimstr" => s.strip_prefix(&*affix).unwrap_or(&s),
+                    _ => s.strip_suffix(&*affix).unwrap_or(&s),
+                })));
+            }
+            Ok(out)
+        }
+        ("join", [separator]) => {
+            let items = items()?;
+            let mut out = Vec::new();
+            for separator in string_arg(name, separator, input)? {
+                let parts = items
+                    .iter()
+                    .map(|item| match item {
+                        Value::Null => Ok(String::new()),
+                        Value::Bool(_) | Value::Number(_) | Value::String(_) => Ok(to_text(item)),
+                        _ => Err(JqError(format!("Cannot join with {}", type_name(item)))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                out.push(Output::Owned(string(&parts.join(&separator))));
+            }
+            Ok(out)
+        }
+        ("split", [separator]) => {
+            let s = input_string(name, value)?;
+            Ok(string_arg(name, separator, input)?
+                .iter()
+                .map(|separator| Output::Owned(split(&escape(&s), &escape(separator))))
+                .collect())
+        }
+        ("test", [pattern]) => {
+            let s = input_string(name, value)?;
+            let mut out = Vec::new();
+            for pattern in string_arg(name, pattern, input)? {
+                let regex = Regex::new(&pattern)
+                    .map_err(|err| JqError(format!("{pattern} is not a valid regex: {err}")))?;
+                out.push(Output::Owned(Value::Bool(regex.is_match(&s))));
+            }
+            Ok(out)
+        }
+        ("ascii_downcase", []) => owned(string(&input_string(name, value)?.to_ascii_lowercase())),
+        ("ascii_upcase", []) => owned(string(&input_string(name, value)?.to_ascii_uppercase())),
+        _ => unreachable!("the parser only accepts the builtins"),
+    }
+}
+
+/// Every output of `expr` for `input`. Outputs that are values of the document keep their path.
+pub(super) fn eval<'v>(expr: &Expr, input: &Output<'v>) -> Outputs<'v> {
+    if is_cancelled() {
+        return Err(JqError("the query was cancelled".to_string()));
+    }
+    match expr {
+        Expr::Identity => Ok(vec![input.clone()]),
+        Expr::Recurse => {
+            let mut out = Vec::new();
+            recurse(input, &mut out);
+            Ok(out)
+        }
+        Expr::Literal(value) => Ok(vec![Output::Owned(value.clone())]),
+        Expr::String(parts) => interpolate(parts, input),
+        Expr::Format(f) => Ok(vec![Output::Owned(string(&format(*f, input.value())?))]),
+        Expr::Index(target, key) => {
+            let mut out = Vec::new();
+            for target in eval(target, input)? {
+                for key in eval(key, input)? {
+                    out.push(index(&target, key.value())?);
+                }
+            }
+            Ok(out)
+        }
+        Expr::Slice(target, from, to) => {
+            let bounds = |bound: &Option<Box<Expr>>| match bound {
+                Some(bound) => eval(bound, input),
+                None => Ok(vec![Output::Owned(Value::Null)]),
+            };
+            let (from, to) = (bounds(from)?, bounds(to)?);
+            let mut out = Vec::new();
+            for target in eval(target, input)? {
+                for to in &to {
+                    for from in &from {
+                        out.push(slice(&target, from.value(), to.value())?);
+                    }
+                }
+            }
+            Ok(out)
+        }
+        Expr::Iterate(target) => {
+            let mut out = Vec::new();
+            for target in eval(target, input)? {
+                out.extend(children(&target)?);
+            }
+            Ok(out)
+        }
+        Expr::Try(expr) => Ok(eval(expr, input).unwrap_or_default()),
+        Expr::Pipe(lhs, rhs) => {
+            let mut out = Vec::new();
+            for value in eval(lhs, input)? {
+                out.extend(eval(rhs, &value)?);
+            }
+            Ok(out)
+        }
+        Expr::Comma(lhs, rhs) => {
+            let mut out = eval(lhs, input)?;
+            out.extend(eval(rhs, input)?);
+            Ok(out)
+        }
+        Expr::Alternative(lhs, rhs) => {
+            let values = eval(lhs, input)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|value| truthy(value.value()))
+                .collect::<Vec<_>>();
+            if values.is_empty() {
+                return eval(rhs, input);
+            }
+            Ok(values)
+        }
+        Expr::Binary(lhs, op, rhs) => {
+            // like jq, the left side varies the fastest
+            let rhs = eval(rhs, input)?;
+            let lhs = eval(lhs, input)?;
+            let mut out = Vec::with_capacity(lhs.len() * rhs.len());
+            for b in &rhs {
+                for a in &lhs {
+                    out.push(Output::Owned(binary(a.value(), *op, b.value())?));
+                }
+            }
+            Ok(out)
+        }
+        Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+            let is_and = matches!(expr, Expr::And(..));
+            let mut out = Vec::new();
+            for a in eval(lhs, input)? {
+                // the right side is only evaluated when it decides the result
+                if truthy(a.value()) != is_and {
+                    out.push(Output::Owned(Value::Bool(!is_and)));
+                    continue;
+                }
+                for b in eval(rhs, input)? {
+                    out.push(Output::Owned(Value::Bool(truthy(b.value()))));
+                }
+            }
+            Ok(out)
+        }
+        Expr::Negate(expr) => eval(expr, input)?
+            .iter()
+            .map(|value| match value.value() {
+                Value::Number(n) => Ok(Output::Owned(arithmetic(
+                    &Number::from(0),
+                    n,
+                    i128::checked_sub,
+                    |x, y| x - y,
+                ))),
+                value => Err(JqError(format!("{} cannot be negated", describe(value)))),
+            })
+            .collect(),
+        Expr::Array(None) => Ok(vec![Output::Owned(array(Vec::new()))]),
+        Expr::Array(Some(expr)) => Ok(vec![Output::Owned(array(
+            eval(expr, input)?
+                .into_iter()
+                .map(Output::into_value)
+                .collect(),
+        ))]),
+        Expr::Object(entries) => construct_object(entries, input),
+        Expr::If(branches, otherwise) => conditional(branches, otherwise.as_deref(), input),
+        Expr::Call(name, args) => call(name, args, input),
+    }
+}
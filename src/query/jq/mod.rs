@@ -0,0 +1,131 @@
+//! A subset of jq, for queries that build new values instead of only selecting them.
+
+mod eval;
+mod parser;
+
+use std::str::FromStr;
+
+use crate::value::Value;
+
+use super::{path::PathParser, Output, QueryError};
+
+/// An error while running a [`Program`], like `Cannot index number with "a"`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{0}")]
+pub struct JqError(pub String);
+
+/// A jq program. The supported subset:
+///
+/// - paths: `.`, `.a`, `."a"`, `.[0]`, `.[-1]`, `.[2:4]`, `.[]`, `..`, `?` to ignore errors
+/// - `|`, `,`, `//`, `and`, `or`, `if ... then ... elif ... else ... end`
+/// - `+`, `-`, `*`, `/`, `%` and the comparisons, values are ordered like in jq
+/// - literals, strings with `\(...)` interpolations, `[...]` and `{a: .x, "b": 1, (.k): .v, c}`
+/// - the builtins `map`, `select`, `keys`, `length`, `to_entries`, `from_entries`,
+///   `with_entries`, `sort_by`, `group_by`, `unique_by`, `add`, `join` and more, see
+///   `BUILTINS` in the parser
+/// - the formats `@csv`, `@tsv`, `@json`, `@text` and `@html`
+///
+/// Variables, function definitions, `reduce` and assignments aren't supported.
+#[derive(Debug, Clone)]
+pub struct Program {
+    expr: parser::Expr,
+}
+
+impl FromStr for Program {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let expr = PathParser { query, pos: 0 }.program()?;
+        Ok(Self { expr })
+    }
+}
+
+impl Program {
+    /// Every output of the program for the root `value`.
+    pub fn run<'v>(&self, value: &'v Value) -> Result<Vec<Output<'v>>, JqError> {
+        eval::eval(&self.expr, &Output::Ref(Vec::new(), value))
+    }
+}
+
+/// `value` as JSON on a single line, like jq prints it with `-c`.
+pub fn to_json(value: &Value) -> String {
+    eval::to_json(value)
+}
+
+/// The first `width` characters of [`to_json`], without writing the rest of the value.
+pub fn to_json_preview(value: &Value, width: usize) -> String {
+    eval::to_json_preview(value, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::parse;
+
+    /// Runs the cases of `manual.test`: blank line separated blocks of a program, its input and
+    /// its outputs, one per line. `%%FAIL` before the program expects an error.
+    #[test]
+    fn manual() {
+        let cases = include_str!("manual.test");
+        for case in cases.split("\n\n") {
+            let mut lines = case
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with('#'));
+            let Some(mut program) = lines.next() else {
+                continue;
+            };
+            let fails = program == "%%FAIL";
+            if fails {
+                program = lines.next().unwrap();
+            }
+            let input = parse(lines.next().unwrap()).unwrap();
+            let result = program
+                .parse::<Program>()
+                .unwrap_or_else(|err| panic!("{program}: {err}"))
+                .run(&input);
+            if fails {
+                assert!(result.is_err(), "{program} should fail");
+                continue;
+            }
+
+            let outputs = result.unwrap_or_else(|err| panic!("{program}: {err}"));
+            let outputs = outputs
+                .iter()
+                .map(|output| to_json(output.value()))
+                .collect::<Vec<_>>();
+            let expected = lines
+                .map(|line| to_json(&parse(line).unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(outputs, expected, "{program}");
+        }
+    }
+
+    #[test]
+    fn paths() {
+        let input = parse(r#"{"a": [{"b": 1}, {"b": 2}], "c": 3}"#).unwrap();
+        let program: Program = ".a[] | select(.b > 1) | .b, (.c + 1)".parse().unwrap();
+        let outputs = program.run(&input).unwrap();
+        assert_eq!(outputs[0].path(), Some([0, 1, 0].as_slice()));
+        assert_eq!(outputs[1].path(), None);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |query: &str| query.parse::<Program>().unwrap_err().to_string();
+        assert_eq!(error(".a |"), "unexpected end of query at column 5");
+        assert_eq!(error("mapp(.)"), "unknown function `mapp` at column 1");
+        assert_eq!(error("map"), "`map` takes 1 argument at column 1");
+        assert_eq!(error("@yaml"), "unknown function `@yaml` at column 1");
+
+        let input = parse(r#"{"a": 1}"#).unwrap();
+        let run = |query: &str| {
+            let program: Program = query.parse().unwrap();
+            program.run(&input).unwrap_err().to_string()
+        };
+        assert_eq!(run(".a.b"), "Cannot index number with \"b\"");
+        assert_eq!(
+            run(". + 1"),
+            "object ({\"a\":1}) and number (1) cannot be added"
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use crate::value::{escape, Number, Value};
+
+use super::super::{path::PathParser, QueryError, QueryErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A string format, `@csv` converts an array to a line of CSV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Format {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+    Html,
+}
+
+/// A part of a string literal, strings are kept raw.
+#[derive(Debug, Clone)]
+pub(super) enum StringPart {
+    Raw(String),
+    /// `\(...)`, the outputs are inserted as text.
+    Interpolated(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Expr {
+    /// `.`
+    Identity,
+    /// `..`
+    Recurse,
+    Literal(Value),
+    String(Vec<StringPart>),
+    Format(Format),
+    /// `target[index]`, `.a` is an index by the string `"a"`.
+    Index(Box<Expr>, Box<Expr>),
+    /// `target[from:to]`
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    /// `target[]`
+    Iterate(Box<Expr>),
+    /// `expr?`
+    Try(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    /// `a // b`
+    Alternative(Box<Expr>, Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    /// `[expr]`, `[]` when there is no expression.
+    Array(Option<Box<Expr>>),
+    /// `{key: value}`, the keys are expressions that must produce strings.
+    Object(Vec<(Expr, Expr)>),
+    /// The `if` and `elif` branches, followed by the `else` branch.
+    If(Vec<(Expr, Expr)>, Option<Box<Expr>>),
+    Call(&'static str, Vec<Expr>),
+}
+
+/// The builtin functions and the number of arguments they take, the arguments are separated by
+/// `;`.
+const BUILTINS: &[(&str, usize)] = &[
+    ("empty", 0),
+    ("not", 0),
+    ("length", 0),
+    ("utf8bytelength", 0),
+    ("keys", 0),
+    ("keys_unsorted", 0),
+    ("has", 1),
+    ("map", 1),
+    ("map_values", 1),
+    ("select", 1),
+    ("recurse", 0),
+    ("to_entries", 0),
+    ("from_entries", 0),
+    ("with_entries", 1),
+    ("add", 0),
+    ("any", 0),
+    ("all", 0),
+    ("flatten", 0),
+    ("range", 1),
+    ("range", 2),
+    ("floor", 0),
+    ("sqrt", 0),
+    ("tostring", 0),
+    ("tonumber", 0),
+    ("tojson", 0),
+    ("fromjson", 0),
+    ("type", 0),
+    ("sort", 0),
+    ("sort_by", 1),
+    ("group_by", 1),
+    ("unique", 0),
+    ("unique_by", 1),
+    ("min", 0),
+    ("max", 0),
+    ("min_by", 1),
+    ("max_by", 1),
+    ("reverse", 0),
+    ("first", 0),
+    ("last", 0),
+    ("startswith", 1),
+    ("endswith", 1),
+    ("ltrimstr", 1),
+    ("rtrimstr", 1),
+    ("join", 1),
+    ("split", 1),
+    ("test", 1),
+    ("ascii_downcase", 0),
+    ("ascii_upcase", 0),
+];
+
+/// Words that can't be used as function names.
+const KEYWORDS: &[&str] = &["and", "or", "if", "then", "elif", "else", "end"];
+
+fn index(target: Expr, key: &str) -> Expr {
+    Expr::Index(
+        Box::new(target),
+        Box::new(Expr::Literal(Value::String(escape(key).into_owned()))),
+    )
+}
+
+impl PathParser<'_> {
+    /// A whole jq program.
+    pub(super) fn program(mut self) -> Result<Expr, QueryError> {
+        let expr = self.pipe(true)?;
+        self.skip_whitespace();
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(expr)
+    }
+
+    /// Consumes `token` when it comes next, words must not be followed by more of the word.
+    fn token(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let Some(after) = self.rest().strip_prefix(token) else {
+            return false;
+        };
+        let is_word = token.starts_with(|c: char| c.is_alphabetic());
+        if is_word && after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return false;
+        }
+        self.pos += token.len();
+        true
+    }
+
+    fn expect_token(&mut self, token: &str) -> Result<(), QueryError> {
+        if !self.token(token) {
+            return Err(self.unexpected());
+        }
+        Ok(())
+    }
+
+    /// The lowest precedence, object values can't contain `,` without parentheses.
+    fn pipe(&mut self, comma: bool) -> Result<Expr, QueryError> {
+        let lhs = if comma {
+            self.comma()?
+        } else {
+            self.alternative()?
+        };
+        if self.token("|") {
+            let rhs = self.pipe(comma)?;
+            return Ok(Expr::Pipe(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn comma(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.alternative()?;
+        while self.token(",") {
+            let rhs = self.alternative()?;
+            lhs = Expr::Comma(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn alternative(&mut self) -> Result<Expr, QueryError> {
+        let lhs = self.or()?;
+        if self.token("//") {
+            let rhs = self.alternative()?;
+            return Ok(Expr::Alternative(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.conjunction()?;
+        while self.token("or") {
+            let rhs = self.conjunction()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn conjunction(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.comparison()?;
+        while self.token("and") {
+            let rhs = self.comparison()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, QueryError> {
+        let lhs = self.additive()?;
+        let op = [
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.token(token));
+        match op {
+            Some((_, op)) => {
+                let rhs = self.additive()?;
+                Ok(Expr::Binary(Box::new(lhs), op, Box::new(rhs)))
+            }
+            None => Ok(lhs),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = if self.token("+") {
+                BinaryOp::Add
+            } else if self.token("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.multiplicative()?;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.negation()?;
+        loop {
+            self.skip_whitespace();
+            let op = if self.token("*") {
+                BinaryOp::Mul
+            } else if self.rest().starts_with('/') && !self.rest().starts_with("//") {
+                self.pos += 1;
+                BinaryOp::Div
+            } else if self.token("%") {
+                BinaryOp::Mod
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.negation()?;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        }
+    }
+
+    fn negation(&mut self) -> Result<Expr, QueryError> {
+        if self.token("-") {
+            return Ok(Expr::Negate(Box::new(self.negation()?)));
+        }
+        self.postfix()
+    }
+
+    /// A term followed by `.key`, `[...]` and `?`.
+    fn postfix(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.term()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('?') => {
+                    self.pos += 1;
+                    expr = Expr::Try(Box::new(expr));
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    expr = self.bracket_suffix(expr)?;
+                }
+                Some('.') if !self.rest().starts_with("..") => {
+                    self.pos += 1;
+                    expr = self.field(expr)?;
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /// What follows a `.` after a term: a key, a quoted key or brackets.
+    fn field(&mut self, target: Expr) -> Result<Expr, QueryError> {
+        match self.peek() {
+            Some('"') => {
+                let key = self.string_literal()?;
+                Ok(Expr::Index(Box::new(target), Box::new(key)))
+            }
+            Some('[') => {
+                self.pos += 1;
+                self.bracket_suffix(target)
+            }
+            _ => match self.identifier() {
+                Some(key) => Ok(index(target, &key)),
+                None => Err(self.unexpected()),
+            },
+        }
+    }
+
+    /// The content of `[...]` after a term, after the opening bracket.
+    fn bracket_suffix(&mut self, target: Expr) -> Result<Expr, QueryError> {
+        let target = Box::new(target);
+        if self.token("]") {
+            return Ok(Expr::Iterate(target));
+        }
+        if self.token(":") {
+            let to = self.pipe(true)?;
+            self.expect_token("]")?;
+            return Ok(Expr::Slice(target, None, Some(Box::new(to))));
+        }
+        let from = self.pipe(true)?;
+        if self.token(":") {
+            let to = if self.token("]") {
+                return Ok(Expr::Slice(target, Some(Box::new(from)), None));
+            } else {
+                self.pipe(true)?
+            };
+            self.expect_token("]")?;
+            return Ok(Expr::Slice(
+                target,
+                Some(Box::new(from)),
+                Some(Box::new(to)),
+            ));
+        }
+        self.expect_token("]")?;
+        Ok(Expr::Index(target, Box::new(from)))
+    }
+
+    fn term(&mut self) -> Result<Expr, QueryError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            None => Err(self.unexpected()),
+            Some('.') if self.rest().starts_with("..") => {
+                self.pos += 2;
+                Ok(Expr::Recurse)
+            }
+            Some('.') if !self.rest()[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                self.pos += 1;
+                match self.peek() {
+                    Some('"') => self.field(Expr::Identity),
+                    Some(c) if c.is_alphabetic() || c == '_' => self.field(Expr::Identity),
+                    _ => Ok(Expr::Identity),
+                }
+            }
+            Some(c) if c == '.' || c.is_ascii_digit() => self.number(),
+            Some('"') => self.string_literal(),
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.pipe(true)?;
+                self.expect_token(")")?;
+                Ok(expr)
+            }
+            Some('[') => {
+                self.pos += 1;
+                if self.token("]") {
+                    return Ok(Expr::Array(None));
+                }
+                let expr = self.pipe(true)?;
+                self.expect_token("]")?;
+                Ok(Expr::Array(Some(Box::new(expr))))
+            }
+            Some('{') => {
+                self.pos += 1;
+                self.object()
+            }
+            Some('@') => {
+                self.pos += 1;
+                let name = self.identifier().unwrap_or_default();
+                let format = match name.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    "tsv" => Format::Tsv,
+                    "html" => Format::Html,
+                    _ => {
+                        return Err(QueryError::new(
+                            QueryErrorKind::UnknownFunction(format!("@{name}")),
+                            start,
+                        ))
+                    }
+                };
+                Ok(Expr::Format(format))
+            }
+            Some(_) => {
+                let Some(name) = self.identifier() else {
+                    return Err(self.unexpected());
+                };
+                match name.as_str() {
+                    "true" => Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => Ok(Expr::Literal(Value::Bool(false))),
+                    "null" => Ok(Expr::Literal(Value::Null)),
+                    "if" => self.conditional(),
+                    _ if KEYWORDS.contains(&name.as_str()) => {
+                        self.pos = start;
+                        Err(self.unexpected())
+                    }
+                    _ => self.call(name, start),
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, QueryError> {
+        let start = self.pos;
+        let mut len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(self.rest().len());
+        // an exponent, the sign is part of it
+        let exponent = &self.rest()[len..];
+        if exponent.starts_with(['e', 'E']) {
+            let sign = usize::from(exponent[1..].starts_with(['+', '-']));
+            let digits = exponent[1 + sign..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(exponent.len() - 1 - sign);
+            if digits > 0 {
+                len += 1 + sign + digits;
+            }
+        }
+        self.pos += len;
+        Number::parse_lenient(&self.query[start..self.pos])
+            .map(|n| Expr::Literal(Value::Number(n)))
+            .ok_or_else(|| QueryError::new(QueryErrorKind::InvalidNumber, start))
+    }
+
+    /// A string in double quotes, with JSON escape sequences and `\(...)` interpolations.
+    fn string_literal(&mut self) -> Result<Expr, QueryError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut parts = Vec::new();
+        let mut raw = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(QueryError::new(QueryErrorKind::UnclosedString, start));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => break,
+                '\\' if self.peek() == Some('(') => {
+                    self.pos += 1;
+                    if !raw.is_empty() {
+                        parts.push(StringPart::Raw(std::mem::take(&mut raw)));
+                    }
+                    parts.push(StringPart::Interpolated(self.pipe(true)?));
+                    self.expect_token(")")?;
+                }
+                '\\' => {
+                    raw.push(c);
+                    if let Some(c) = self.peek() {
+                        raw.push(c);
+                        self.pos += c.len_utf8();
+                    }
+                }
+                c => raw.push(c),
+            }
+        }
+
+        match parts.is_empty() {
+            true => Ok(Expr::Literal(Value::String(raw))),
+            false => {
+                if !raw.is_empty() {
+                    parts.push(StringPart::Raw(raw));
+                }
+                Ok(Expr::String(parts))
+            }
+        }
+    }
+
+    /// The entries of `{...}`, after the opening brace.
+    fn object(&mut self) -> Result<Expr, QueryError> {
+        let mut entries = Vec::new();
+        if self.token("}") {
+            return Ok(Expr::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') => self.string_literal()?,
+                Some('(') => {
+                    self.pos += 1;
+                    let key = self.pipe(true)?;
+                    self.expect_token(")")?;
+                    key
+                }
+                _ => match self.identifier() {
+                    Some(key) => Expr::Literal(Value::String(key)),
+                    None => return Err(self.unexpected()),
+                },
+            };
+            // `{a}` is short for `{a: .a}`
+            let value = if self.token(":") {
+                self.pipe(false)?
+            } else {
+                Expr::Index(Box::new(Expr::Identity), Box::new(key.clone()))
+            };
+            entries.push((key, value));
+
+            if self.token("}") {
+                return Ok(Expr::Object(entries));
+            }
+            self.expect_token(",")?;
+        }
+    }
+
+    /// `if c then a elif d then b else e end`, after the `if`.
+    fn conditional(&mut self) -> Result<Expr, QueryError> {
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.pipe(true)?;
+            self.expect_token("then")?;
+            let then = self.pipe(true)?;
+            branches.push((condition, then));
+            if !self.token("elif") {
+                break;
+            }
+        }
+        let otherwise = match self.token("else") {
+            true => Some(Box::new(self.pipe(true)?)),
+            false => None,
+        };
+        self.expect_token("end")?;
+        Ok(Expr::If(branches, otherwise))
+    }
+
+    /// A call of the builtin `name`, after its name.
+    fn call(&mut self, name: String, start: usize) -> Result<Expr, QueryError> {
+        let mut args = Vec::new();
+        if self.token("(") {
+            loop {
+                args.push(self.pipe(true)?);
+                if self.token(")") {
+                    break;
+                }
+                self.expect_token(";")?;
+            }
+        }
+
+        if let Some(&(builtin, _)) = BUILTINS
+            .iter()
+            .find(|&&(builtin, arity)| builtin == name && arity == args.len())
+        {
+            return Ok(Expr::Call(builtin, args));
+        }
+        let arities = BUILTINS
+            .iter()
+            .filter(|(builtin, _)| *builtin == name)
+            .map(|(_, arity)| *arity)
+            .collect::<Vec<_>>();
+        let kind = match arities.as_slice() {
+            [] => QueryErrorKind::UnknownFunction(name),
+            [0] => QueryErrorKind::Arguments(name, "no arguments"),
+            [1] => QueryErrorKind::Arguments(name, "1 argument"),
+            _ => QueryErrorKind::Arguments(name, "1 or 2 arguments"),
+        };
+        Err(QueryError::new(kind, start))
+    }
+}
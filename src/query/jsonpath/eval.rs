@@ -0,0 +1,294 @@
+use std::{borrow::Cow, cell::RefCell, collections::HashMap};
+
+use regex::Regex;
+
+use crate::{
+    query::task::is_cancelled,
+    value::{unescape, Number, Value},
+};
+
+use super::parser::{Comparable, CompareOp, Function, LogicalExpr, Query, Segment, Selector};
+
+/// A selected value and its path.
+pub(super) type Node<'v> = (Vec<usize>, &'v Value);
+
+pub(super) struct Context<'v> {
+    pub root: &'v Value,
+    /// The compiled patterns of `match` and `search`, `None` when they aren't valid.
+    regexes: RefCell<HashMap<(String, bool), Option<Regex>>>,
+}
+
+impl<'v> Context<'v> {
+    pub fn new(root: &'v Value) -> Self {
+        Self {
+            root,
+            regexes: RefCell::default(),
+        }
+    }
+}
+
+/// The nodes selected by `segments` from `start`, in the order of the RFC.
+pub(super) fn select<'v>(cx: &Context<'v>, segments: &[Segment], start: Node<'v>) -> Vec<Node<'v>> {
+    let mut nodes = vec![start];
+    for segment in segments {
+        let mut next = Vec::new();
+        for node in &nodes {
+            if is_cancelled() {
+                return Vec::new();
+            }
+            match segment {
+                Segment::Child(selectors) => {
+                    for selector in selectors {
+                        apply(cx, selector, node, &mut next);
+                    }
+                }
+                Segment::Descendant(selectors) => descend(cx, selectors, node, &mut next),
+            }
+        }
+        nodes = next;
+    }
+    nodes
+}
+
+/// Applies the selectors to `node` and then to each of its descendants, parents first.
+fn descend<'v>(cx: &Context<'v>, selectors: &[Selector], node: &Node<'v>, out: &mut Vec<Node<'v>>) {
+    if is_cancelled() {
+        return;
+    }
+    for selector in selectors {
+        apply(cx, selector, node, out);
+    }
+    for child in children(node) {
+        descend(cx, selectors, &child, out);
+    }
+}
+
+fn child<'v>((path, _): &Node<'v>, i: usize, value: &'v Value) -> Node<'v> {
+    let mut path = path.clone();
+    path.push(i);
+    (path, value)
+}
+
+/// The elements of an array or the values of an object, unloaded containers have none.
+fn children<'v>(node: &Node<'v>) -> Vec<Node<'v>> {
+    match node.1 {
+        Value::Array(arr) => arr
+            .arr
+            .iter()
+            .enumerate()
+            .map(|(i, value)| child(node, i, value))
+            .collect(),
+        Value::Object(object) => object
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(i, (_, value))| child(node, i, value))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn apply<'v>(cx: &Context<'v>, selector: &Selector, node: &Node<'v>, out: &mut Vec<Node<'v>>) {
+    match (selector, node.1) {
+        (Selector::Name(name), Value::Object(object)) => {
+            for i in object.positions(name) {
+                out.push(child(node, i, &object.entries()[i].1));
+            }
+        }
+        (Selector::Wildcard, _) => out.extend(children(node)),
+        (Selector::Index(index), Value::Array(arr)) => {
+            let len = arr.arr.len() as i64;
+            let i = if *index < 0 { len + index } else { *index };
+            if (0..len).contains(&i) {
+                out.push(child(node, i as usize, &arr.arr[i as usize]));
+            }
+        }
+        (Selector::Slice(start, end, step), Value::Array(arr)) => {
+            for i in slice(arr.arr.len() as i64, *start, *end, *step) {
+                out.push(child(node, i, &arr.arr[i]));
+            }
+        }
+        (Selector::Filter(expr), _) => {
+            out.extend(
+                children(node)
+                    .into_iter()
+                    .filter(|(_, value)| test(cx, expr, value)),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// The indices of a slice of an array of `len` elements, like section 2.3.4.2.2 of the RFC.
+fn slice(len: i64, start: Option<i64>, end: Option<i64>, step: Option<i64>) -> Vec<usize> {
+    let step = step.unwrap_or(1);
+    let normalize = |i: i64| if i < 0 { len + i } else { i };
+    let mut indices = Vec::new();
+    if step > 0 {
+        let lower = normalize(start.unwrap_or(0)).clamp(0, len);
+        let upper = normalize(end.unwrap_or(len)).clamp(0, len);
+        let mut i = lower;
+        while i < upper {
+            indices.push(i as usize);
+            i += step;
+        }
+    } else if step < 0 {
+        let upper = normalize(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+        let lower = normalize(end.unwrap_or(-len - 1)).clamp(-1, len - 1);
+        let mut i = upper;
+        while lower < i {
+            indices.push(i as usize);
+            i += step;
+        }
+    }
+    indices
+}
+
+fn query<'v>(cx: &Context<'v>, query: &Query, current: &'v Value) -> Vec<Node<'v>> {
+    let start = if query.absolute { cx.root } else { current };
+    select(cx, &query.segments, (Vec::new(), start))
+}
+
+fn test<'v>(cx: &Context<'v>, expr: &LogicalExpr, current: &'v Value) -> bool {
+    match expr {
+        LogicalExpr::Or(exprs) => exprs.iter().any(|expr| test(cx, expr, current)),
+        LogicalExpr::And(exprs) => exprs.iter().all(|expr| test(cx, expr, current)),
+        LogicalExpr::Not(expr) => !test(cx, expr, current),
+        LogicalExpr::Compare(lhs, op, rhs) => compare(
+            *op,
+            comparable(cx, lhs, current).as_deref(),
+            comparable(cx, rhs, current).as_deref(),
+        ),
+        LogicalExpr::Exists(q) => !query(cx, q, current).is_empty(),
+        LogicalExpr::Match {
+            full,
+            value,
+            pattern,
+        } => {
+            let value = comparable(cx, value, current);
+            let pattern = comparable(cx, pattern, current);
+            let (Some(Value::String(value)), Some(Value::String(pattern))) =
+                (value.as_deref(), pattern.as_deref())
+            else {
+                return false;
+            };
+            let mut regexes = cx.regexes.borrow_mut();
+            let regex = regexes
+                .entry((pattern.clone(), *full))
+                .or_insert_with(|| Regex::new(&translate(&unescape(pattern), *full)).ok());
+            regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(&unescape(value)))
+        }
+    }
+}
+
+/// The value of a side of a comparison, `None` when a query selects nothing.
+fn comparable<'a>(
+    cx: &Context<'a>,
+    expr: &'a Comparable,
+    current: &'a Value,
+) -> Option<Cow<'a, Value>> {
+    match expr {
+        Comparable::Literal(value) => Some(Cow::Borrowed(value)),
+        Comparable::Query(q) => single(query(cx, q, current)).map(Cow::Borrowed),
+        Comparable::Function(Function::Length(arg)) => {
+            let length = match &*comparable(cx, arg, current)? {
+                Value::String(s) => unescape(s).chars().count(),
+                Value::Array(arr) => arr.arr.len(),
+                Value::Object(object) => object.len(),
+                _ => return None,
+            };
+            Some(Cow::Owned(Value::Number(Number::from(length as i64))))
+        }
+        Comparable::Function(Function::Count(q)) => {
+            let count = query(cx, q, current).len();
+            Some(Cow::Owned(Value::Number(Number::from(count as i64))))
+        }
+        Comparable::Function(Function::Value(q)) => {
+            single(query(cx, q, current)).map(Cow::Borrowed)
+        }
+    }
+}
+
+fn single<'v>(mut nodes: Vec<Node<'v>>) -> Option<&'v Value> {
+    match nodes.len() {
+        1 => nodes.pop().map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+fn compare(op: CompareOp, lhs: Option<&Value>, rhs: Option<&Value>) -> bool {
+    let equal = match (lhs, rhs) {
+        (None, None) => true,
+        (Some(lhs), Some(rhs)) => equal(lhs, rhs),
+        _ => false,
+    };
+    let less = |a: Option<&Value>, b: Option<&Value>| match (a, b) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a < b,
+        (Some(Value::String(a)), Some(Value::String(b))) => unescape(a) < unescape(b),
+        _ => false,
+    };
+    match op {
+        CompareOp::Eq => equal,
+        CompareOp::Ne => !equal,
+        CompareOp::Lt => less(lhs, rhs),
+        CompareOp::Le => less(lhs, rhs) || equal,
+        CompareOp::Gt => less(rhs, lhs),
+        CompareOp::Ge => less(rhs, lhs) || equal,
+    }
+}
+
+/// Deep equality, objects are equal when they have the same members in any order.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, Value::Null) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Number(a), Value::Number(b)) => a == b,
+        (Value::String(a), Value::String(b)) => unescape(a) == unescape(b),
+        (Value::Array(a), Value::Array(b)) => {
+            a.arr.len() == b.arr.len() && a.arr.iter().zip(&b.arr).all(|(a, b)| equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.entries()
+                    .iter()
+                    .all(|(key, a)| b.get(&unescape(key)).is_some_and(|b| equal(a, b)))
+        }
+        _ => false,
+    }
+}
+
+/// Translates an I-Regexp (RFC 9485) to the syntax of the regex crate: `.` doesn't match line
+/// breaks, and `match` must match the whole string.
+fn translate(pattern: &str, full: bool) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut in_class = false;
+    let mut escaped = false;
+    for c in pattern.chars() {
+        match c {
+            _ if escaped => {
+                out.push(c);
+                escaped = false;
+            }
+            '\\' => {
+                out.push(c);
+                escaped = true;
+            }
+            '[' if !in_class => {
+                in_class = true;
+                out.push(c);
+            }
+            ']' if in_class => {
+                in_class = false;
+                out.push(c);
+            }
+            '.' if !in_class => out.push_str("[^\\n\\r]"),
+            c => out.push(c),
+        }
+    }
+    match full {
+        true => format!("\\A(?:{out})\\z"),
+        false => out,
+    }
+}
@@ -0,0 +1,190 @@
+//! JSONPath as specified by RFC 9535, as an alternative to the [`Path`](super::Path) syntax.
+
+mod eval;
+mod parser;
+
+use std::str::FromStr;
+
+use crate::value::Value;
+
+use super::QueryError;
+
+/// A JSONPath query:
+///
+/// - `$` is the root, and `@` the current value in filters
+/// - `.name`, `['name']`, `["name"]`, `.*`, `[*]`, `[0]`, `[-1]`, `[1:5:2]` and several
+///   selectors in one bracket, `[0, 'a']`
+/// - `..name`, `..*` and `..[...]` select among every descendant
+/// - filters like `[?@.price < 10 && !@.sold]` with `==`, `!=`, `<`, `<=`, `>`, `>=`, `&&`,
+///   `||`, `!`, existence tests and the functions `length`, `count`, `match`, `search` and
+///   `value`
+///
+/// Queries are checked like the RFC requires, e.g. a query that can select several values can't
+/// be compared.
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    query: parser::Query,
+}
+
+impl FromStr for JsonPath {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let query = parser::Parser::new(query).parse()?;
+        Ok(Self { query })
+    }
+}
+
+impl JsonPath {
+    /// The selected values and their paths, in the order of the RFC's nodelists.
+    pub fn select<'v>(&self, value: &'v Value) -> Vec<(Vec<usize>, &'v Value)> {
+        let cx = eval::Context::new(value);
+        eval::select(&cx, &self.query.segments, (Vec::new(), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        query::jq::to_json,
+        value::{parse, unescape},
+    };
+
+    /// Cases of the suite that aren't run, by name, with the reason.
+    const SKIPPED: &[(&str, &str)] = &[];
+
+    /// The normalized path of RFC 9535 of the value at `path`, like `$['a'][0]`.
+    fn normalized_path(document: &Value, path: &[usize]) -> String {
+        let mut out = "$".to_string();
+        let mut value = document;
+        for &i in path {
+            if let Value::Object(object) = value {
+                out.push_str("['");
+                for c in unescape(&object.entries()[i].0).chars() {
+                    match c {
+                        '\u{8}' => out.push_str("\\b"),
+                        '\u{c}' => out.push_str("\\f"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        '\'' | '\\' => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                        c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                    }
+                }
+                out.push_str("']");
+            } else {
+                out.push_str(&format!("[{i}]"));
+            }
+            value = value.get_path(&[i]).unwrap();
+        }
+        out
+    }
+
+    /// Runs the cases of `cases.json`, written from the RFC's examples in the format of the JSONPath
+    /// compliance test suite: a selector and a document with the expected `result`, or several
+    /// possible `results` when the order of an object's members matters, and their normalized
+    /// paths. Or `invalid_selector`.
+    #[test]
+    fn cases() {
+        let suite = parse(include_str!("cases.json")).unwrap();
+        let Value::Object(suite) = suite else {
+            panic!("the suite is an object");
+        };
+        let Some(Value::Array(tests)) = suite.get("tests") else {
+            panic!("the suite has tests");
+        };
+        let mut skipped = Vec::new();
+        for test in &tests.arr {
+            let Value::Object(test) = test else {
+                panic!("a test is an object");
+            };
+            let string = |key: &str| match test.get(key) {
+                Some(Value::String(s)) => unescape(s).into_owned(),
+                _ => panic!("a test has a `{key}`"),
+            };
+            let name = string("name");
+            if SKIPPED.iter().any(|(skip, _)| *skip == name) {
+                skipped.push(name);
+                continue;
+            }
+            let selector = string("selector");
+            let query = selector.parse::<JsonPath>();
+            if test.get("invalid_selector").is_some() {
+                assert!(query.is_err(), "{name}: `{selector}` should be invalid");
+                continue;
+            }
+
+            let query = query.unwrap_or_else(|err| panic!("{name}: `{selector}`: {err}"));
+            let document = test.get("document").unwrap();
+            let (values, paths): (Vec<_>, Vec<_>) = query
+                .select(document)
+                .into_iter()
+                .map(|(path, value)| (to_json(value), normalized_path(document, &path)))
+                .unzip();
+            let list = |value: &Value| match value {
+                Value::Array(values) => values.arr.iter().map(to_json).collect::<Vec<_>>(),
+                _ => panic!("{name}: a result is an array"),
+            };
+            let strings = |value: &Value| match value {
+                Value::Array(values) => values
+                    .arr
+                    .iter()
+                    .map(|value| unescape(value.get_string().unwrap()).into_owned())
+                    .collect::<Vec<_>>(),
+                _ => panic!("{name}: the paths of a result are an array"),
+            };
+            if let Some(expected) = test.get("result") {
+                assert_eq!(values, list(expected), "{name}: `{selector}`");
+                if let Some(expected) = test.get("result_paths") {
+                    assert_eq!(paths, strings(expected), "{name}: `{selector}`");
+                }
+                continue;
+            }
+
+            let Some(Value::Array(results)) = test.get("results") else {
+                panic!("{name}: a test has a `result` or `results`");
+            };
+            let results_paths = test.get("results_paths").and_then(Value::as_array);
+            let found = results.arr.iter().enumerate().any(|(i, expected)| {
+                values == list(expected)
+                    && results_paths.is_none_or(|expected| paths == strings(&expected.arr[i]))
+            });
+            assert!(found, "{name}: `{selector}` gave {values:?} at {paths:?}");
+        }
+        // the list doesn't name cases that aren't in the suite
+        assert_eq!(skipped.len(), SKIPPED.len());
+    }
+
+    #[test]
+    fn paths() {
+        let input = parse(r#"{"a": [{"b": 1}, {"b": 2}], "c": 3}"#).unwrap();
+        let query: JsonPath = "$..b".parse().unwrap();
+        let paths = query
+            .select(&input)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [vec![0, 0, 0], vec![0, 1, 0]]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |query: &str| query.parse::<JsonPath>().unwrap_err().to_string();
+        assert_eq!(error("$.a["), "unexpected end of query at column 5");
+        assert_eq!(error("$[01]"), "invalid index at column 3");
+        assert_eq!(
+            error("$[?@.* == 1]"),
+            "a query that can select several nodes can't be compared at column 4"
+        );
+        assert_eq!(error("$[?foo(@)]"), "unknown function `foo` at column 4");
+        assert_eq!(
+            error("$[?count(1) == 1]"),
+            "the argument must be a query at column 10"
+        );
+    }
+}
@@ -0,0 +1,612 @@
+use crate::value::{escape, Number, Value};
+
+use super::super::{QueryError, QueryErrorKind};
+
+#[derive(Debug, Clone)]
+pub(super) enum Selector {
+    /// A decoded member name.
+    Name(String),
+    Wildcard,
+    Index(i64),
+    /// `start:end:step`
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Filter(Box<LogicalExpr>),
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Segment {
+    /// `[...]`, `.name` or `.*`
+    Child(Vec<Selector>),
+    /// `..[...]`, `..name` or `..*`
+    Descendant(Vec<Selector>),
+}
+
+/// A query from the root `$`, or relative to the current node `@` inside of filters.
+#[derive(Debug, Clone)]
+pub(super) struct Query {
+    pub absolute: bool,
+    pub segments: Vec<Segment>,
+}
+
+impl Query {
+    /// Whether the query selects at most one node: only names and indices, without descendants.
+    fn is_singular(&self) -> bool {
+        self.segments.iter().all(|segment| {
+            matches!(
+                segment,
+                Segment::Child(selectors)
+                    if matches!(selectors.as_slice(), [Selector::Name(_) | Selector::Index(_)])
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// The functions that produce a value.
+#[derive(Debug, Clone)]
+pub(super) enum Function {
+    Length(Box<Comparable>),
+    Count(Query),
+    Value(Query),
+}
+
+/// A side of a comparison, queries are singular.
+#[derive(Debug, Clone)]
+pub(super) enum Comparable {
+    Literal(Value),
+    Query(Query),
+    Function(Function),
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum LogicalExpr {
+    Or(Vec<LogicalExpr>),
+    And(Vec<LogicalExpr>),
+    Not(Box<LogicalExpr>),
+    Compare(Comparable, CompareOp, Comparable),
+    /// True when the query selects at least one node.
+    Exists(Query),
+    /// `match` when `full`, `search` otherwise.
+    Match {
+        full: bool,
+        value: Comparable,
+        pattern: Comparable,
+    },
+}
+
+/// What can appear where a comparable or a test is expected, before it is known which one it is.
+enum Operand {
+    Comparable(Comparable),
+    Match(LogicalExpr),
+}
+
+/// The largest index that is exact in every JSON implementation (I-JSON).
+const MAX_INDEX: i64 = (1 << 53) - 1;
+
+/// A parser that follows the grammar of RFC 9535 exactly, including where whitespace is allowed.
+pub(super) struct Parser<'a> {
+    query: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(query: &'a str) -> Self {
+        Self { query, pos: 0 }
+    }
+
+    fn error(&self, kind: QueryErrorKind) -> QueryError {
+        QueryError::new(kind, self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.query[self.pos..]
+    }
+
+    fn unexpected(&self) -> QueryError {
+        match self.peek() {
+            Some(c) => self.error(QueryErrorKind::Unexpected(c)),
+            None => self.error(QueryErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn invalid(&self, start: usize, reason: &'static str) -> QueryError {
+        QueryError::new(QueryErrorKind::InvalidExpression(reason), start)
+    }
+
+    /// Skips spaces, tabs and line breaks, the only whitespace the grammar allows.
+    fn blank(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), QueryError> {
+        if !self.eat(token) {
+            return Err(self.unexpected());
+        }
+        Ok(())
+    }
+
+    /// A whole query, which must start with `$`.
+    pub fn parse(mut self) -> Result<Query, QueryError> {
+        self.expect("$")?;
+        let segments = self.segments()?;
+        if self.pos < self.query.len() {
+            return Err(self.unexpected());
+        }
+        Ok(Query {
+            absolute: true,
+            segments,
+        })
+    }
+
+    fn segments(&mut self) -> Result<Vec<Segment>, QueryError> {
+        let mut segments = Vec::new();
+        loop {
+            // whitespace is allowed before a segment, but not after the last one
+            let start = self.pos;
+            self.blank();
+            let segment = if self.eat("..") {
+                Segment::Descendant(if self.eat("[") {
+                    self.bracketed()?
+                } else if self.eat("*") {
+                    vec![Selector::Wildcard]
+                } else {
+                    vec![Selector::Name(self.member_name()?)]
+                })
+            } else if self.eat(".") {
+                Segment::Child(if self.eat("*") {
+                    vec![Selector::Wildcard]
+                } else {
+                    vec![Selector::Name(self.member_name()?)]
+                })
+            } else if self.eat("[") {
+                Segment::Child(self.bracketed()?)
+            } else {
+                self.pos = start;
+                return Ok(segments);
+            };
+            segments.push(segment);
+        }
+    }
+
+    /// The name after a `.`: letters, `_` and any non ASCII character, then also digits.
+    fn member_name(&mut self) -> Result<String, QueryError> {
+        let len = self
+            .rest()
+            .char_indices()
+            .find(|&(i, c)| {
+                !(c.is_ascii_alphabetic()
+                    || c == '_'
+                    || !c.is_ascii()
+                    || (i > 0 && c.is_ascii_digit()))
+            })
+            .map_or(self.rest().len(), |(i, _)| i);
+        if len == 0 {
+            return Err(self.unexpected());
+        }
+        let name = self.rest()[..len].to_string();
+        self.pos += len;
+        Ok(name)
+    }
+
+    /// The selectors of `[...]`, after the opening bracket.
+    fn bracketed(&mut self) -> Result<Vec<Selector>, QueryError> {
+        let mut selectors = Vec::new();
+        loop {
+            self.blank();
+            selectors.push(self.selector()?);
+            self.blank();
+            if self.eat("]") {
+                return Ok(selectors);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, QueryError> {
+        match self.peek() {
+            Some('\'' | '"') => Ok(Selector::Name(self.string_literal()?)),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.blank();
+                Ok(Selector::Filter(Box::new(self.logical_or()?)))
+            }
+            _ => {
+                let start = self.int()?;
+                let before_colon = self.pos;
+                self.blank();
+                if !self.eat(":") {
+                    self.pos = before_colon;
+                    return start.map(Selector::Index).ok_or_else(|| self.unexpected());
+                }
+                self.blank();
+                let end = self.int()?;
+                let before_colon = self.pos;
+                self.blank();
+                let step = if self.eat(":") {
+                    self.blank();
+                    self.int()?
+                } else {
+                    self.pos = before_colon;
+                    None
+                };
+                Ok(Selector::Slice(start, end, step))
+            }
+        }
+    }
+
+    /// An integer without leading zeros (and not `-0`) that fits in [`MAX_INDEX`], if there is
+    /// one.
+    fn int(&mut self) -> Result<Option<i64>, QueryError> {
+        let start = self.pos;
+        let negative = self.eat("-");
+        let digits = self
+            .rest()
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest().len());
+        if digits == 0 {
+            return match negative {
+                true => Err(self.unexpected()),
+                false => Ok(None),
+            };
+        }
+        let text = &self.rest()[..digits];
+        self.pos += digits;
+        let invalid = || QueryError::new(QueryErrorKind::InvalidIndex, start);
+        if (text.starts_with('0') && (digits > 1 || negative)) || digits > 16 {
+            return Err(invalid());
+        }
+        let x = text.parse::<i64>().map_err(|_| invalid())?;
+        if x > MAX_INDEX {
+            return Err(invalid());
+        }
+        Ok(Some(if negative { -x } else { x }))
+    }
+
+    /// A string in single or double quotes, decoded. Only the quote that delimits it may be
+    /// escaped, control characters must be.
+    fn string_literal(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(QueryError::new(QueryErrorKind::UnclosedString, start));
+            };
+            if c < '\u{20}' {
+                return Err(self.unexpected());
+            }
+            self.pos += c.len_utf8();
+            match c {
+                c if c == quote => return Ok(out),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some(c) if c == quote => c,
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('/') => '/',
+                        Some('\\') => '\\',
+                        Some('u') => {
+                            self.pos += 1;
+                            out.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.unexpected()),
+                    };
+                    self.pos += 1;
+                    out.push(escaped);
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, QueryError> {
+        let hex = self
+            .rest()
+            .get(..4)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+        let Some(hex) = hex else {
+            return Err(self.error(QueryErrorKind::InvalidEscape));
+        };
+        self.pos += 4;
+        Ok(u32::from_str_radix(hex, 16).unwrap())
+    }
+
+    /// The code point of `\uXXXX` after the `u`, a surrogate pair must be complete.
+    fn unicode_escape(&mut self) -> Result<char, QueryError> {
+        let start = self.pos;
+        let high = self.hex4()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if !self.eat("\\u") {
+                    return Err(QueryError::new(QueryErrorKind::InvalidEscape, start));
+                }
+                let low = self.hex4()?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(QueryError::new(QueryErrorKind::InvalidEscape, start));
+                }
+                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+            }
+            _ => high,
+        };
+        char::from_u32(code).ok_or_else(|| QueryError::new(QueryErrorKind::InvalidEscape, start))
+    }
+
+    fn logical_or(&mut self) -> Result<LogicalExpr, QueryError> {
+        let mut exprs = vec![self.logical_and()?];
+        loop {
+            let before = self.pos;
+            self.blank();
+            if !self.eat("||") {
+                self.pos = before;
+                break;
+            }
+            self.blank();
+            exprs.push(self.logical_and()?);
+        }
+        Ok(match exprs.len() {
+            1 => exprs.pop().unwrap(),
+            _ => LogicalExpr::Or(exprs),
+        })
+    }
+
+    fn logical_and(&mut self) -> Result<LogicalExpr, QueryError> {
+        let mut exprs = vec![self.basic()?];
+        loop {
+            let before = self.pos;
+            self.blank();
+            if !self.eat("&&") {
+                self.pos = before;
+                break;
+            }
+            self.blank();
+            exprs.push(self.basic()?);
+        }
+        Ok(match exprs.len() {
+            1 => exprs.pop().unwrap(),
+            _ => LogicalExpr::And(exprs),
+        })
+    }
+
+    /// A parenthesized expression, a comparison or a test, optionally negated.
+    fn basic(&mut self) -> Result<LogicalExpr, QueryError> {
+        if self.eat("!") {
+            self.blank();
+            if self.eat("(") {
+                return Ok(LogicalExpr::Not(Box::new(self.parenthesized()?)));
+            }
+            return Ok(LogicalExpr::Not(Box::new(self.test()?)));
+        }
+        if self.eat("(") {
+            return self.parenthesized();
+        }
+
+        let start = self.pos;
+        let lhs = self.operand()?;
+        let before = self.pos;
+        self.blank();
+        let op = [
+            ("==", CompareOp::Eq),
+            ("!=", CompareOp::Ne),
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token));
+        let Some((_, op)) = op else {
+            self.pos = before;
+            return self.check_test(lhs, start);
+        };
+        let lhs = self.check_comparable(lhs, start)?;
+        self.blank();
+        let start = self.pos;
+        let rhs = self.operand()?;
+        let rhs = self.check_comparable(rhs, start)?;
+        Ok(LogicalExpr::Compare(lhs, op, rhs))
+    }
+
+    /// The rest of `(...)`, after the opening parenthesis.
+    fn parenthesized(&mut self) -> Result<LogicalExpr, QueryError> {
+        self.blank();
+        let expr = self.logical_or()?;
+        self.blank();
+        self.expect(")")?;
+        Ok(expr)
+    }
+
+    fn test(&mut self) -> Result<LogicalExpr, QueryError> {
+        let start = self.pos;
+        let operand = self.operand()?;
+        self.check_test(operand, start)
+    }
+
+    fn check_test(&self, operand: Operand, start: usize) -> Result<LogicalExpr, QueryError> {
+        match operand {
+            Operand::Match(expr) => Ok(expr),
+            Operand::Comparable(Comparable::Query(query)) => Ok(LogicalExpr::Exists(query)),
+            Operand::Comparable(Comparable::Literal(_)) => {
+                Err(self.invalid(start, "a literal is not a condition"))
+            }
+            Operand::Comparable(Comparable::Function(_)) => {
+                Err(self.invalid(start, "the function returns a value, not a condition"))
+            }
+        }
+    }
+
+    fn check_comparable(&self, operand: Operand, start: usize) -> Result<Comparable, QueryError> {
+        match operand {
+            Operand::Comparable(Comparable::Query(query)) if !query.is_singular() => Err(self
+                .invalid(
+                    start,
+                    "a query that can select several nodes can't be compared",
+                )),
+            Operand::Comparable(comparable) => Ok(comparable),
+            Operand::Match(_) => Err(self.invalid(start, "a condition can't be compared")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, QueryError> {
+        let start = self.pos;
+        let comparable = match self.peek() {
+            Some('@' | '$') => {
+                let absolute = self.peek() == Some('$');
+                self.pos += 1;
+                Comparable::Query(Query {
+                    absolute,
+                    segments: self.segments()?,
+                })
+            }
+            Some('\'' | '"') => {
+                Comparable::Literal(Value::String(escape(&self.string_literal()?).into_owned()))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => Comparable::Literal(self.number()?),
+            Some(c) if c.is_ascii_lowercase() => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'))
+                    .unwrap_or(self.rest().len());
+                let name = &self.rest()[..len];
+                self.pos += len;
+                match name {
+                    "true" => Comparable::Literal(Value::Bool(true)),
+                    "false" => Comparable::Literal(Value::Bool(false)),
+                    "null" => Comparable::Literal(Value::Null),
+                    _ => return self.function(name, start),
+                }
+            }
+            _ => return Err(self.unexpected()),
+        };
+        Ok(Operand::Comparable(comparable))
+    }
+
+    /// A number literal, like in JSON but `-0` is allowed.
+    fn number(&mut self) -> Result<Value, QueryError> {
+        let start = self.pos;
+        let invalid = || QueryError::new(QueryErrorKind::InvalidNumber, start);
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+        self.eat("-");
+        let int = digits(self.rest());
+        if int == 0 || (int > 1 && self.rest().starts_with('0')) {
+            return Err(invalid());
+        }
+        self.pos += int;
+        if self.eat(".") {
+            let frac = digits(self.rest());
+            if frac == 0 {
+                return Err(invalid());
+            }
+            self.pos += frac;
+        }
+        if self.eat("e") || self.eat("E") {
+            let _ = self.eat("+") || self.eat("-");
+            let exp = digits(self.rest());
+            if exp == 0 {
+                return Err(invalid());
+            }
+            self.pos += exp;
+        }
+        Number::parse(&self.query[start..self.pos])
+            .map(Value::Number)
+            .ok_or_else(invalid)
+    }
+
+    /// A call of the function `name`, after its name. Their arguments are checked like the
+    /// RFC's type system requires.
+    fn function(&mut self, name: &str, start: usize) -> Result<Operand, QueryError> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        self.blank();
+        if !self.eat(")") {
+            loop {
+                self.blank();
+                args.push((self.pos, self.operand()?));
+                self.blank();
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+
+        let arity = match name {
+            "length" | "count" | "value" => 1,
+            "match" | "search" => 2,
+            _ => {
+                return Err(QueryError::new(
+                    QueryErrorKind::UnknownFunction(name.to_string()),
+                    start,
+                ))
+            }
+        };
+        if args.len() != arity {
+            let expected = if arity == 1 {
+                "1 argument"
+            } else {
+                "2 arguments"
+            };
+            return Err(QueryError::new(
+                QueryErrorKind::Arguments(name.to_string(), expected),
+                start,
+            ));
+        }
+
+        let mut args = args.into_iter();
+        let mut value = |parser: &Self| {
+            let (start, arg) = args.next().unwrap();
+            parser.check_comparable(arg, start)
+        };
+        Ok(match name {
+            "length" => Operand::Comparable(Comparable::Function(Function::Length(Box::new(
+                value(self)?,
+            )))),
+            "match" | "search" => Operand::Match(LogicalExpr::Match {
+                full: name == "match",
+                value: value(self)?,
+                pattern: value(self)?,
+            }),
+            _ => {
+                let (start, arg) = args.next().unwrap();
+                let Operand::Comparable(Comparable::Query(query)) = arg else {
+                    return Err(self.invalid(start, "the argument must be a query"));
+                };
+                Operand::Comparable(Comparable::Function(match name {
+                    "count" => Function::Count(query),
+                    _ => Function::Value(query),
+                }))
+            }
+        })
+    }
+}
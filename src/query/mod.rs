@@ -0,0 +1,170 @@
+//! Queries select values of a document by their paths, see [`Path`] for the syntax, or compute
+//! new ones with a subset of jq, see [`jq::Program`]. RFC 9535 JSONPath is supported too, see
+//! [`jsonpath::JsonPath`].
+
+pub mod complete;
+pub mod filter;
+pub mod history;
+pub mod jq;
+pub mod jsonpath;
+pub mod path;
+pub mod task;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::value::Value;
+
+pub use filter::Filter;
+pub use path::{format_path, format_path_preview, Path, Segment};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueryErrorKind {
+    #[error("unexpected end of query")]
+    UnexpectedEnd,
+    #[error("unexpected `{0}`")]
+    Unexpected(char),
+    #[error("unclosed string")]
+    UnclosedString,
+    #[error("invalid index")]
+    InvalidIndex,
+    #[error("invalid number")]
+    InvalidNumber,
+    #[error("unknown function `{0}`")]
+    UnknownFunction(String),
+    #[error("`{0}` takes {1}")]
+    Arguments(String, &'static str),
+    #[error("invalid regex: {0}")]
+    InvalidRegex(String),
+    #[error("invalid escape sequence")]
+    InvalidEscape,
+    #[error("{0}")]
+    InvalidExpression(&'static str),
+}
+
+/// An error in a query, `offset` is the byte offset in the query where it was found.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} at column {}", .offset + 1)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    pub offset: usize,
+}
+
+impl QueryError {
+    pub fn new(kind: QueryErrorKind, offset: usize) -> Self {
+        Self { kind, offset }
+    }
+}
+
+/// The language queries are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// Paths that select values of the document, like `.users[*].name`.
+    #[default]
+    Path,
+    /// A subset of jq that can also build new values, like `.users | map({name})`.
+    Jq,
+    /// RFC 9535 JSONPath, like `$.users[?@.age > 30].name`.
+    #[value(name = "jsonpath")]
+    JsonPath,
+}
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::Jq => "jq",
+            Self::JsonPath => "jsonpath",
+        }
+    }
+
+    /// The next language, to cycle through them.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Path => Self::Jq,
+            Self::Jq => Self::JsonPath,
+            Self::JsonPath => Self::Path,
+        }
+    }
+}
+
+/// A result of a query: a value of the document, or a value the query built.
+#[derive(Debug, Clone)]
+pub enum Output<'v> {
+    /// A value of the document and its path.
+    Ref(Vec<usize>, &'v Value),
+    Owned(Value),
+}
+
+impl Output<'_> {
+    pub fn value(&self) -> &Value {
+        match self {
+            Self::Ref(_, value) => value,
+            Self::Owned(value) => value,
+        }
+    }
+
+    /// The path of the value when it is part of the document.
+    pub fn path(&self) -> Option<&[usize]> {
+        match self {
+            Self::Ref(path, _) => Some(path),
+            Self::Owned(_) => None,
+        }
+    }
+
+    pub fn into_value(self) -> Value {
+        match self {
+            Self::Ref(_, value) => value.clone(),
+            Self::Owned(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RunError {
+    #[error(transparent)]
+    Query(#[from] QueryError),
+    #[error(transparent)]
+    Jq(#[from] jq::JqError),
+    #[error("the query was cancelled")]
+    Cancelled,
+}
+
+/// Parses `query` in `language` and runs it on `value`. Paths can't start with `$`, so those
+/// queries are run as JSONPath.
+pub fn run<'v>(
+    language: Language,
+    query: &str,
+    value: &'v Value,
+) -> Result<Vec<Output<'v>>, RunError> {
+    let outputs = evaluate(language, query, value);
+    // a cancelled query stops early, what it returned is incomplete
+    if task::is_cancelled() {
+        return Err(RunError::Cancelled);
+    }
+    outputs
+}
+
+fn evaluate<'v>(
+    language: Language,
+    query: &str,
+    value: &'v Value,
+) -> Result<Vec<Output<'v>>, RunError> {
+    Ok(match language {
+        Language::Path if query.starts_with('$') => evaluate(Language::JsonPath, query, value)?,
+        Language::Path => query
+            .parse::<Path>()?
+            .select(value)
+            .into_iter()
+            .map(|(path, value)| Output::Ref(path, value))
+            .collect(),
+        Language::Jq => query.parse::<jq::Program>()?.run(value)?,
+        Language::JsonPath => query
+            .parse::<jsonpath::JsonPath>()?
+            .select(value)
+            .into_iter()
+            .map(|(path, value)| Output::Ref(path, value))
+            .collect(),
+    })
+}
@@ -0,0 +1,422 @@
+use std::str::FromStr;
+
+use crate::value::{unescape, Value};
+
+use super::{filter::Filter, task::is_cancelled, QueryError, QueryErrorKind};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Every entry with this key, objects may repeat keys.
+    Key(String),
+    /// An array item, negative indices count from the end.
+    Index(i64),
+    /// Every item of an array or entry of an object.
+    Wildcard,
+    /// The items of an array or entries of an object that match the filter.
+    Filter(Filter),
+}
+
+/// A path to some values of a document, written like in jq:
+///
+/// - `.` is the root value
+/// - `.key` or `."key"` or `["key"]` selects the entries with that key
+/// - `[0]` selects an array item, `[-1]` is the last one
+/// - `[]`, `[*]` or `.*` select every child
+/// - `[?(...)]` selects the children that match a [`Filter`]
+///
+/// For example `.users[*].name` selects the name of every user. A key selects every entry it
+/// is repeated in, not only the first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub segments: Vec<Segment>,
+}
+
+pub(super) struct PathParser<'a> {
+    pub query: &'a str,
+    pub pos: usize,
+}
+
+impl PathParser<'_> {
+    pub fn error(&self, kind: QueryErrorKind) -> QueryError {
+        QueryError::new(kind, self.pos)
+    }
+
+    pub fn skip_whitespace(&mut self) {
+        let rest = &self.query[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    pub fn peek(&self) -> Option<char> {
+        self.query[self.pos..].chars().next()
+    }
+
+    pub fn rest(&self) -> &str {
+        &self.query[self.pos..]
+    }
+
+    pub fn unexpected(&self) -> QueryError {
+        match self.peek() {
+            Some(c) => self.error(QueryErrorKind::Unexpected(c)),
+            None => self.error(QueryErrorKind::UnexpectedEnd),
+        }
+    }
+
+    pub fn expect(&mut self, expected: char) -> Result<(), QueryError> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.unexpected());
+        }
+        self.pos += expected.len_utf8();
+        Ok(())
+    }
+
+    pub fn identifier(&mut self) -> Option<String> {
+        let rest = &self.query[self.pos..];
+        let len = rest
+            .char_indices()
+            .find(|&(i, c)| !(c.is_alphabetic() || c == '_' || (i > 0 && c.is_alphanumeric())))
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        self.pos += len;
+        (len > 0).then(|| rest[..len].to_string())
+    }
+
+    /// A string in double quotes, with JSON escape sequences.
+    fn string(&mut self) -> Result<String, QueryError> {
+        Ok(unescape(&self.raw_string()?).into_owned())
+    }
+
+    /// A string in double (or single) quotes, with its escape sequences kept.
+    pub fn raw_string(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut escaped = false;
+        for (i, c) in self.query[self.pos..].char_indices() {
+            match c {
+                c if c == quote && !escaped => {
+                    let raw = &self.query[self.pos..self.pos + i];
+                    self.pos += i + 1;
+                    return Ok(raw.to_string());
+                }
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        Err(QueryError::new(QueryErrorKind::UnclosedString, start))
+    }
+
+    fn index(&mut self) -> Result<i64, QueryError> {
+        let start = self.pos;
+        let rest = &self.query[self.pos..];
+        let len = rest
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len]
+            .parse()
+            .map_err(|_| QueryError::new(QueryErrorKind::InvalidIndex, start))
+    }
+
+    /// The content of `[...]`, after the opening bracket.
+    fn bracket(&mut self) -> Result<Segment, QueryError> {
+        self.skip_whitespace();
+        let segment = match self.peek() {
+            Some(']') => Segment::Wildcard,
+            Some('*') => {
+                self.pos += 1;
+                Segment::Wildcard
+            }
+            Some('"') => Segment::Key(self.string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => Segment::Index(self.index()?),
+            Some('?') => {
+                self.pos += 1;
+                Segment::Filter(self.filter()?)
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.expect(']')?;
+        Ok(segment)
+    }
+
+    fn parse(mut self) -> Result<Path, QueryError> {
+        let mut segments = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            // a lone `.` is the root
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                return Ok(Path { segments });
+            }
+            if self.peek() != Some('[') {
+                segments.push(self.dotted()?);
+            }
+        } else if self.peek() != Some('[') {
+            return Err(self.unexpected());
+        }
+
+        self.segments(&mut segments)?;
+        match self.peek() {
+            None => Ok(Path { segments }),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    /// The segments up to the first character that can't continue the path.
+    pub fn segments(&mut self, segments: &mut Vec<Segment>) -> Result<(), QueryError> {
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('.') => {
+                    self.pos += 1;
+                    if self.peek() == Some('[') {
+                        continue;
+                    }
+                    segments.push(self.dotted()?);
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    segments.push(self.bracket()?);
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// The segment after a `.`.
+    pub fn dotted(&mut self) -> Result<Segment, QueryError> {
+        match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Ok(Segment::Wildcard)
+            }
+            Some('"') => Ok(Segment::Key(self.string()?)),
+            _ => self
+                .identifier()
+                .map(Segment::Key)
+                .ok_or_else(|| self.unexpected()),
+        }
+    }
+}
+
+impl FromStr for Path {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        PathParser { query, pos: 0 }.parse()
+    }
+}
+
+impl Path {
+    /// The paths of the selected values (see [`Value::get_path`]), in document order. Containers
+    /// that weren't parsed yet are not searched.
+    pub fn evaluate(&self, value: &Value) -> Vec<Vec<usize>> {
+        self.select(value)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// The selected values and their paths, see [`Path::evaluate`].
+    pub fn select<'v>(&self, value: &'v Value) -> Vec<(Vec<usize>, &'v Value)> {
+        let mut current = vec![(Vec::new(), value)];
+        for segment in &self.segments {
+            let mut next = Vec::new();
+            for (path, value) in current {
+                if is_cancelled() {
+                    return Vec::new();
+                }
+                let child = |i: usize| {
+                    let mut path = path.clone();
+                    path.push(i);
+                    path
+                };
+                match (segment, value) {
+                    (Segment::Key(key), Value::Object(object)) => next.extend(
+                        object
+                            .positions(key)
+                            .into_iter()
+                            .map(|i| (child(i), &object.entries()[i].1)),
+                    ),
+                    (Segment::Index(index), Value::Array(arr)) => {
+                        let len = arr.arr.len() as i64;
+                        let i = if *index < 0 { len + index } else { *index };
+                        if (0..len).contains(&i) {
+                            next.push((child(i as usize), &arr.arr[i as usize]));
+                        }
+                    }
+                    (Segment::Wildcard, Value::Array(arr)) => {
+                        next.extend(arr.arr.iter().enumerate().map(|(i, v)| (child(i), v)))
+                    }
+                    (Segment::Wildcard, Value::Object(object)) => next.extend(
+                        object
+                            .entries()
+                            .iter()
+                            .enumerate()
+                            .map(|(i, (_, v))| (child(i), v)),
+                    ),
+                    (Segment::Filter(filter), Value::Array(arr)) => next.extend(
+                        arr.arr
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, v)| filter.matches(v))
+                            .map(|(i, v)| (child(i), v)),
+                    ),
+                    (Segment::Filter(filter), Value::Object(object)) => next.extend(
+                        object
+                            .entries()
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, (_, v))| filter.matches(v))
+                            .map(|(i, (_, v))| (child(i), v)),
+                    ),
+                    _ => {}
+                }
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Writes a path of child indices in the query syntax, e.g. `.users[2].name`.
+pub fn format_path(value: &Value, path: &[usize]) -> String {
+    format_path_preview(value, path, usize::MAX)
+}
+
+/// The first `width` characters of [`format_path`], the segments after them aren't written.
+pub fn format_path_preview(value: &Value, path: &[usize], width: usize) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+    let mut out = String::new();
+    let mut len = 0;
+    let mut value = Some(value);
+    for &i in path {
+        if len >= width {
+            break;
+        }
+        let segment = match value {
+            Some(Value::Object(object)) if i < object.len() => {
+                let (key, child) = &object.entries()[i];
+                value = Some(child);
+                if is_identifier(&unescape(key)) {
+                    format!(".{key}")
+                } else {
+                    format!(".\"{key}\"")
+                }
+            }
+            Some(Value::Array(arr)) => {
+                value = arr.get(i);
+                format!("[{i}]")
+            }
+            _ => {
+                value = None;
+                format!("[{i}]")
+            }
+        };
+        len += segment.chars().count();
+        out += &segment;
+    }
+    if let Some((i, _)) = out.char_indices().nth(width) {
+        out.truncate(i);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::parse;
+
+    fn select(source: &str, query: &str) -> Vec<String> {
+        let value = parse(source).unwrap();
+        let path: Path = query.parse().unwrap();
+        path.evaluate(&value)
+            .iter()
+            .map(|path| value.get_path(path).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parse_paths() {
+        use Segment::*;
+        let parse = |s: &str| s.parse::<Path>().map(|path| path.segments);
+        assert_eq!(parse("."), Ok(vec![]));
+        assert_eq!(
+            parse(".a.\"b c\"[\"d\"][0][-1]"),
+            Ok(vec![
+                Key("a".into()),
+                Key("b c".into()),
+                Key("d".into()),
+                Index(0),
+                Index(-1)
+            ])
+        );
+        assert_eq!(parse(".[] .* [*]"), Ok(vec![Wildcard, Wildcard, Wildcard]));
+        assert_eq!(parse("[0].a_1"), Ok(vec![Index(0), Key("a_1".into())]));
+
+        let error = |s: &str| s.parse::<Path>().unwrap_err();
+        assert_eq!(error(""), QueryError::new(QueryErrorKind::UnexpectedEnd, 0));
+        assert_eq!(
+            error("a"),
+            QueryError::new(QueryErrorKind::Unexpected('a'), 0)
+        );
+        assert_eq!(
+            error(".a.\"b"),
+            QueryError::new(QueryErrorKind::UnclosedString, 3)
+        );
+        assert_eq!(
+            error(".a[1"),
+            QueryError::new(QueryErrorKind::UnexpectedEnd, 4)
+        );
+        assert_eq!(
+            error(".a[-]"),
+            QueryError::new(QueryErrorKind::InvalidIndex, 3)
+        );
+        assert_eq!(
+            error(".a..b"),
+            QueryError::new(QueryErrorKind::Unexpected('.'), 3)
+        );
+    }
+
+    #[test]
+    fn evaluate() {
+        let source = r#"{"a": [1, {"b": 2}, {"b": 3}], "c": {"d": null}}"#;
+        assert_eq!(
+            select(source, "."),
+            vec![parse(source).unwrap().to_string()]
+        );
+        assert_eq!(select(source, ".a[0]"), vec!["1"]);
+        assert_eq!(select(source, ".a[-1].b"), vec!["3"]);
+        assert_eq!(select(source, ".a[3]"), Vec::<String>::new());
+        assert_eq!(select(source, ".a[].b"), vec!["2", "3"]);
+        assert_eq!(select(source, ".*.d"), vec!["null"]);
+        assert_eq!(select(source, ".c[0]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let source = r#"{"a": 1, "b": 2, "a": 3, "\u0061": 4}"#;
+        assert_eq!(select(source, ".a"), vec!["1", "3", "4"]);
+        assert_eq!(select(source, ".b"), vec!["2"]);
+    }
+
+    #[test]
+    fn format() {
+        let value = parse(r#"{"a": [{"b c": 1}], "\n": 2}"#).unwrap();
+        assert_eq!(format_path(&value, &[]), ".");
+        assert_eq!(format_path(&value, &[0, 0, 0]), ".a[0].\"b c\"");
+        assert_eq!(format_path(&value, &[1]), ".\"\\n\"");
+        assert_eq!(format_path_preview(&value, &[0, 0, 0], 6), ".a[0].");
+    }
+}
@@ -0,0 +1,117 @@
+//! Queries running on a background thread, so that the UI stays responsive while a slow query
+//! runs on a large document.
+
+use std::{
+    cell::RefCell,
+    panic::resume_unwind,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+thread_local! {
+    /// The flag of the task running on this thread.
+    static CANCELLED: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Whether the task running the query on this thread was cancelled. The evaluators check it
+/// while they walk the document and stop early, [`super::run`] then fails with
+/// [`super::RunError::Cancelled`]. Work done with the outputs afterwards checks it too.
+pub fn is_cancelled() -> bool {
+    CANCELLED.with(|cancelled| {
+        cancelled
+            .borrow()
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::Relaxed))
+    })
+}
+
+/// Work that runs queries on its own thread and can be cancelled, like a query that became
+/// stale because the text changed.
+pub struct QueryTask<T> {
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<T>,
+}
+
+impl<T: Send + 'static> QueryTask<T> {
+    pub fn spawn(f: impl FnOnce() -> T + Send + 'static) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let cancelled = cancelled.clone();
+            std::thread::spawn(move || {
+                CANCELLED.with(|flag| *flag.borrow_mut() = Some(cancelled));
+                f()
+            })
+        };
+        Self { cancelled, handle }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Asks the queries to stop, without waiting for the thread.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Cancels the queries and waits for the thread to stop, which is quick since they check
+    /// [`is_cancelled`] often.
+    pub fn stop(self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+
+    /// Waits for the result.
+    pub fn join(self) -> T {
+        self.handle
+            .join()
+            .unwrap_or_else(|panic| resume_unwind(panic))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        query::{run, Language, RunError},
+        value::parse,
+    };
+
+    #[test]
+    fn cancel() {
+        let items = vec![r#"[[1, 2], {"a": 3}]"#; 1000].join(",");
+        let value = Arc::new(parse(&format!("[{items}]")).unwrap());
+        let (started, start) = mpsc::channel();
+        let (go, wait) = mpsc::channel::<()>();
+        let task = {
+            let value = value.clone();
+            QueryTask::spawn(move || {
+                started.send(()).unwrap();
+                wait.recv().unwrap();
+                [Language::Path, Language::Jq, Language::JsonPath].map(|language| {
+                    let query = match language {
+                        Language::Path => "[*][*]",
+                        Language::Jq => "..",
+                        Language::JsonPath => "$..*",
+                    };
+                    run(language, query, &value).map(|outputs| outputs.len())
+                })
+            })
+        };
+        start.recv().unwrap();
+        task.cancelled.store(true, Ordering::Relaxed);
+        go.send(()).unwrap();
+        for result in task.join() {
+            assert_eq!(result, Err(RunError::Cancelled));
+        }
+
+        let task = QueryTask::spawn(move || run(Language::Jq, "..", &value).map(|o| o.len()));
+        assert_eq!(task.join(), Ok(6001));
+        assert!(!is_cancelled());
+    }
+}
@@ -1,6 +1,7 @@
 use derive_more::IsVariant;
 use std::str::FromStr;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IsVariant)]
 pub enum MatchStatus {
     FullMatch,
@@ -10,37 +11,11 @@ pub enum MatchStatus {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IsVariant)]
 pub enum PatternItem {
-    Token(&'static str),
     Literal(char),
     Number,
     AnyChar,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IsVariant)]
-enum PatternInputItem {
-    Token(&'static str),
-    Char(char),
-}
-
-impl PatternInputItem {
-    fn str_to_item_char_vec(s: &str) -> Vec<Self> {
-        s.chars().into_iter().map(Self::Char).collect()
-    }
-
-    fn is_char_numeric(self) -> bool {
-        match self {
-            Self::Char(c) => c.is_numeric(),
-            _ => false,
-        }
-    }
-}
-
-impl From<char> for PatternInputItem {
-    fn from(value: char) -> Self {
-        Self::Char(value)
-    }
-}
-
 #[derive(Debug, thiserror::Error)]
 pub enum PatternParseError {
     #[error("Invalid escape character `{0}`")]
@@ -84,7 +59,7 @@ impl FromStr for Pattern {
 }
 
 impl Pattern {
-    fn match_aux(pattern: &[PatternItem], source: &[PatternInputItem]) -> MatchStatus {
+    fn match_aux(pattern: &[PatternItem], source: &[char]) -> MatchStatus {
         if pattern.is_empty() && source.is_empty() {
             return MatchStatus::FullMatch;
         }
@@ -112,28 +87,21 @@ impl Pattern {
         };
 
         match (p, s) {
-            (PatternItem::Token(t), PatternInputItem::Token(s)) if t == s => {
-                Self::match_aux(&pattern[1..], &source[1..])
-            }
-            (PatternItem::Literal(c), PatternInputItem::Char(s)) if c == s => {
-                return Self::match_aux(&pattern[1..], &source[1..])
-            }
-            (PatternItem::Number, PatternInputItem::Char(s)) if s.is_numeric() => {
+            (PatternItem::Literal(c), s) if c == s => Self::match_aux(&pattern[1..], &source[1..]),
+            (PatternItem::Number, s) if s.is_numeric() => {
                 let mut source = source;
-                while source.first().map(|c| c.is_char_numeric()).unwrap_or(false) {
+                while source.first().is_some_and(|c| c.is_numeric()) {
                     source = &source[1..];
                 }
                 Self::match_aux(&pattern[1..], source)
             }
-            (PatternItem::AnyChar, PatternInputItem::Char(_)) => {
-                Self::match_aux(&pattern[1..], &source[1..])
-            }
+            (PatternItem::AnyChar, _) => Self::match_aux(&pattern[1..], &source[1..]),
             _ => MatchStatus::NoMatch,
         }
     }
 
     pub fn match_str(&self, s: &str) -> MatchStatus {
-        let s = PatternInputItem::str_to_item_char_vec(s);
+        let s = s.chars().collect::<Vec<_>>();
         Self::match_aux(&self.0, &s)
     }
 }
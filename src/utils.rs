@@ -26,6 +26,7 @@ pub fn element_type_to_color(ty: ElementType) -> Color {
         ElementType::Whtiespace => Color::Gray,
         ElementType::Comma => Color::Gray,
         ElementType::Key => Color::Cyan,
+        ElementType::DuplicateKey => Color::LightMagenta,
         ElementType::NullLiteral => Color::LightYellow,
         ElementType::BoolLiteral => Color::LightYellow,
         ElementType::NumberLiteral => Color::LightYellow,
@@ -35,6 +36,8 @@ pub fn element_type_to_color(ty: ElementType) -> Color {
         ElementType::Brace => Color::Gray,
         ElementType::CollapsedBracket => Color::Gray, // TODO: add fill color
         ElementType::CollapsedBrace => Color::Gray,   // TODO add fill color
+        ElementType::Comment => Color::DarkGray,
+        ElementType::Error => Color::LightRed,
     }
 }
 
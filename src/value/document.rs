@@ -0,0 +1,638 @@
+use std::{
+    borrow::Cow,
+    ffi::OsString,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicUsize, Arc},
+};
+
+use memmap2::Mmap;
+
+use super::{
+    edit::{shift_comments, Edit, EditError, Op, Restore},
+    lazy::{self, StructuralIndex},
+    parse, parse_with,
+    source_map::{SourceItem, SourceMap},
+    writer::{LayoutConfig, Writer},
+    Comment, Extensions, ParseError, ParseOptions, Value,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DocumentError {
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the file: {0}")]
+    Parse(#[from] ParseError),
+    #[error("The document wasn't loaded from a file")]
+    NoFile,
+}
+
+/// The bytes a document was parsed from.
+#[derive(Debug)]
+pub enum Source {
+    Text(String),
+    Mapped(Mmap),
+}
+
+impl Source {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(s) => s.as_bytes(),
+            Self::Mapped(m) => m,
+        }
+    }
+}
+
+/// A parsed document together with the source it was parsed from. The value tree owns its data,
+/// so documents can be freely moved between threads, mutated and replaced by a reparse.
+///
+/// Lazy documents are memory mapped and only parse a container once it is expanded, using a
+/// structural index to skip over the content of the containers that aren't.
+///
+/// Lenient documents may use JSONC and JSON5 syntax, `extensions` records which parts of it.
+/// Recovered documents contain [`Value::Error`] nodes for every error in `errors`.
+///
+/// Edits go through [`Document::apply`], which keeps track of where the values that didn't
+/// change are in the source so that saving keeps their formatting.
+#[derive(Debug)]
+pub struct Document {
+    source: Source,
+    path: Option<PathBuf>,
+    options: ParseOptions,
+    index: Option<StructuralIndex>,
+    /// Shared with the queries running on other threads, see [`Document::snapshot`].
+    value: Arc<Value>,
+    comments: Vec<Comment>,
+    extensions: Extensions,
+    errors: Vec<ParseError>,
+    /// Built on the first edit, which parses the source again and takes about as long as loading
+    /// it did. Most documents are only viewed, building it while loading would double the memory
+    /// they take.
+    source_map: Option<SourceMap>,
+    /// Whether the value changed since it was parsed from the source.
+    edited: bool,
+}
+
+impl Document {
+    pub fn parse(source: String) -> Result<Self, DocumentError> {
+        let value = parse(&source)?;
+        Ok(Self {
+            source: Source::Text(source),
+            path: None,
+            options: ParseOptions::default(),
+            index: None,
+            value: Arc::new(value),
+            comments: Vec::new(),
+            extensions: Extensions::default(),
+            errors: Vec::new(),
+            source_map: None,
+            edited: false,
+        })
+    }
+
+    /// Parses leniently and/or with error recovery, see [`ParseOptions`].
+    pub fn parse_with(source: String, options: ParseOptions) -> Result<Self, DocumentError> {
+        let parsed = parse_with(&source, options)?;
+        Ok(Self {
+            source: Source::Text(source),
+            path: None,
+            options,
+            index: None,
+            value: Arc::new(parsed.value),
+            comments: parsed.comments,
+            extensions: parsed.extensions,
+            errors: parsed.errors,
+            source_map: None,
+            edited: false,
+        })
+    }
+
+    /// Memory maps the file and parses only its top level container. `progress` is updated with
+    /// the number of bytes indexed so far.
+    pub fn parse_lazy(source: Source, progress: &AtomicUsize) -> Result<Self, DocumentError> {
+        let index = StructuralIndex::build(source.as_bytes(), progress)?;
+        let value = lazy::parse_root(source.as_bytes(), &index)?;
+        Ok(Self {
+            source,
+            path: None,
+            options: ParseOptions::default(),
+            index: Some(index),
+            value: Arc::new(value),
+            comments: Vec::new(),
+            extensions: Extensions::default(),
+            errors: Vec::new(),
+            source_map: None,
+            edited: false,
+        })
+    }
+
+    pub fn load(file: impl AsRef<Path>) -> Result<Self, DocumentError> {
+        let mut document = Self::parse(std::fs::read_to_string(&file)?)?;
+        document.path = Some(file.as_ref().to_path_buf());
+        Ok(document)
+    }
+
+    pub fn load_with(file: impl AsRef<Path>, options: ParseOptions) -> Result<Self, DocumentError> {
+        // recovering also covers files that aren't valid UTF-8
+        let source = if options.recover {
+            String::from_utf8_lossy(&std::fs::read(&file)?).into_owned()
+        } else {
+            std::fs::read_to_string(&file)?
+        };
+        let mut document = Self::parse_with(source, options)?;
+        document.path = Some(file.as_ref().to_path_buf());
+        Ok(document)
+    }
+
+    pub fn load_lazy(
+        file: impl AsRef<Path>,
+        progress: &AtomicUsize,
+    ) -> Result<Self, DocumentError> {
+        let path = file.as_ref().to_path_buf();
+        let file = File::open(file)?;
+        // SAFETY: the map is read only, modifying the file while it is open is not supported.
+        let map = unsafe { Mmap::map(&file)? };
+        let mut document = Self::parse_lazy(Source::Mapped(map), progress)?;
+        document.path = Some(path);
+        Ok(document)
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// The file the document was loaded from.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The options the document was parsed with.
+    pub fn options(&self) -> ParseOptions {
+        self.options
+    }
+
+    /// Whether the document was edited since it was loaded or saved.
+    pub fn is_edited(&self) -> bool {
+        self.edited
+    }
+
+    pub fn is_lazy(&self) -> bool {
+        self.index.is_some()
+    }
+
+    /// The comments before and after the root value.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// The errors that were recovered from.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn get(&self) -> &Value {
+        &self.value
+    }
+
+    /// The value, copied first when a snapshot of it is still around.
+    pub fn get_mut(&mut self) -> &mut Value {
+        Arc::make_mut(&mut self.value)
+    }
+
+    /// The value as it is now, unaffected by later changes, e.g. to run a query on another
+    /// thread. Changing the document while a snapshot is around copies its value.
+    pub fn snapshot(&self) -> Arc<Value> {
+        self.value.clone()
+    }
+
+    /// Applies an edit and returns the edit that reverts it.
+    pub fn apply(&mut self, edit: Edit) -> Result<Edit, EditError> {
+        if let Op::ToggleCollapse = edit.op {
+            let Self {
+                source,
+                index,
+                value,
+                ..
+            } = self;
+            let target = Arc::make_mut(value)
+                .get_path_mut(&edit.path)
+                .ok_or(EditError::InvalidPath)?;
+            expand(source, index.as_ref(), target, true)?;
+            return Ok(edit);
+        }
+        if self.is_lazy() {
+            return Err(EditError::Lazy);
+        }
+        if self.source_map.is_none() && !self.edited {
+            let Source::Text(source) = &self.source else {
+                return Err(EditError::Lazy);
+            };
+            // parses the whole source, see `source_map`. It was parsed with the same options, this
+            // only fails if it was replaced
+            self.source_map = SourceMap::build(source, self.options).ok();
+        }
+
+        let Edit { path, op, restore } = edit;
+        let inverse = match op {
+            Op::Replace(value) => {
+                let target = self
+                    .get_mut()
+                    .get_path_mut(&path)
+                    .ok_or(EditError::InvalidPath)?;
+                let old = std::mem::replace(target, value);
+                let span = match restore {
+                    Restore::Span(span) => span,
+                    _ => None,
+                };
+                let old_span = self
+                    .source_map
+                    .as_mut()
+                    .and_then(|map| map.replace(&path, span));
+                Edit {
+                    path,
+                    op: Op::Replace(old),
+                    restore: Restore::Span(old_span),
+                }
+            }
+            Op::Insert(key, value) => {
+                let (&i, parent) = path.split_last().ok_or(EditError::InvalidPath)?;
+                match self.get_mut().get_path_mut(parent) {
+                    Some(Value::Array(arr)) if i <= arr.arr.len() => {
+                        arr.arr.insert(i, value);
+                        shift_comments(&mut arr.comments, i, 1);
+                    }
+                    Some(Value::Object(object)) if i <= object.len() => {
+                        object.insert(i, key.ok_or(EditError::MissingKey)?, value);
+                        shift_comments(&mut object.comments, i, 1);
+                    }
+                    _ => return Err(EditError::InvalidPath),
+                }
+                let item = match restore {
+                    Restore::Item(item) => item,
+                    _ => SourceItem::new(),
+                };
+                if let Some(map) = &mut self.source_map {
+                    map.insert(&path, item);
+                }
+                Edit::remove(path)
+            }
+            Op::Remove => {
+                let (&i, parent) = path.split_last().ok_or(EditError::RemoveRoot)?;
+                let (key, value) = match self.get_mut().get_path_mut(parent) {
+                    Some(Value::Array(arr)) if i < arr.arr.len() => {
+                        shift_comments(&mut arr.comments, i, -1);
+                        (None, arr.arr.remove(i))
+                    }
+                    Some(Value::Object(object)) if i < object.len() => {
+                        shift_comments(&mut object.comments, i, -1);
+                        let (key, value) = object.remove(i);
+                        (Some(key), value)
+                    }
+                    _ => return Err(EditError::InvalidPath),
+                };
+                let item = self.source_map.as_mut().and_then(|map| map.remove(&path));
+                Edit {
+                    path,
+                    op: Op::Insert(key, value),
+                    restore: item.map_or(Restore::None, Restore::Item),
+                }
+            }
+            Op::Rename(key) => {
+                let (&i, parent) = path.split_last().ok_or(EditError::InvalidPath)?;
+                let object = self
+                    .get_mut()
+                    .get_path_mut(parent)
+                    .and_then(Value::as_object_mut)
+                    .filter(|object| i < object.len())
+                    .ok_or(EditError::InvalidPath)?;
+                let old = object.rename(i, key);
+                let changed = match restore {
+                    Restore::KeyChanged(changed) => changed,
+                    _ => true,
+                };
+                let was_changed = self
+                    .source_map
+                    .as_mut()
+                    .is_none_or(|map| map.set_key_changed(&path, changed));
+                Edit {
+                    path,
+                    op: Op::Rename(old),
+                    restore: Restore::KeyChanged(was_changed),
+                }
+            }
+            Op::ToggleCollapse => unreachable!(),
+        };
+        self.edited = true;
+        Ok(inverse)
+    }
+
+    /// The document as it would be saved: the parts that weren't edited are copied from the
+    /// source, the others are written with `layout`.
+    pub fn source_text(&self, layout: &LayoutConfig) -> Cow<'_, str> {
+        let source = match &self.source {
+            Source::Text(text) => Cow::Borrowed(text.as_str()),
+            Source::Mapped(map) => String::from_utf8_lossy(map),
+        };
+        match &self.source_map {
+            _ if !self.edited => source,
+            Some(map) => Cow::Owned(map.write(&source, &self.value, layout)),
+            None => {
+                let mut writer = Writer::new();
+                writer.with_layout(layout);
+                writer.with_all_expanded(true);
+                writer.write_root(&self.value, &self.comments);
+                Cow::Owned(writer.to_string() + "\n")
+            }
+        }
+    }
+
+    /// Writes the document to the file it was loaded from, see [`Document::source_text`]. The
+    /// saved text becomes the new source.
+    pub fn save(&mut self, layout: &LayoutConfig) -> Result<(), DocumentError> {
+        let path = self.path.as_ref().ok_or(DocumentError::NoFile)?;
+        let text = self.source_text(layout).into_owned();
+        write_atomically(path, &text)?;
+        self.source = Source::Text(text);
+        self.source_map = None;
+        self.edited = false;
+        Ok(())
+    }
+
+    /// Collapses or expands the container at `path`, parsing it first if needed.
+    pub fn toggle_collapse(&mut self, path: &[usize]) -> Result<(), DocumentError> {
+        let Self {
+            source,
+            index,
+            value,
+            ..
+        } = self;
+        match Arc::make_mut(value).get_path_mut(path) {
+            Some(value) => Ok(expand(source, index.as_ref(), value, true)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Expands every container on the way to `path`, so that the value there gets rendered.
+    pub fn expand_to(&mut self, path: &[usize]) -> Result<(), DocumentError> {
+        for depth in 0..path.len() {
+            let Self {
+                source,
+                index,
+                value,
+                ..
+            } = self;
+            let Some(value) = Arc::make_mut(value).get_path_mut(&path[..depth]) else {
+                break;
+            };
+            expand(source, index.as_ref(), value, false)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `text` to a new file next to `path` and renames it over `path`, so that the file is
+/// never left half written. The file keeps its permissions, and a link to it stays a link.
+fn write_atomically(path: &Path, text: &str) -> std::io::Result<()> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().ok_or(std::io::ErrorKind::InvalidInput)?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let write = || {
+        let mut file = File::options().write(true).create_new(true).open(&temp)?;
+        file.write_all(text.as_bytes())?;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp, &path)
+    };
+    write().inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+/// Expands `value` if it is a collapsed container, or toggles it when `toggle` is set. Containers
+/// that weren't parsed yet are parsed, which also expands them.
+fn expand(
+    source: &Source,
+    index: Option<&StructuralIndex>,
+    value: &mut Value,
+    toggle: bool,
+) -> Result<(), ParseError> {
+    match value {
+        Value::Array(arr) if toggle => arr.toggle_collapse(),
+        Value::Array(arr) => arr.expand(),
+        Value::Object(object) if toggle => object.toggle_collapse(),
+        Value::Object(object) => object.expand(),
+        Value::Lazy(container) => {
+            let index = index.expect("lazy values require an index");
+            *value = lazy::parse_container(source.as_bytes(), index, *container)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::{Document, ParseOptions, Source};
+    use crate::value::{
+        edit::Edit,
+        parse,
+        writer::{Indent, LayoutConfig, Writer},
+        Value,
+    };
+
+    #[test]
+    fn parse_on_another_thread() {
+        let source = r#"{"a": [1, 2, 3], "b": "text"}"#.to_string();
+        let document = std::thread::spawn(move || Document::parse(source).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(
+            document.get().get_path(&[1]).unwrap().get_string(),
+            Some("text")
+        );
+    }
+
+    #[test]
+    fn mutate() {
+        let mut document = Document::parse(r#"{"a": [1, 2, 3]}"#.to_string()).unwrap();
+        let arr = document
+            .get_mut()
+            .as_object_mut()
+            .unwrap()
+            .get_mut("a")
+            .unwrap()
+            .as_array_mut()
+            .unwrap();
+        arr.arr.push(Value::Null);
+        arr.collapse();
+        assert_eq!(document.get().to_string(), "{\n    \"a\": [ ... ]\n}");
+        assert!(document.source().as_bytes().starts_with(br#"{"a""#));
+    }
+
+    #[test]
+    fn edit() {
+        let source = "// settings\n{\n  a: 1, // one\n  'b': [0x10,   2],\n}\n".to_string();
+        let options = ParseOptions {
+            lenient: true,
+            recover: false,
+        };
+        let mut document = Document::parse_with(source.clone(), options).unwrap();
+        let layout = LayoutConfig::default();
+
+        let mut undo = vec![
+            document
+                .apply(Edit::replace(vec![1, 0], parse("true").unwrap()))
+                .unwrap(),
+            document.apply(Edit::remove(vec![0])).unwrap(),
+            document
+                .apply(Edit::rename(vec![0], "c".to_string()))
+                .unwrap(),
+            document
+                .apply(Edit::insert(vec![1], Some("d".to_string()), Value::Null))
+                .unwrap(),
+        ];
+        assert!(document.is_edited());
+        assert_eq!(
+            document.source_text(&layout),
+            "// settings\n{\n  \"c\": [true,   2],\n  \"d\": null\n}\n"
+        );
+
+        while let Some(edit) = undo.pop() {
+            document.apply(edit).unwrap();
+        }
+        assert_eq!(document.source_text(&layout), source);
+        assert!(document.apply(Edit::remove(Vec::new())).is_err());
+        assert!(document.save(&layout).is_err());
+    }
+
+    #[test]
+    fn save() {
+        let dir = std::env::temp_dir().join(format!("tispect-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("doc.json");
+        std::fs::write(&path, "{\"a\":  1}\n").unwrap();
+
+        let mut document = Document::load(&path).unwrap();
+        document
+            .apply(Edit::replace(vec![0], parse("2").unwrap()))
+            .unwrap();
+        document.save(&LayoutConfig::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"a\":  2}\n");
+        assert!(!document.is_edited());
+        // the text is written next to the file, nothing is left behind
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn save_tabs() {
+        let dir = std::env::temp_dir().join(format!("tispect-save-tabs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("doc.json");
+        std::fs::write(&path, "{\n\t\"a\": 1\n}\n").unwrap();
+
+        let mut document = Document::load(&path).unwrap();
+        document
+            .apply(Edit::replace(
+                vec![0],
+                parse(r#"{"b": 1, "c": 2}"#).unwrap(),
+            ))
+            .unwrap();
+        let layout = LayoutConfig {
+            indent: Indent::TAB,
+            ..Default::default()
+        };
+        document.save(&layout).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(text, "{\n\t\"a\": {\n\t\t\"b\": 1,\n\t\t\"c\": 2\n\t}\n}\n");
+    }
+
+    #[test]
+    fn expand_lazy() {
+        let source = Source::Text(r#"{"a": [1, {"b": 2}]}"#.to_string());
+        let mut document = Document::parse_lazy(source, &AtomicUsize::new(0)).unwrap();
+        assert!(document.get().get_path(&[0]).unwrap().is_lazy());
+
+        document.toggle_collapse(&[0]).unwrap();
+        assert!(document.get().get_path(&[0, 1]).unwrap().is_lazy());
+        document.toggle_collapse(&[0, 1]).unwrap();
+        assert!(document.get().get_path(&[0, 1, 0]).unwrap().is_number());
+
+        document.toggle_collapse(&[0]).unwrap();
+        assert_eq!(document.get().to_string(), "{\n    \"a\": [ ... ]\n}");
+    }
+
+    #[test]
+    fn lenient() {
+        let source = "// settings\n{\n  a: 1, // one\n  'b': [0x10, .5,],\n}\n".to_string();
+        assert!(Document::parse(source.clone()).is_err());
+
+        let options = ParseOptions {
+            lenient: true,
+            recover: false,
+        };
+        let document = Document::parse_with(source, options).unwrap();
+        assert_eq!(
+            document.extensions().names(),
+            [
+                "comments",
+                "trailing commas",
+                "single quoted strings",
+                "unquoted keys",
+                "hexadecimal numbers",
+                "loose number syntax"
+            ]
+        );
+        let mut writer = Writer::new();
+        writer.write_root(document.get(), document.comments());
+        assert_eq!(
+            writer.to_string(),
+            "// settings\n{\n    \"a\": 1, // one\n    \"b\": [0x10,.5]\n}"
+        );
+    }
+
+    #[test]
+    fn recover() {
+        let options = ParseOptions {
+            lenient: false,
+            recover: true,
+        };
+        let mut document =
+            Document::parse_with(r#"{"a": [1, x], "b": {"c": tru}}"#.to_string(), options).unwrap();
+        assert_eq!(document.errors().len(), 2);
+        let errors = document.get().find_paths(Value::is_error);
+        assert_eq!(errors, [vec![0, 1], vec![1, 0]]);
+
+        document.toggle_collapse(&[1]).unwrap();
+        document.expand_to(&[1, 0]).unwrap();
+        assert!(
+            !document
+                .get()
+                .get_path(&[1])
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .collapse
+        );
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(Document::parse("{".to_string()).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Changes to the values of a document, see [`Document::apply`](super::Document::apply).
+
+use super::{
+    source_map::{SourceItem, SourceSpan},
+    Comment, ParseError, Value,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EditError {
+    #[error("lazily loaded documents can't be edited, open the file without --lazy")]
+    Lazy,
+    #[error("there is no value there")]
+    InvalidPath,
+    #[error("the root value can't be removed")]
+    RemoveRoot,
+    #[error("object entries need a key")]
+    MissingKey,
+    #[error("could not parse the container: {0}")]
+    Parse(#[from] ParseError),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Op {
+    Replace(Value),
+    /// Inserts an item, with a raw key for objects.
+    Insert(Option<String>, Value),
+    Remove,
+    /// Renames an object entry, the key is raw.
+    Rename(String),
+    /// Collapses or expands a container, this doesn't change the value.
+    ToggleCollapse,
+}
+
+/// What an inverse edit puts back into the source map, so that undoing an edit also brings
+/// back the original formatting.
+#[derive(Debug, Clone, Default)]
+pub(crate) enum Restore {
+    #[default]
+    None,
+    Span(Option<SourceSpan>),
+    Item(SourceItem),
+    KeyChanged(bool),
+}
+
+/// A change to the value at `path`. Applying an edit returns the edit that reverts it.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub(crate) path: Vec<usize>,
+    pub(crate) op: Op,
+    pub(crate) restore: Restore,
+}
+
+impl Edit {
+    fn new(path: Vec<usize>, op: Op) -> Self {
+        Self {
+            path,
+            op,
+            restore: Restore::None,
+        }
+    }
+
+    pub fn replace(path: Vec<usize>, value: Value) -> Self {
+        Self::new(path, Op::Replace(value))
+    }
+
+    /// Inserts an item at `path`, moving the items from there on. `key` is raw and only used,
+    /// but required, in objects.
+    pub fn insert(path: Vec<usize>, key: Option<String>, value: Value) -> Self {
+        Self::new(path, Op::Insert(key, value))
+    }
+
+    pub fn remove(path: Vec<usize>) -> Self {
+        Self::new(path, Op::Remove)
+    }
+
+    /// Renames the object entry at `path`, `key` is raw.
+    pub fn rename(path: Vec<usize>, key: String) -> Self {
+        Self::new(path, Op::Rename(key))
+    }
+
+    /// Collapses or expands the container at `path`, parsing it first if needed.
+    pub fn toggle_collapse(path: Vec<usize>) -> Self {
+        Self::new(path, Op::ToggleCollapse)
+    }
+
+    /// The path of the value that the edit changes.
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    /// Whether the edit changes the value, rather than only how it is shown.
+    pub fn changes_value(&self) -> bool {
+        !matches!(self.op, Op::ToggleCollapse)
+    }
+
+    /// Drops what the edit knows about the source, once the source it refers to was replaced.
+    pub(crate) fn forget_source(&mut self) {
+        self.restore = Restore::None;
+    }
+}
+
+/// Moves the comments of a container after an item was inserted at `i` (or removed there when
+/// `delta` is negative). The comments before a removed item stay where it was, and inline ones
+/// stay after the item they were on the line of.
+pub(crate) fn shift_comments(comments: &mut [Comment], i: usize, delta: isize) {
+    for comment in comments {
+        if comment.before > i || (delta > 0 && comment.before == i && !comment.inline) {
+            comment.before = comment.before.saturating_add_signed(delta);
+        }
+    }
+}
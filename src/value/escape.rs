@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+
+fn parse_hex4(s: &[u8]) -> Option<u32> {
+    let s = std::str::from_utf8(s.get(..4)?).ok()?;
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// Decodes the escape sequences of a raw JSON string (the content between the quotes). Strings
+/// without escapes are returned as is, without allocating. Invalid sequences decode to `U+FFFD`.
+pub fn unescape(raw: &str) -> Cow<'_, str> {
+    if !raw.contains('\\') {
+        return Cow::Borrowed(raw);
+    }
+
+    let bytes = raw.as_bytes();
+    let mut out = String::with_capacity(raw.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let Some(offset) = raw[i..].find('\\') else {
+            out.push_str(&raw[i..]);
+            break;
+        };
+        out.push_str(&raw[i..i + offset]);
+        i += offset + 1;
+
+        let Some(&c) = bytes.get(i) else {
+            out.push(char::REPLACEMENT_CHARACTER);
+            break;
+        };
+        i += 1;
+        match c {
+            b'"' => out.push('"'),
+            b'\\' => out.push('\\'),
+            b'/' => out.push('/'),
+            b'b' => out.push('\u{8}'),
+            b'f' => out.push('\u{c}'),
+            b'n' => out.push('\n'),
+            b'r' => out.push('\r'),
+            b't' => out.push('\t'),
+            b'u' => {
+                let Some(high) = parse_hex4(&bytes[i..]) else {
+                    out.push(char::REPLACEMENT_CHARACTER);
+                    continue;
+                };
+                i += 4;
+
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    // a surrogate pair is encoded as two consecutive `\uXXXX` sequences
+                    match bytes.get(i..i + 2) {
+                        Some(b"\\u") => match parse_hex4(&bytes[i + 2..]) {
+                            Some(low) if (0xDC00..0xE000).contains(&low) => {
+                                i += 6;
+                                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                            }
+                            _ => high,
+                        },
+                        _ => high,
+                    }
+                } else {
+                    high
+                };
+                out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            _ => {
+                out.push(char::REPLACEMENT_CHARACTER);
+                // keep the character that followed the backslash
+                i -= 1;
+                let c = raw[i..].chars().next().unwrap();
+                out.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    Cow::Owned(out)
+}
+
+/// Escapes a decoded string so that it can be stored raw, the inverse of [`unescape`]. Strings
+/// without quotes, backslashes or control characters are returned as is.
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if !s.chars().any(|c| c == '"' || c == '\\' || c.is_control()) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c => out.push_str(&escape_control(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Escapes only the characters that can't be displayed on a single line (control characters).
+/// Used to render decoded strings in the viewer.
+pub fn escape_control(s: &str) -> Cow<'_, str> {
+    if !s.chars().any(char::is_control) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{escape, escape_control, unescape};
+
+    #[test]
+    fn unescape_borrows_plain_strings() {
+        assert!(matches!(
+            unescape("hello world"),
+            Cow::Borrowed("hello world")
+        ));
+        assert!(matches!(unescape(""), Cow::Borrowed("")));
+    }
+
+    #[test]
+    fn unescape_sequences() {
+        assert_eq!(unescape(r#"a\"b\\c\/d"#), "a\"b\\c/d");
+        assert_eq!(
+            unescape(r"line\nnext\ttab\r\b\f"),
+            "line\nnext\ttab\r\u{8}\u{c}"
+        );
+        assert_eq!(unescape(r"caf\u00e9"), "café");
+        assert_eq!(unescape(r"\u00e9\u00E9"), "éé");
+        assert_eq!(unescape(r"\ud83d\ude00!"), "😀!");
+    }
+
+    #[test]
+    fn unescape_invalid() {
+        assert_eq!(unescape(r"\ud83d"), "\u{fffd}");
+        assert_eq!(unescape(r"\ud83dx"), "\u{fffd}x");
+        assert_eq!(unescape(r"\u12"), "\u{fffd}12");
+        assert_eq!(unescape(r"\x"), "\u{fffd}x");
+        assert_eq!(unescape(r"end\"), "end\u{fffd}");
+    }
+
+    #[test]
+    fn escape_control_characters() {
+        assert!(matches!(escape_control("café"), Cow::Borrowed("café")));
+        assert_eq!(escape_control("a\nb\tc\u{1}"), r"a\nb\tc\u0001");
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        assert!(matches!(escape("café"), Cow::Borrowed("café")));
+        assert_eq!(escape("say \"hi\"\\\n"), r#"say \"hi\"\\\n"#);
+        assert_eq!(unescape(&escape("a\u{1}\"b")), "a\u{1}\"b");
+    }
+}
@@ -0,0 +1,166 @@
+//! Undo and redo of the edits made to a [`Document`].
+
+use super::{
+    edit::{Edit, EditError},
+    Document,
+};
+
+/// An edit that reverts a change, with where the cursor was before the change.
+#[derive(Debug, Clone)]
+struct Entry {
+    edit: Edit,
+    cursor: [i32; 2],
+}
+
+/// A change that was undone or redone. `cursor` is where the cursor was before it was made.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub path: Vec<usize>,
+    pub cursor: [i32; 2],
+    pub changes_value: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+    /// How many of the undo entries change the value when the document was saved, `None` when
+    /// that state can't be reached anymore.
+    saved: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            saved: Some(0),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.undo.iter().filter(|e| e.edit.changes_value()).count()
+    }
+
+    /// Records a change that was applied, `inverse` is the edit that reverts it.
+    pub fn record(&mut self, inverse: Edit, cursor: [i32; 2]) {
+        if inverse.changes_value() {
+            if self.saved.is_some_and(|saved| saved > self.depth()) {
+                self.saved = None;
+            }
+            self.redo.clear();
+        }
+        self.undo.push(Entry {
+            edit: inverse,
+            cursor,
+        });
+    }
+
+    /// Applies the top entry of `from` and pushes its inverse on `to`.
+    fn step(
+        document: &mut Document,
+        from: &mut Vec<Entry>,
+        to: &mut Vec<Entry>,
+        cursor: [i32; 2],
+    ) -> Result<Option<Step>, EditError> {
+        let Some(entry) = from.pop() else {
+            return Ok(None);
+        };
+        let step = Step {
+            path: entry.edit.path().to_vec(),
+            cursor: entry.cursor,
+            changes_value: entry.edit.changes_value(),
+        };
+        match document.apply(entry.edit.clone()) {
+            Ok(inverse) => {
+                to.push(Entry {
+                    edit: inverse,
+                    cursor,
+                });
+                Ok(Some(step))
+            }
+            Err(err) => {
+                from.push(entry);
+                Err(err)
+            }
+        }
+    }
+
+    /// Reverts the last change, `cursor` is restored when it is redone.
+    pub fn undo(
+        &mut self,
+        document: &mut Document,
+        cursor: [i32; 2],
+    ) -> Result<Option<Step>, EditError> {
+        Self::step(document, &mut self.undo, &mut self.redo, cursor)
+    }
+
+    /// Applies the last change that was undone again.
+    pub fn redo(
+        &mut self,
+        document: &mut Document,
+        cursor: [i32; 2],
+    ) -> Result<Option<Step>, EditError> {
+        Self::step(document, &mut self.redo, &mut self.undo, cursor)
+    }
+
+    /// Marks the current state as saved. The source of the document was replaced, so the
+    /// entries can't restore its formatting anymore.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.depth());
+        for entry in self.undo.iter_mut().chain(&mut self.redo) {
+            entry.edit.forget_source();
+        }
+    }
+
+    /// Whether the value differs from the one that was loaded or last saved.
+    pub fn is_modified(&self) -> bool {
+        self.saved != Some(self.depth())
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    #[test]
+    fn undo_redo() {
+        let source = "[1, [2, 3]]";
+        let mut document = Document::parse(source.to_string()).unwrap();
+        let mut history = History::new();
+
+        let inverse = document.apply(Edit::remove(vec![0])).unwrap();
+        history.record(inverse, [0, 1]);
+        let inverse = document.apply(Edit::toggle_collapse(vec![0])).unwrap();
+        history.record(inverse, [0, 4]);
+        assert!(history.is_modified());
+
+        let step = history.undo(&mut document, [0, 0]).unwrap().unwrap();
+        assert!(!step.changes_value);
+        assert_eq!(step.cursor, [0, 4]);
+        let step = history.undo(&mut document, [0, 0]).unwrap().unwrap();
+        assert_eq!((step.path, step.cursor), (vec![0], [0, 1]));
+        assert!(!history.is_modified());
+        assert_eq!(document.get().to_string(), "[\n    1,\n    [2,3]\n]");
+        assert!(history.undo(&mut document, [0, 0]).unwrap().is_none());
+
+        history.redo(&mut document, [0, 0]).unwrap();
+        history.mark_saved();
+        assert!(!history.is_modified());
+        history.undo(&mut document, [0, 0]).unwrap();
+        assert!(history.is_modified());
+
+        // a new change can't be redone back to the saved state
+        let inverse = document.apply(Edit::replace(vec![0], Value::Null)).unwrap();
+        history.record(inverse, [0, 0]);
+        assert!(history.redo(&mut document, [0, 0]).unwrap().is_none());
+        assert!(history.is_modified());
+    }
+}
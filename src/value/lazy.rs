@@ -0,0 +1,274 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use derive_more::IsVariant;
+use memchr::memchr2;
+
+use super::{
+    parser::{ParseError, ParseErrorKind, Scanner},
+    Value, ValueArray, ValueObject,
+};
+
+/// How often (in bytes) the index builder reports its progress.
+const PROGRESS_STEP: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IsVariant)]
+pub enum LazyKind {
+    Array,
+    Object,
+}
+
+/// A container that has not been parsed yet. `open` is the byte offset of its opening bracket in
+/// the source.
+#[derive(Debug, Clone, Copy)]
+pub struct LazyContainer {
+    pub kind: LazyKind,
+    pub open: usize,
+}
+
+/// The offsets of every pair of matching brackets in a document, ordered by the opening bracket.
+/// Building it is a single pass over the bytes that only tracks strings and nesting, which lets
+/// containers be skipped without parsing their content.
+#[derive(Debug, Clone, Default)]
+pub struct StructuralIndex {
+    containers: Vec<(usize, usize)>,
+}
+
+impl StructuralIndex {
+    /// Scans `bytes`, storing the number of bytes processed so far in `progress`.
+    pub fn build(bytes: &[u8], progress: &AtomicUsize) -> Result<Self, ParseError> {
+        let mut containers = Vec::new();
+        let mut stack: Vec<(u8, usize)> = Vec::new();
+        let mut next_report = PROGRESS_STEP;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            if pos >= next_report {
+                progress.store(pos, Ordering::Relaxed);
+                next_report = pos + PROGRESS_STEP;
+            }
+
+            match bytes[pos] {
+                b'"' => {
+                    // skip to the closing quote, jumping over escaped characters
+                    let open = pos;
+                    pos += 1;
+                    loop {
+                        let Some(i) = memchr2(b'"', b'\\', &bytes[pos..]) else {
+                            progress.store(bytes.len(), Ordering::Relaxed);
+                            return Err(ParseError::new(
+                                ParseErrorKind::Unclosed('"'),
+                                bytes,
+                                open,
+                            ));
+                        };
+                        pos += i + 1;
+                        if bytes[pos - 1] == b'"' {
+                            break;
+                        }
+                        pos += 1;
+                    }
+                    continue;
+                }
+                b @ (b'[' | b'{') => {
+                    stack.push((b, containers.len()));
+                    containers.push((pos, 0));
+                }
+                b @ (b']' | b'}') => {
+                    let expected = if b == b']' { b'[' } else { b'{' };
+                    match stack.pop() {
+                        Some((open, entry)) if open == expected => {
+                            containers[entry].1 = pos;
+                        }
+                        _ => {
+                            progress.store(pos, Ordering::Relaxed);
+                            return Err(ParseError::new(
+                                ParseErrorKind::Unexpected(b as char),
+                                bytes,
+                                pos,
+                            ));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        progress.store(bytes.len(), Ordering::Relaxed);
+
+        if let Some((open, entry)) = stack.pop() {
+            return Err(ParseError::new(
+                ParseErrorKind::Unclosed(open as char),
+                bytes,
+                containers[entry].0,
+            ));
+        }
+
+        Ok(Self { containers })
+    }
+
+    /// The offset of the bracket that closes the one at `open`.
+    pub fn close_of(&self, open: usize) -> Option<usize> {
+        self.containers
+            .binary_search_by_key(&open, |(open, _)| *open)
+            .ok()
+            .map(|i| self.containers[i].1)
+    }
+}
+
+/// Parses a single level of a container, nested containers are left as [`Value::Lazy`].
+struct ShallowParser<'s> {
+    scanner: Scanner<'s>,
+    index: &'s StructuralIndex,
+}
+
+impl<'s> ShallowParser<'s> {
+    fn new(bytes: &'s [u8], index: &'s StructuralIndex, pos: usize) -> Self {
+        let mut scanner = Scanner::new(bytes);
+        scanner.pos = pos;
+        Self { scanner, index }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.scanner.peek()? {
+            b @ (b'[' | b'{') => {
+                let open = self.scanner.pos;
+                let close = self.index.close_of(open).ok_or_else(|| {
+                    self.scanner
+                        .error_at(ParseErrorKind::Unclosed(b as char), open)
+                })?;
+                self.scanner.pos = close + 1;
+                Ok(Value::Lazy(LazyContainer {
+                    kind: if b == b'[' {
+                        LazyKind::Array
+                    } else {
+                        LazyKind::Object
+                    },
+                    open,
+                }))
+            }
+            _ => self.scanner.scalar(),
+        }
+    }
+
+    /// Calls `item` for every element until `close`, handling the separating commas.
+    fn items(
+        &mut self,
+        close: u8,
+        mut item: impl FnMut(&mut Self) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        if self.scanner.peek()? == close {
+            self.scanner.pos += 1;
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            match self.scanner.peek()? {
+                b',' => self.scanner.pos += 1,
+                b if b == close => {
+                    self.scanner.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.scanner.unexpected()),
+            }
+        }
+    }
+
+    fn container(&mut self) -> Result<Value, ParseError> {
+        match self.scanner.peek()? {
+            b'[' => {
+                self.scanner.pos += 1;
+                let mut arr = Vec::new();
+                self.items(b']', |p| {
+                    arr.push(p.value()?);
+                    Ok(())
+                })?;
+                Ok(Value::Array(ValueArray::new(arr)))
+            }
+            b'{' => {
+                self.scanner.pos += 1;
+                let mut map = Vec::new();
+                self.items(b'}', |p| {
+                    let key = p.scanner.key()?;
+                    map.push((key, p.value()?));
+                    Ok(())
+                })?;
+                Ok(Value::Object(ValueObject::from_entries(map)))
+            }
+            _ => Err(self.scanner.unexpected()),
+        }
+    }
+}
+
+/// Parses the top level container of `bytes`.
+pub fn parse_root(bytes: &[u8], index: &StructuralIndex) -> Result<Value, ParseError> {
+    let mut parser = ShallowParser::new(bytes, index, 0);
+    let value = parser.container()?;
+    if !parser.scanner.is_done()? {
+        return Err(parser.scanner.unexpected());
+    }
+    Ok(value)
+}
+
+/// Parses one level of a container that was previously skipped.
+pub fn parse_container(
+    bytes: &[u8],
+    index: &StructuralIndex,
+    container: LazyContainer,
+) -> Result<Value, ParseError> {
+    ShallowParser::new(bytes, index, container.open).container()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::{parse_container, parse_root, StructuralIndex};
+    use crate::value::Value;
+
+    fn index(s: &str) -> StructuralIndex {
+        StructuralIndex::build(s.as_bytes(), &AtomicUsize::new(0)).unwrap()
+    }
+
+    #[test]
+    fn build_index() {
+        let s = r#"{"a": [1, "]", {"b": "\"}"}], "c": {}}"#;
+        let index = index(s);
+        assert_eq!(index.close_of(0), Some(s.len() - 1));
+        assert_eq!(index.close_of(6), Some(s.find("}]").unwrap() + 1));
+        assert_eq!(index.close_of(1), None);
+
+        let progress = AtomicUsize::new(0);
+        StructuralIndex::build(s.as_bytes(), &progress).unwrap();
+        assert_eq!(progress.into_inner(), s.len());
+
+        let progress = AtomicUsize::new(0);
+        assert!(StructuralIndex::build(b"[1, 2", &progress).is_err());
+        assert!(StructuralIndex::build(b"[1, 2}", &progress).is_err());
+    }
+
+    #[test]
+    fn parse_lazily() {
+        let s = r#" {"a": [1, [2, 3]], "b": "x", "c": 1.5} "#;
+        let index = index(s);
+        let root = parse_root(s.as_bytes(), &index).unwrap();
+        let Some(Value::Lazy(a)) = root.get_path(&[0]) else {
+            panic!("`a` should not be parsed");
+        };
+        assert_eq!(root.get_path(&[1]).unwrap().get_string(), Some("x"));
+        assert_eq!(
+            root.get_path(&[2]).unwrap().get_number().unwrap().as_f64(),
+            1.5
+        );
+
+        let a = parse_container(s.as_bytes(), &index, *a).unwrap();
+        assert!(a.get_path(&[0]).unwrap().is_number());
+        assert!(a.get_path(&[1]).unwrap().is_lazy());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for s in [r#"{"a" 1}"#, "[1 2]", "[tru]", r#"{"a": 1} 1"#] {
+            assert!(parse_root(s.as_bytes(), &index(s)).is_err(), "{s}");
+        }
+    }
+}
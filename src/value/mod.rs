@@ -1,97 +1,330 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
+
 use derive_more::IsVariant;
+use smallvec::SmallVec;
 
-mod contained_value;
+pub mod document;
+pub mod edit;
+mod escape;
+pub mod history;
+pub mod lazy;
+pub mod number;
 mod parser;
+#[cfg(feature = "pest-parser")]
+pub mod pest_parser;
+pub mod source_map;
 pub mod writer;
 
-pub use contained_value::ContainedValue;
-pub use parser::parse;
+pub use document::Document;
+pub use escape::{escape, escape_control, unescape};
+pub use lazy::LazyContainer;
+pub use number::Number;
+pub use parser::{
+    parse, parse_lenient, parse_with, Extensions, ParseError, ParseErrorKind, ParseOptions, Parsed,
+};
 
-use self::writer::{Element, Writer};
+use self::writer::{Element, LayoutCache, Writer};
 
-// #[derive(Debug, Clone, Copy, IsVariant)]
-// pub enum Number {
-//     U64(u64),
-//     I64(i64),
-//     F64(f64),
-// }
-//
-// impl ToString {
-// }
+/// A comment kept by the lenient parser, `text` includes its delimiters. It is placed before the
+/// item at index `before` of its container (or after the last item when `before` is the length),
+/// `inline` comments were written on the same line as whatever precedes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub before: usize,
+    pub text: String,
+    pub inline: bool,
+}
 
-#[derive(Debug, Clone)]
-pub struct ValueArray<'a> {
+#[derive(Debug, Clone, Default)]
+pub struct ValueArray {
     pub collapse: bool,
-    pub arr: Vec<Value<'a>>,
+    pub arr: Vec<Value>,
+    pub comments: Vec<Comment>,
+    layout: LayoutCache,
 }
 
-impl<'a> ValueArray<'a> {
+impl ValueArray {
+    pub fn new(arr: Vec<Value>) -> Self {
+        Self {
+            collapse: false,
+            arr,
+            comments: Vec::new(),
+            layout: LayoutCache::default(),
+        }
+    }
+
     pub fn toggle_collapse(&mut self) {
+        self.layout.clear();
         self.collapse = !self.collapse;
     }
 
     pub fn collapse(&mut self) {
+        self.layout.clear();
         self.collapse = true;
     }
 
     pub fn expand(&mut self) {
+        self.layout.clear();
         self.collapse = false;
     }
-    pub fn get(&self, idx: usize) -> Option<&Value<'a>> {
+    pub fn get(&self, idx: usize) -> Option<&Value> {
         self.arr.get(idx)
     }
 
-    pub fn get_mut(&mut self, idx: usize) -> Option<&mut Value<'a>> {
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut Value> {
+        self.layout.clear();
         self.arr.get_mut(idx)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ValueObject<'a> {
+/// Objects with at least this many entries get a hash index on their first lookup.
+const INDEX_THRESHOLD: usize = 32;
+
+/// Objects keep their entries in order, including repeated keys. Keys are stored raw, lookups
+/// compare them with their escape sequences decoded, so `"a"` and `"\u0061"` are the same key.
+///
+/// Lookups in large objects go through a hash index that is built on first use. Entries can
+/// only be added, removed or renamed through methods that keep the index and the duplicate
+/// flag up to date, values can be mutated freely.
+#[derive(Debug, Clone, Default)]
+pub struct ValueObject {
     pub collapse: bool,
-    pub map: Vec<(&'a str, Value<'a>)>,
+    map: Vec<(String, Value)>,
+    pub comments: Vec<Comment>,
+    duplicate_keys: bool,
+    /// The positions of the entries of every key, in order.
+    index: OnceLock<HashMap<String, SmallVec<[usize; 1]>>>,
+    layout: LayoutCache,
 }
 
-impl<'a> ValueObject<'a> {
+impl ValueObject {
+    pub fn from_entries(map: Vec<(String, Value)>) -> Self {
+        let mut object = Self {
+            collapse: false,
+            map,
+            comments: Vec::new(),
+            duplicate_keys: false,
+            index: OnceLock::new(),
+            layout: LayoutCache::default(),
+        };
+        object.check_duplicates();
+        object
+    }
+
     pub fn toggle_collapse(&mut self) {
+        self.layout.clear();
         self.collapse = !self.collapse;
     }
 
     pub fn collapse(&mut self) {
+        self.layout.clear();
         self.collapse = true;
     }
 
     pub fn expand(&mut self) {
+        self.layout.clear();
         self.collapse = false;
     }
 
-    pub fn get(&self, key: &str) -> Option<&Value<'a>> {
-        self.map
-            .iter()
-            .find(|(k, _)| *k == key)
-            .map(|(_, item)| item)
+    pub fn entries(&self) -> &[(String, Value)] {
+        &self.map
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Whether two entries share a key.
+    pub fn has_duplicate_keys(&self) -> bool {
+        self.duplicate_keys
+    }
+
+    fn index(&self) -> Option<&HashMap<String, SmallVec<[usize; 1]>>> {
+        if self.map.len() < INDEX_THRESHOLD {
+            return None;
+        }
+        Some(self.index.get_or_init(|| {
+            let mut index = HashMap::<_, SmallVec<_>>::with_capacity(self.map.len());
+            for (i, (k, _)) in self.map.iter().enumerate() {
+                index.entry(unescape(k).into_owned()).or_default().push(i);
+            }
+            index
+        }))
+    }
+
+    /// The position of the first entry with `key`.
+    pub fn position(&self, key: &str) -> Option<usize> {
+        match self.index() {
+            Some(index) => index.get(key).map(|positions| positions[0]),
+            None => self.map.iter().position(|(k, _)| unescape(k) == key),
+        }
+    }
+
+    /// The positions of every entry with `key`, in order.
+    pub fn positions(&self, key: &str) -> Vec<usize> {
+        match self.index() {
+            Some(index) => index
+                .get(key)
+                .map_or_else(Vec::new, |positions| positions.to_vec()),
+            None if !self.duplicate_keys => self.position(key).into_iter().collect(),
+            None => (0..self.map.len())
+                .filter(|&i| unescape(&self.map[i].0) == key)
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.position(key).map(|i| &self.map[i].1)
     }
 
-    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value<'a>> {
-        self.map
-            .iter_mut()
-            .find(|(k, _)| *k == key)
-            .map(|(_, item)| item)
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.layout.clear();
+        self.position(key).map(|i| &mut self.map[i].1)
+    }
+
+    /// The value of the entry at `i`.
+    pub fn value_mut(&mut self, i: usize) -> Option<&mut Value> {
+        self.layout.clear();
+        self.map.get_mut(i).map(|(_, value)| value)
+    }
+
+    /// Every value stored under `key`, unlike [`ValueObject::get`] which only returns the first.
+    pub fn get_all<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.positions(key).into_iter().map(|i| &self.map[i].1)
+    }
+
+    /// Appends an entry, `key` is raw.
+    pub fn push(&mut self, key: String, value: Value) {
+        self.layout.clear();
+        let decoded = unescape(&key).into_owned();
+        // a large object gets its index now, so that the new entry is added to it
+        self.index();
+        match self.index.get_mut() {
+            Some(index) => {
+                let positions = index.entry(decoded).or_default();
+                self.duplicate_keys |= !positions.is_empty();
+                positions.push(self.map.len());
+            }
+            None => self.duplicate_keys |= self.position(&decoded).is_some(),
+        }
+        self.map.push((key, value));
+    }
+
+    /// Inserts an entry at `i`, `key` is raw.
+    pub fn insert(&mut self, i: usize, key: String, value: Value) {
+        self.map.insert(i, (key, value));
+        self.entries_changed();
+    }
+
+    pub fn remove(&mut self, i: usize) -> (String, Value) {
+        let entry = self.map.remove(i);
+        self.entries_changed();
+        entry
+    }
+
+    /// Replaces the key of the entry at `i`, returning the old one. `key` is raw.
+    pub fn rename(&mut self, i: usize, key: String) -> String {
+        let old = std::mem::replace(&mut self.map[i].0, key);
+        // the value moves when the key changes length, which can change its layout
+        self.map[i].1.clear_layout();
+        self.entries_changed();
+        old
+    }
+
+    fn entries_changed(&mut self) {
+        self.layout.clear();
+        self.index = OnceLock::new();
+        self.check_duplicates();
+    }
+
+    /// The decoded keys of the entries, `None` for the errors that recovering from a missing
+    /// comma or a bracket put between entries, which have no key of their own.
+    fn keys(&self) -> impl Iterator<Item = Option<Cow<'_, str>>> {
+        self.map.iter().map(|(k, v)| match (k.as_str(), v) {
+            ("", Value::Error(_)) => None,
+            (k, _) => Some(unescape(k)),
+        })
+    }
+
+    /// Updates the duplicate flag, the parsers call this once an object is complete.
+    fn check_duplicates(&mut self) {
+        // hashing only pays off for larger objects
+        self.duplicate_keys = if self.map.len() <= 16 {
+            let keys = self.keys().flatten().collect::<Vec<_>>();
+            (1..keys.len()).any(|i| keys[..i].contains(&keys[i]))
+        } else {
+            let mut seen = HashSet::with_capacity(self.map.len());
+            !self.keys().flatten().all(|key| seen.insert(key))
+        };
+    }
+
+    /// The indices of the entries whose key appears more than once, in order.
+    pub fn duplicates(&self) -> Vec<usize> {
+        if !self.duplicate_keys {
+            return Vec::new();
+        }
+        let mut counts = HashMap::new();
+        for key in self.keys().flatten() {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+        let keys = self.keys().collect::<Vec<_>>();
+        (0..self.map.len())
+            .filter(|&i| keys[i].as_ref().is_some_and(|key| counts[key] > 1))
+            .collect()
+    }
+}
+
+/// Drops values without recursing into nested containers, which would overflow the stack on
+/// deeply nested documents.
+fn drop_iteratively(mut values: Vec<Value>) {
+    while let Some(value) = values.pop() {
+        match value {
+            Value::Array(mut arr) => values.append(&mut arr.arr),
+            Value::Object(mut object) => values.extend(object.map.drain(..).map(|(_, v)| v)),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for ValueArray {
+    fn drop(&mut self) {
+        if self.arr.iter().any(Value::is_container) {
+            drop_iteratively(std::mem::take(&mut self.arr));
+        }
+    }
+}
+
+impl Drop for ValueObject {
+    fn drop(&mut self) {
+        if self.map.iter().any(|(_, value)| value.is_container()) {
+            drop_iteratively(self.map.drain(..).map(|(_, v)| v).collect());
+        }
     }
 }
 
-// TODO: add cache
 #[derive(Debug, Clone, IsVariant)]
-pub enum Value<'a> {
+pub enum Value {
     Null,
     Bool(bool),
-    Number(&'a str),
-    String(&'a str),
-    Array(ValueArray<'a>),
-    Object(ValueObject<'a>),
+    Number(Number),
+    String(String),
+    Array(ValueArray),
+    Object(ValueObject),
+    /// A container whose content is parsed only once it is expanded.
+    Lazy(LazyContainer),
+    /// A part of the document that couldn't be parsed, only produced when recovering.
+    Error(ParseError),
 }
 
-impl<'a> Value<'a> {
+impl Value {
     pub fn get_null(&self) -> Option<()> {
         match self {
             Self::Null => Some(()),
@@ -106,16 +339,24 @@ impl<'a> Value<'a> {
         }
     }
 
-    pub fn get_number(&self) -> Option<&str> {
+    pub fn get_number(&self) -> Option<&Number> {
         match self {
-            Self::Number(x) => Some(*x),
+            Self::Number(x) => Some(x),
             _ => None,
         }
     }
 
     pub fn get_string(&self) -> Option<&str> {
         match self {
-            Self::String(x) => Some(*x),
+            Self::String(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Like [`Value::get_string`], but with the escape sequences decoded.
+    pub fn get_decoded_string(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Self::String(x) => Some(unescape(x)),
             _ => None,
         }
     }
@@ -127,9 +368,12 @@ impl<'a> Value<'a> {
         }
     }
 
-    pub fn as_array_mut(&mut self) -> Option<&mut ValueArray<'a>> {
+    pub fn as_array_mut(&mut self) -> Option<&mut ValueArray> {
         match self {
-            Self::Array(x) => Some(x),
+            Self::Array(x) => {
+                x.layout.clear();
+                Some(x)
+            }
             _ => None,
         }
     }
@@ -141,13 +385,102 @@ impl<'a> Value<'a> {
         }
     }
 
-    pub fn as_object_mut(&mut self) -> Option<&mut ValueObject<'a>> {
+    pub fn as_object_mut(&mut self) -> Option<&mut ValueObject> {
         match self {
-            Self::Object(x) => Some(x),
+            Self::Object(x) => {
+                x.layout.clear();
+                Some(x)
+            }
             _ => None,
         }
     }
 
+    /// Follows a path of child indices, see [`writer::NodeSpan`].
+    pub fn get_path(&self, path: &[usize]) -> Option<&Value> {
+        path.iter().try_fold(self, |value, &i| match value {
+            Self::Array(arr) => arr.get(i),
+            Self::Object(object) => object.map.get(i).map(|(_, value)| value),
+            _ => None,
+        })
+    }
+
+    /// Like [`Value::get_path`], every container on the way is assumed to change and loses its
+    /// cached layout, including the value itself.
+    pub fn get_path_mut(&mut self, path: &[usize]) -> Option<&mut Value> {
+        let value = path.iter().try_fold(self, |value, &i| match value {
+            Self::Array(arr) => arr.get_mut(i),
+            Self::Object(object) => object.value_mut(i),
+            _ => None,
+        })?;
+        value.clear_layout();
+        Some(value)
+    }
+
+    fn clear_layout(&mut self) {
+        match self {
+            Self::Array(arr) => arr.layout.clear(),
+            Self::Object(object) => object.layout.clear(),
+            _ => {}
+        }
+    }
+
+    /// The paths of every value matching `predicate`, in document order.
+    pub fn find_paths(&self, predicate: impl Fn(&Value) -> bool) -> Vec<Vec<usize>> {
+        let mut found = Vec::new();
+        let mut path = Vec::new();
+        // (depth, index in the parent, value), children are pushed in reverse
+        let mut stack = vec![(0, None, self)];
+        while let Some((depth, index, value)) = stack.pop() {
+            path.truncate(depth);
+            path.extend(index);
+            if predicate(value) {
+                found.push(path.clone());
+            }
+            let depth = path.len();
+            match value {
+                Self::Array(arr) => stack.extend(
+                    arr.arr
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(i, v)| (depth, Some(i), v)),
+                ),
+                Self::Object(object) => stack.extend(
+                    object
+                        .map
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(i, (_, v))| (depth, Some(i), v)),
+                ),
+                _ => {}
+            }
+        }
+        found
+    }
+
+    /// The paths of every entry whose key is repeated in its object, in document order.
+    pub fn find_duplicate_keys(&self) -> Vec<Vec<usize>> {
+        let objects = self.find_paths(|value| value.as_object().is_some_and(|o| o.duplicate_keys));
+        let mut found = Vec::new();
+        for path in objects {
+            let object = self.get_path(&path).and_then(Value::as_object).unwrap();
+            found.extend(object.duplicates().into_iter().map(|i| {
+                let mut path = path.clone();
+                path.push(i);
+                path
+            }));
+        }
+        // entries of nested objects come before the later entries of their parents
+        found.sort();
+        found
+    }
+
+    /// Arrays, objects and containers that weren't parsed yet.
+    pub fn is_container(&self) -> bool {
+        matches!(self, Self::Array(_) | Self::Object(_) | Self::Lazy(_))
+    }
+
     pub fn lines(&self) -> Vec<String> {
         let mut writer = Writer::new();
         writer.write_value(self);
@@ -161,10 +494,78 @@ impl<'a> Value<'a> {
     }
 }
 
-impl ToString for Value<'_> {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut writer = Writer::new();
         writer.write_value(self);
-        writer.to_string()
+        writer.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(keys: impl IntoIterator<Item = String>) -> ValueObject {
+        ValueObject::from_entries(
+            keys.into_iter()
+                .enumerate()
+                .map(|(i, k)| (k, Value::Number(Number::parse(&i.to_string()).unwrap())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn object_index() {
+        for len in [4, 100] {
+            let mut object = numbered((0..len).map(|i| format!("k{i}")));
+            assert_eq!(object.get("k3").unwrap().to_string(), "3");
+            assert_eq!(object.position("k3"), Some(3));
+            assert!(object.get("x").is_none());
+            assert!(!object.has_duplicate_keys());
+
+            object.push("k1".into(), Value::Null);
+            object.push("\\u0078".into(), Value::Null);
+            assert!(object.has_duplicate_keys());
+            assert_eq!(object.positions("k1"), [1, len]);
+            assert_eq!(object.position("x"), Some(len + 1));
+
+            object.rename(1, "y".into());
+            assert!(!object.has_duplicate_keys());
+            assert_eq!(object.position("k1"), Some(len));
+            assert_eq!(object.position("y"), Some(1));
+
+            object.remove(0);
+            object.insert(0, "z".into(), Value::Null);
+            assert_eq!(object.position("z"), Some(0));
+            assert_eq!(object.position("k2"), Some(2));
+            assert!(object.get("k0").is_none());
+            // entries keep their order
+            assert_eq!(object.entries()[len].0, "k1");
+        }
+
+        // every lookup decodes the keys, with or without an index
+        for len in [4, 100] {
+            let keys = (0..len).map(|i| format!("k{i}"));
+            let object = numbered(keys.chain(["\\u0061".into(), "a".into()]));
+            let all = object
+                .get_all("a")
+                .map(Value::to_string)
+                .collect::<Vec<_>>();
+            assert_eq!(all, [len.to_string(), (len + 1).to_string()]);
+            assert_eq!(object.get("a").map(Value::to_string), Some(len.to_string()));
+            assert!(object.get("\\u0061").is_none());
+        }
+
+        // entries pushed before the first lookup are indexed
+        for len in [31, 100] {
+            let mut object = numbered((0..len).map(|i| format!("k{i}")));
+            object.push("new".into(), Value::Null);
+            object.push("k3".into(), Value::Null);
+            assert_eq!(object.position("new"), Some(len));
+            assert!(object.get("new").is_some_and(Value::is_null));
+            assert_eq!(object.positions("k3"), [3, len + 1]);
+            assert!(object.has_duplicate_keys());
+        }
     }
 }
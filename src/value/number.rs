@@ -0,0 +1,506 @@
+use std::{borrow::Cow, cmp::Ordering, fmt::Display};
+
+/// An exact decimal `digits * 10^exponent`. `digits` has no leading or trailing zeros, zero is
+/// represented by empty digits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigDecimal {
+    negative: bool,
+    digits: String,
+    exponent: i64,
+}
+
+impl BigDecimal {
+    /// Parses a JSON number lexeme. Returns `None` for anything that is not a number.
+    pub fn parse(lexeme: &str) -> Option<Self> {
+        let (negative, rest) = match lexeme.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, lexeme),
+        };
+        let (mantissa, exponent) = match rest.find(['e', 'E']) {
+            Some(i) => {
+                let exponent = &rest[i + 1..];
+                let exponent = exponent.strip_prefix('+').unwrap_or(exponent);
+                if exponent.is_empty() {
+                    return None;
+                }
+                // absurd exponents saturate instead of failing
+                let exponent = match exponent.parse::<i64>() {
+                    Ok(e) => e,
+                    Err(_) if exponent.starts_with('-') => i64::MIN / 2,
+                    Err(_) => i64::MAX / 2,
+                };
+                (&rest[..i], exponent)
+            }
+            None => (rest, 0),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if int.is_empty()
+            || !int.bytes().all(|b| b.is_ascii_digit())
+            || !frac.bytes().all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let digits = format!("{int}{frac}");
+        let digits = digits.trim_start_matches('0');
+        let trimmed = digits.trim_end_matches('0');
+        let exponent = exponent
+            .saturating_sub(frac.len() as i64)
+            .saturating_add((digits.len() - trimmed.len()) as i64);
+
+        if trimmed.is_empty() {
+            return Some(Self {
+                negative: false,
+                digits: String::new(),
+                exponent: 0,
+            });
+        }
+
+        Some(Self {
+            negative,
+            digits: trimmed.to_string(),
+            exponent,
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.exponent >= 0
+    }
+
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        match (self.is_zero(), other.is_zero()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            (false, false) => {}
+        }
+        // the position of the most significant digit decides first
+        let self_adjusted = self.digits.len() as i64 + self.exponent;
+        let other_adjusted = other.digits.len() as i64 + other.exponent;
+        self_adjusted
+            .cmp(&other_adjusted)
+            .then_with(|| self.digits.cmp(&other.digits))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        format!(
+            "{}{}e{}",
+            if self.negative { "-" } else { "" },
+            if self.is_zero() { "0" } else { &self.digits },
+            self.exponent
+        )
+        .parse()
+        .unwrap_or(f64::NAN)
+    }
+}
+
+impl Ord for BigDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+        }
+    }
+}
+
+impl PartialOrd for BigDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The most precise representation a number lexeme fits in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumberValue {
+    Integer(i128),
+    Float(f64),
+    BigDecimal(BigDecimal),
+}
+
+/// The number of significant decimal digits an `f64` is guaranteed to round trip.
+const F64_DIGITS: usize = 15;
+
+/// A JSON number. The original lexeme is kept for display so that numbers are never altered on
+/// output, while the parsed value is used for comparisons and arithmetic.
+#[derive(Debug, Clone)]
+pub struct Number {
+    lexeme: String,
+    value: NumberValue,
+}
+
+impl Number {
+    pub fn parse(lexeme: &str) -> Option<Self> {
+        let is_float_lexeme = lexeme.contains(['.', 'e', 'E']);
+
+        // fast path for the common case, without building a decimal first
+        let digits = lexeme.strip_prefix('-').unwrap_or(lexeme);
+        if !is_float_lexeme && !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(x) = lexeme.parse::<i128>() {
+                return Some(Self {
+                    lexeme: lexeme.to_string(),
+                    value: NumberValue::Integer(x),
+                });
+            }
+        }
+
+        let decimal = BigDecimal::parse(lexeme)?;
+        let value = if !is_float_lexeme {
+            NumberValue::BigDecimal(decimal)
+        } else {
+            let f = decimal.to_f64();
+            if decimal.digits.len() <= F64_DIGITS
+                && f.is_finite()
+                && (f != 0.0 || decimal.is_zero())
+            {
+                NumberValue::Float(f)
+            } else {
+                NumberValue::BigDecimal(decimal)
+            }
+        };
+
+        Some(Self {
+            lexeme: lexeme.to_string(),
+            value,
+        })
+    }
+
+    /// Like [`Number::parse`], but also accepts the JSON5 forms: hexadecimal integers, `NaN`,
+    /// `Infinity`, an explicit `+` and a leading or trailing decimal point. The lexeme is kept as
+    /// written.
+    pub fn parse_lenient(lexeme: &str) -> Option<Self> {
+        let (negative, unsigned) = match lexeme.as_bytes().first() {
+            Some(b'-') => (true, &lexeme[1..]),
+            Some(b'+') => (false, &lexeme[1..]),
+            _ => (false, lexeme),
+        };
+        let sign = if negative { "-" } else { "" };
+
+        let value = if unsigned == "Infinity" {
+            NumberValue::Float(if negative {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            })
+        } else if unsigned == "NaN" {
+            NumberValue::Float(f64::NAN)
+        } else if let Some(hex) = unsigned
+            .strip_prefix("0x")
+            .or_else(|| unsigned.strip_prefix("0X"))
+        {
+            if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let x = i128::from_str_radix(hex, 16).ok()?;
+            NumberValue::Integer(if negative { -x } else { x })
+        } else {
+            let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+                Some(i) => unsigned.split_at(i),
+                None => (unsigned, ""),
+            };
+            if mantissa.is_empty() || mantissa == "." {
+                return None;
+            }
+            let mantissa = match mantissa.strip_suffix('.') {
+                Some(int) if !int.is_empty() => format!("{int}.0"),
+                _ if mantissa.starts_with('.') => format!("0{mantissa}"),
+                _ => mantissa.to_string(),
+            };
+            Self::parse(&format!("{sign}{mantissa}{exponent}"))?.value
+        };
+
+        Some(Self {
+            lexeme: lexeme.to_string(),
+            value,
+        })
+    }
+
+    /// The result of a calculation. Integral values are written without a fraction, others in
+    /// their shortest form that round trips, and the values that aren't finite like JSON5 does.
+    pub fn from_f64(x: f64) -> Self {
+        if x.fract() == 0.0 && x.abs() < 1e17 {
+            return Self::from(x as i64);
+        }
+        let lexeme = if x.is_nan() {
+            "NaN".to_string()
+        } else if x.is_infinite() {
+            if x > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        } else {
+            format!("{x:?}")
+        };
+        Self {
+            lexeme,
+            value: NumberValue::Float(x),
+        }
+    }
+
+    pub fn lexeme(&self) -> &str {
+        &self.lexeme
+    }
+
+    pub fn value(&self) -> &NumberValue {
+        &self.value
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match &self.value {
+            NumberValue::Integer(_) => true,
+            NumberValue::Float(x) => x.fract() == 0.0,
+            NumberValue::BigDecimal(x) => x.is_integer(),
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match &self.value {
+            NumberValue::Integer(x) => (*x).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match &self.value {
+            NumberValue::Integer(x) => (*x).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        match &self.value {
+            NumberValue::Integer(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// The closest `f64`, may lose precision.
+    pub fn as_f64(&self) -> f64 {
+        match &self.value {
+            NumberValue::Integer(x) => *x as f64,
+            NumberValue::Float(x) => *x,
+            NumberValue::BigDecimal(x) => x.to_f64(),
+        }
+    }
+
+    /// `NaN` and the infinities, which only the lenient parser produces.
+    pub fn is_finite(&self) -> bool {
+        match &self.value {
+            NumberValue::Float(x) => x.is_finite(),
+            _ => true,
+        }
+    }
+
+    /// The number in standard JSON syntax: the lexeme unless it uses a JSON5 form. Numbers that
+    /// aren't finite have no JSON representation and are `None`.
+    pub fn to_json(&self) -> Option<Cow<'_, str>> {
+        if is_json_number(&self.lexeme) {
+            return Some(Cow::Borrowed(&self.lexeme));
+        }
+        Some(Cow::Owned(match &self.value {
+            NumberValue::Integer(x) => x.to_string(),
+            NumberValue::Float(x) if x.is_finite() => x.to_string(),
+            NumberValue::Float(_) => return None,
+            NumberValue::BigDecimal(x) if x.is_zero() => "0".to_string(),
+            NumberValue::BigDecimal(x) => format!(
+                "{}{}e{}",
+                if x.negative { "-" } else { "" },
+                x.digits,
+                x.exponent
+            ),
+        }))
+    }
+
+    /// The exact value of the number, `None` when it isn't finite.
+    pub fn to_decimal(&self) -> Option<BigDecimal> {
+        match &self.value {
+            NumberValue::Integer(x) => BigDecimal::parse(&x.to_string()),
+            // floats are only used when they round trip, so the shortest representation is exact
+            NumberValue::Float(x) if x.is_finite() => BigDecimal::parse(&x.to_string()),
+            NumberValue::Float(_) => None,
+            NumberValue::BigDecimal(x) => Some(x.clone()),
+        }
+    }
+}
+
+/// Whether `s` follows the JSON number grammar.
+fn is_json_number(s: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let int = digits(s);
+    if int == 0 || (int > 1 && s.starts_with('0')) {
+        return false;
+    }
+    let mut s = &s[int..];
+    if let Some(rest) = s.strip_prefix('.') {
+        let fraction = digits(rest);
+        if fraction == 0 {
+            return false;
+        }
+        s = &rest[fraction..];
+    }
+    if let Some(rest) = s.strip_prefix(['e', 'E']) {
+        let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+        let exponent = digits(rest);
+        if exponent == 0 {
+            return false;
+        }
+        s = &rest[exponent..];
+    }
+    s.is_empty()
+}
+
+impl From<i64> for Number {
+    fn from(x: i64) -> Self {
+        Self {
+            lexeme: x.to_string(),
+            value: NumberValue::Integer(x.into()),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+/// Numbers are ordered by their exact value, `1.10 == 1.1` and `1 == 1.0`. The infinities are
+/// the smallest and largest numbers, `NaN` comes after all of them.
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // rounding to the nearest f64 keeps the order, so different floats mean different values.
+        // Only equal ones, like a large integer and its rounding, need the exact comparison.
+        let float = |value: &NumberValue| match value {
+            NumberValue::Integer(x) => Some(*x as f64),
+            NumberValue::Float(x) if x.is_finite() => Some(*x),
+            _ => None,
+        };
+        if let (NumberValue::Integer(a), NumberValue::Integer(b)) = (&self.value, &other.value) {
+            return a.cmp(b);
+        }
+        if let (Some(a), Some(b)) = (float(&self.value), float(&other.value)) {
+            if a != b {
+                return a.total_cmp(&b);
+            }
+        }
+        match (self.to_decimal(), other.to_decimal()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (None, Some(_)) if self.as_f64() == f64::NEG_INFINITY => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => other.cmp(self).reverse(),
+            (None, None) => self.as_f64().total_cmp(&other.as_f64()),
+        }
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.lexeme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BigDecimal, Number, NumberValue};
+
+    fn n(s: &str) -> Number {
+        Number::parse(s).unwrap()
+    }
+
+    #[test]
+    fn representation() {
+        assert_eq!(n("42").value(), &NumberValue::Integer(42));
+        assert_eq!(n("-7").value(), &NumberValue::Integer(-7));
+        assert_eq!(
+            n("12345678901234567890").value(),
+            &NumberValue::Integer(12345678901234567890)
+        );
+        assert_eq!(n("1.5").value(), &NumberValue::Float(1.5));
+        assert_eq!(n("1e3").value(), &NumberValue::Float(1000.0));
+        assert!(matches!(
+            n("123456789012345678901234567890123456789012").value(),
+            NumberValue::BigDecimal(_)
+        ));
+        assert!(matches!(
+            n("0.12345678901234567890").value(),
+            NumberValue::BigDecimal(_)
+        ));
+        assert!(matches!(n("1e400").value(), NumberValue::BigDecimal(_)));
+        assert!(matches!(n("1e-400").value(), NumberValue::BigDecimal(_)));
+    }
+
+    #[test]
+    fn lexeme_is_preserved() {
+        assert_eq!(n("1.10").to_string(), "1.10");
+        assert_eq!(
+            n("12345678901234567890").to_string(),
+            "12345678901234567890"
+        );
+        assert_eq!(n("-0").to_string(), "-0");
+        assert_eq!(n("1E+2").to_string(), "1E+2");
+    }
+
+    #[test]
+    fn ordering() {
+        assert_eq!(n("1.10"), n("1.1"));
+        assert_eq!(n("1"), n("1.0"));
+        assert_eq!(n("100"), n("1e2"));
+        assert_eq!(n("0"), n("-0.0"));
+        assert!(n("2") > n("1.999999999999999999999"));
+        assert!(n("-2") < n("-1.5"));
+        assert!(n("0.001") < n("0.01"));
+        assert!(n("12345678901234567891") > n("12345678901234567890"));
+        assert!(n("1e400") > n("99999999999999999999999999999999999999999"));
+        assert!(n("-1e400") < n("-1"));
+        // the same f64, but not the same number
+        assert!(n("100000000000000001") > n("1e17"));
+        assert!(n("-100000000000000001") < n("-1e17"));
+        assert_eq!(n("100000000000000000"), n("1e17"));
+    }
+
+    #[test]
+    fn lenient() {
+        let n = |s: &str| Number::parse_lenient(s).unwrap();
+        assert_eq!(n("0x1F").value(), &NumberValue::Integer(31));
+        assert_eq!(n("-0xff").value(), &NumberValue::Integer(-255));
+        assert_eq!(n("+1").value(), &NumberValue::Integer(1));
+        assert_eq!(n(".5").value(), &NumberValue::Float(0.5));
+        assert_eq!(n("5.").value(), &NumberValue::Float(5.0));
+        assert_eq!(n("-.5e1").value(), &NumberValue::Float(-5.0));
+        assert_eq!(n("0x1F").to_string(), "0x1F");
+        assert!(!n("NaN").is_finite());
+        assert!(n("-Infinity") < n("-1e400"));
+        assert!(n("Infinity") > n("1e400"));
+        assert!(n("NaN") > n("Infinity"));
+        assert_eq!(n("0x10"), n("16.0"));
+        assert!(Number::parse_lenient("0x").is_none());
+        assert!(Number::parse_lenient(".").is_none());
+        assert!(Number::parse("0x1F").is_none());
+
+        assert_eq!(n("0x1F").to_json().unwrap(), "31");
+        assert_eq!(n("+1.5").to_json().unwrap(), "1.5");
+        assert_eq!(n("-1.5e-3").to_json().unwrap(), "-1.5e-3");
+        assert_eq!(n("+1e400").to_json().unwrap(), "1e400");
+        assert_eq!(n("Infinity").to_json(), None);
+    }
+
+    #[test]
+    fn decimal() {
+        assert!(BigDecimal::parse("abc").is_none());
+        assert!(BigDecimal::parse("1e").is_none());
+        assert!(BigDecimal::parse("0.0").unwrap().is_zero());
+        assert!(BigDecimal::parse("1.50e1").unwrap().is_integer());
+        assert_eq!(BigDecimal::parse("2.5").unwrap().to_f64(), 2.5);
+    }
+}
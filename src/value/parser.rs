@@ -1,51 +1,1195 @@
-use pest::{error::Error, iterators::Pair, Parser};
-use pest_derive::Parser;
-
-#[derive(Parser)]
-#[grammar = "value/value.pest"]
-struct ValueParser;
-
-use super::{Value, ValueArray, ValueObject};
-
-fn parse_value<'a>(pair: Pair<'a, Rule>) -> Value<'a> {
-    match pair.as_rule() {
-        Rule::object => Value::Object(ValueObject {
-            collapse: false,
-            map: pair
-                .into_inner()
-                .map(|pair| {
-                    let mut inner_rules = pair.into_inner();
-                    let name = inner_rules
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str();
-                    let value = parse_value(inner_rules.next().unwrap());
-                    (name, value)
-                })
-                .collect(),
-        }),
-        Rule::array => Value::Array(ValueArray {
-            collapse: false,
-            arr: pair.into_inner().map(parse_value).collect(),
-        }),
-        Rule::string => Value::String(pair.into_inner().next().unwrap().as_str()),
-        Rule::number => Value::Number(pair.as_span().as_str()),
-        Rule::bool => Value::Bool(pair.as_str().parse().unwrap()),
-        Rule::null => Value::Null,
-        Rule::json
-        | Rule::EOI
-        | Rule::pair
-        | Rule::value
-        | Rule::inner
-        | Rule::char
-        | Rule::WHITESPACE => unreachable!(),
-    }
-}
-
-pub fn parse<'a>(s: &'a str) -> Result<Value<'a>, Error<Rule>> {
-    let json = ValueParser::parse(Rule::json, s)?.next().unwrap();
-    Ok(parse_value(json))
+use std::{cell::RefCell, ops::Range};
+
+use memchr::{memchr2, memchr3, memmem};
+
+use super::{
+    source_map::{ItemOrigin, SourceItem, SourceSpan},
+    Comment, Number, Value, ValueArray, ValueObject,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ParseErrorKind {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("unexpected `{0}`")]
+    Unexpected(char),
+    #[error("unclosed `{0}`")]
+    Unclosed(char),
+    #[error("unclosed comment")]
+    UnclosedComment,
+    #[error("invalid escape sequence")]
+    InvalidEscape,
+    #[error("invalid number")]
+    InvalidNumber,
+    #[error("invalid literal")]
+    InvalidLiteral,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} at line {line}, column {column}")]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Byte offset into the source.
+    pub offset: usize,
+    /// 1-based.
+    pub line: usize,
+    /// 1-based, in characters.
+    pub column: usize,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, source: &[u8], offset: usize) -> Self {
+        Lines::default().error(kind, source, offset)
+    }
+}
+
+/// Finds the line and column of offsets in a source, its lines are only counted once however
+/// many errors there are.
+#[derive(Debug, Default)]
+struct Lines {
+    /// Where the lines after the first one start, up to `scanned`.
+    starts: Vec<usize>,
+    scanned: usize,
+    /// The last offset a column was counted for and that column, on long lines the next one is
+    /// counted from there.
+    last: (usize, usize),
+}
+
+impl Lines {
+    fn error(&mut self, kind: ParseErrorKind, source: &[u8], offset: usize) -> ParseError {
+        let offset = offset.min(source.len());
+        if offset > self.scanned {
+            let newlines = memchr::memchr_iter(b'\n', &source[self.scanned..offset]);
+            self.starts.extend(newlines.map(|i| self.scanned + i + 1));
+            self.scanned = offset;
+        }
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let line_start = line.checked_sub(1).map_or(0, |i| self.starts[i]);
+        let (from, column) = match self.last {
+            (last, column) if line_start <= last && last <= offset => (last, column),
+            _ => (line_start, 0),
+        };
+        let column = column
+            + String::from_utf8_lossy(&source[from..offset])
+                .chars()
+                .count();
+        self.last = (offset, column);
+        ParseError {
+            kind,
+            offset,
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+}
+
+/// The extensions to JSON that the lenient parser came across.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// `// line` and `/* block */` comments.
+    pub comments: bool,
+    pub trailing_commas: bool,
+    pub single_quotes: bool,
+    pub unquoted_keys: bool,
+    pub hex_numbers: bool,
+    /// `NaN` and `Infinity`.
+    pub special_numbers: bool,
+    /// An explicit `+`, or a leading or trailing decimal point.
+    pub loose_numbers: bool,
+    /// Escapes only JSON5 has, like `\x41`, `\'` and escaped line breaks.
+    pub string_escapes: bool,
+}
+
+impl Extensions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.comments, "comments"),
+            (self.trailing_commas, "trailing commas"),
+            (self.single_quotes, "single quoted strings"),
+            (self.unquoted_keys, "unquoted keys"),
+            (self.hex_numbers, "hexadecimal numbers"),
+            (self.special_numbers, "NaN/Infinity"),
+            (self.loose_numbers, "loose number syntax"),
+            (self.string_escapes, "JSON5 escapes"),
+        ]
+        .into_iter()
+        .filter(|(used, _)| *used)
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Accept JSONC and JSON5.
+    pub lenient: bool,
+    /// Keep parsing after an error, see [`parse_with`].
+    pub recover: bool,
+}
+
+/// The result of [`parse_with`]. `comments` are the ones around the root value, `before` is `0`
+/// for the ones preceding it and `1` for the ones following it. `errors` lists every error that
+/// was recovered from.
+#[derive(Debug, Clone)]
+pub struct Parsed {
+    pub value: Value,
+    pub comments: Vec<Comment>,
+    pub extensions: Extensions,
+    pub errors: Vec<ParseError>,
+}
+
+/// Byte level building blocks shared by the parsers.
+pub(crate) struct Scanner<'s> {
+    bytes: &'s [u8],
+    /// Set when the bytes are known to be valid UTF-8.
+    text: Option<&'s str>,
+    pub pos: usize,
+    /// Accept JSONC and JSON5.
+    lenient: bool,
+    extensions: Extensions,
+    /// Comments that were skipped but not placed in a container yet.
+    comments: Vec<Comment>,
+    recover: bool,
+    errors: Vec<ParseError>,
+    lines: RefCell<Lines>,
+    /// The quote of the string being scanned, it is still set when the string had an error.
+    in_string: Option<u8>,
+}
+
+impl<'s> Scanner<'s> {
+    pub fn new(bytes: &'s [u8]) -> Self {
+        Self {
+            bytes,
+            text: None,
+            pos: 0,
+            lenient: false,
+            extensions: Extensions::default(),
+            comments: Vec::new(),
+            recover: false,
+            errors: Vec::new(),
+            lines: RefCell::default(),
+            in_string: None,
+        }
+    }
+
+    pub fn from_str(text: &'s str) -> Self {
+        Self {
+            text: Some(text),
+            ..Self::new(text.as_bytes())
+        }
+    }
+
+    pub fn with_options(text: &'s str, options: ParseOptions) -> Self {
+        Self {
+            lenient: options.lenient,
+            recover: options.recover,
+            ..Self::from_str(text)
+        }
+    }
+
+    pub fn error_at(&self, kind: ParseErrorKind, offset: usize) -> ParseError {
+        self.lines.borrow_mut().error(kind, self.bytes, offset)
+    }
+
+    pub fn error(&self, kind: ParseErrorKind) -> ParseError {
+        self.error_at(kind, self.pos)
+    }
+
+    /// An `Unexpected` error for the current byte, or `UnexpectedEnd`.
+    pub fn unexpected(&self) -> ParseError {
+        match self.bytes.get(self.pos) {
+            Some(_) => {
+                let c = String::from_utf8_lossy(
+                    &self.bytes[self.pos..(self.pos + 4).min(self.bytes.len())],
+                )
+                .chars()
+                .next()
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+                self.error(ParseErrorKind::Unexpected(c))
+            }
+            None => self.error(ParseErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn text(&self, range: Range<usize>) -> String {
+        match self.text {
+            Some(text) => text[range].to_string(),
+            None => String::from_utf8_lossy(&self.bytes[range]).into_owned(),
+        }
+    }
+
+    /// Skips whitespace, and comments when lenient.
+    pub fn skip_whitespace(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        loop {
+            let rest = &self.bytes[self.pos..];
+            match rest.first() {
+                Some(b' ' | b'\t' | b'\n' | b'\r') => self.pos += 1,
+                _ if !self.lenient => return Ok(()),
+                Some(b'\x0b' | b'\x0c') => self.pos += 1,
+                // non-breaking space and byte order mark
+                _ if rest.starts_with("\u{a0}".as_bytes()) => self.pos += 2,
+                _ if rest.starts_with("\u{feff}".as_bytes()) => self.pos += 3,
+                Some(b'/') => {
+                    if !self.comment(start)? {
+                        return Ok(());
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Skips the comment at the current position, if there is one. `start` is where the
+    /// whitespace before it started.
+    fn comment(&mut self, start: usize) -> Result<bool, ParseError> {
+        let open = self.pos;
+        let end = match self.bytes.get(open + 1) {
+            Some(b'/') => memchr::memchr(b'\n', &self.bytes[open..])
+                .map(|i| open + i)
+                .unwrap_or(self.bytes.len()),
+            Some(b'*') => memmem::find(&self.bytes[open + 2..], b"*/")
+                .map(|i| open + 2 + i + 2)
+                .ok_or_else(|| self.error_at(ParseErrorKind::UnclosedComment, open))?,
+            _ => return Ok(false),
+        };
+        self.pos = end;
+        self.extensions.comments = true;
+        self.comments.push(Comment {
+            before: 0,
+            text: self.text(open..end).trim_end().to_string(),
+            inline: start > 0 && !self.bytes[start..open].contains(&b'\n'),
+        });
+        Ok(true)
+    }
+
+    /// Moves the pending comments into `comments`, placing them before the item at `before`.
+    fn attach_comments(&mut self, comments: &mut Vec<Comment>, before: usize) {
+        comments.extend(self.comments.drain(..).map(|c| Comment { before, ..c }));
+    }
+
+    /// The next non whitespace byte.
+    pub fn peek(&mut self) -> Result<u8, ParseError> {
+        self.skip_whitespace()?;
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| self.error(ParseErrorKind::UnexpectedEnd))
+    }
+
+    pub fn is_done(&mut self) -> Result<bool, ParseError> {
+        self.skip_whitespace()?;
+        Ok(self.pos >= self.bytes.len())
+    }
+
+    pub fn expect(&mut self, c: u8) -> Result<(), ParseError> {
+        if self.peek()? == c {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Scans a string starting at its opening quote and returns the range of its raw content.
+    pub fn string(&mut self) -> Result<Range<usize>, ParseError> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        self.in_string = Some(b'"');
+        loop {
+            let Some(i) = memchr2(b'"', b'\\', &self.bytes[self.pos..]) else {
+                return Err(self.error_at(ParseErrorKind::Unclosed('"'), start - 1));
+            };
+            self.pos += i;
+            if self.bytes[self.pos] == b'"' {
+                self.pos += 1;
+                self.in_string = None;
+                return Ok(start..self.pos - 1);
+            }
+
+            // escape sequence
+            match self.bytes.get(self.pos + 1) {
+                Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => self.pos += 2,
+                Some(b'u') => {
+                    self.unicode_escape()?;
+                }
+                Some(_) => return Err(self.error(ParseErrorKind::InvalidEscape)),
+                None => return Err(self.error_at(ParseErrorKind::Unclosed('"'), start - 1)),
+            }
+        }
+    }
+
+    /// Skips a `\uXXXX` escape and returns it.
+    fn unicode_escape(&mut self) -> Result<&'s [u8], ParseError> {
+        let escape = self.bytes.get(self.pos..self.pos + 6);
+        match escape {
+            Some(escape) if escape[2..].iter().all(u8::is_ascii_hexdigit) => {
+                self.pos += 6;
+                Ok(escape)
+            }
+            _ => Err(self.error(ParseErrorKind::InvalidEscape)),
+        }
+    }
+
+    /// Scans a string and returns its raw content.
+    pub fn string_value(&mut self) -> Result<String, ParseError> {
+        if self.lenient {
+            return self.lenient_string();
+        }
+        let range = self.string()?;
+        Ok(self.text(range))
+    }
+
+    /// Scans a single or double quoted JSON5 string, rewriting its content so that it is a valid
+    /// raw JSON string.
+    fn lenient_string(&mut self) -> Result<String, ParseError> {
+        let open = self.pos;
+        let quote = self.bytes[open];
+        if quote == b'\'' {
+            self.extensions.single_quotes = true;
+        }
+        self.pos += 1;
+        self.in_string = Some(quote);
+
+        let mut out = Vec::new();
+        loop {
+            let rest = &self.bytes[self.pos..];
+            let Some(i) = memchr3(quote, b'\\', b'"', rest) else {
+                return Err(self.error_at(ParseErrorKind::Unclosed(quote as char), open));
+            };
+            out.extend_from_slice(&rest[..i]);
+            self.pos += i;
+            match self.bytes[self.pos] {
+                b if b == quote => {
+                    self.pos += 1;
+                    self.in_string = None;
+                    break;
+                }
+                // a double quote inside of a single quoted string
+                b'"' => {
+                    out.extend_from_slice(b"\\\"");
+                    self.pos += 1;
+                }
+                _ => self.lenient_escape(&mut out, open, quote)?,
+            }
+        }
+        Ok(match String::from_utf8(out) {
+            Ok(s) => s,
+            Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+        })
+    }
+
+    fn lenient_escape(
+        &mut self,
+        out: &mut Vec<u8>,
+        open: usize,
+        quote: u8,
+    ) -> Result<(), ParseError> {
+        let rest = &self.bytes[self.pos + 1..];
+        let Some(&c) = rest.first() else {
+            return Err(self.error_at(ParseErrorKind::Unclosed(quote as char), open));
+        };
+        match c {
+            b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {
+                out.extend_from_slice(&[b'\\', c]);
+                self.pos += 2;
+                return Ok(());
+            }
+            b'u' => {
+                out.extend_from_slice(self.unicode_escape()?);
+                return Ok(());
+            }
+            b'1'..=b'9' => return Err(self.error(ParseErrorKind::InvalidEscape)),
+            _ => {}
+        }
+
+        self.extensions.string_escapes = true;
+        match c {
+            b'x' => {
+                let hex = rest.get(1..3);
+                if !hex.is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) {
+                    return Err(self.error(ParseErrorKind::InvalidEscape));
+                }
+                out.extend_from_slice(b"\\u00");
+                out.extend_from_slice(hex.unwrap());
+                self.pos += 4;
+            }
+            b'0' if !rest.get(1).is_some_and(u8::is_ascii_digit) => {
+                out.extend_from_slice(b"\\u0000");
+                self.pos += 2;
+            }
+            b'0' => return Err(self.error(ParseErrorKind::InvalidEscape)),
+            b'v' => {
+                out.extend_from_slice(b"\\u000b");
+                self.pos += 2;
+            }
+            b'\'' => {
+                out.push(b'\'');
+                self.pos += 2;
+            }
+            // escaped line breaks are removed
+            b'\n' => self.pos += 2,
+            b'\r' if rest.get(1) == Some(&b'\n') => self.pos += 3,
+            b'\r' => self.pos += 2,
+            _ if rest.starts_with("\u{2028}".as_bytes())
+                || rest.starts_with("\u{2029}".as_bytes()) =>
+            {
+                self.pos += 4
+            }
+            // any other character stands for itself, it is copied with the rest of the string
+            _ => self.pos += 1,
+        }
+        Ok(())
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    pub fn number(&mut self) -> Result<Number, ParseError> {
+        let start = self.pos;
+        let invalid = |s: &Self| s.error_at(ParseErrorKind::InvalidNumber, start);
+
+        if self.bytes.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        match self.bytes.get(self.pos) {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => {
+                self.digits();
+            }
+            _ => return Err(invalid(self)),
+        }
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if self.digits() == 0 {
+                return Err(invalid(self));
+            }
+        }
+        self.exponent().ok_or_else(|| invalid(self))?;
+
+        // the lexeme is ASCII
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(Number::parse)
+            .ok_or_else(|| invalid(self))
+    }
+
+    /// Skips an optional exponent, `None` if it has no digits.
+    fn exponent(&mut self) -> Option<()> {
+        if matches!(self.bytes.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.bytes.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn lenient_number(&mut self) -> Result<Number, ParseError> {
+        let start = self.pos;
+        let invalid = |s: &Self| s.error_at(ParseErrorKind::InvalidNumber, start);
+
+        match self.bytes.get(self.pos) {
+            Some(b'+') => {
+                self.extensions.loose_numbers = true;
+                self.pos += 1;
+            }
+            Some(b'-') => self.pos += 1,
+            _ => {}
+        }
+
+        let rest = &self.bytes[self.pos..];
+        if rest.starts_with(b"Infinity") || rest.starts_with(b"NaN") {
+            self.extensions.special_numbers = true;
+            self.pos += if rest[0] == b'I' { 8 } else { 3 };
+        } else if rest.starts_with(b"0x") || rest.starts_with(b"0X") {
+            self.extensions.hex_numbers = true;
+            self.pos += 2;
+            let digits = self.pos;
+            while self.bytes.get(self.pos).is_some_and(u8::is_ascii_hexdigit) {
+                self.pos += 1;
+            }
+            if self.pos == digits {
+                return Err(invalid(self));
+            }
+        } else {
+            let int = match self.bytes.get(self.pos) {
+                Some(b'0') => {
+                    self.pos += 1;
+                    1
+                }
+                _ => self.digits(),
+            };
+            if self.bytes.get(self.pos) == Some(&b'.') {
+                self.pos += 1;
+                let frac = self.digits();
+                if int == 0 && frac == 0 {
+                    return Err(invalid(self));
+                }
+                if int == 0 || frac == 0 {
+                    self.extensions.loose_numbers = true;
+                }
+            } else if int == 0 {
+                return Err(invalid(self));
+            }
+            self.exponent().ok_or_else(|| invalid(self))?;
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(Number::parse_lenient)
+            .ok_or_else(|| invalid(self))
+    }
+
+    fn keyword(&mut self, keyword: &[u8], value: Value) -> Result<Value, ParseError> {
+        if self.bytes[self.pos..].starts_with(keyword) {
+            self.pos += keyword.len();
+            Ok(value)
+        } else {
+            Err(self.error(ParseErrorKind::InvalidLiteral))
+        }
+    }
+
+    /// Strings, numbers, booleans and null.
+    pub fn scalar(&mut self) -> Result<Value, ParseError> {
+        match self.peek()? {
+            b'"' => self.string_value().map(Value::String),
+            b'\'' if self.lenient => self.string_value().map(Value::String),
+            b'-' | b'+' | b'.' | b'0'..=b'9' | b'I' | b'N' if self.lenient => {
+                self.lenient_number().map(Value::Number)
+            }
+            b'-' | b'0'..=b'9' => self.number().map(Value::Number),
+            b't' => self.keyword(b"true", Value::Bool(true)),
+            b'f' => self.keyword(b"false", Value::Bool(false)),
+            b'n' => self.keyword(b"null", Value::Null),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// An unquoted JSON5 key.
+    fn identifier(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80)
+        {
+            self.pos += 1;
+        }
+        if self.pos == start || self.bytes[start].is_ascii_digit() {
+            self.pos = start;
+            return None;
+        }
+        Some(self.text(start..self.pos))
+    }
+
+    /// An object key followed by its colon.
+    pub fn key(&mut self) -> Result<String, ParseError> {
+        let key = match self.peek()? {
+            b'"' => self.string_value()?,
+            b'\'' if self.lenient => self.string_value()?,
+            _ if self.lenient => match self.identifier() {
+                Some(key) => {
+                    self.extensions.unquoted_keys = true;
+                    key
+                }
+                None => return Err(self.unexpected()),
+            },
+            _ => return Err(self.unexpected()),
+        };
+        self.expect(b':')?;
+        Ok(key)
+    }
+}
+
+/// A container that is still being parsed.
+enum Frame {
+    Array(ValueArray),
+    /// The object so far and the key of the value being parsed.
+    Object(ValueObject, String),
+}
+
+impl Frame {
+    fn len(&self) -> usize {
+        match self {
+            Self::Array(arr) => arr.arr.len(),
+            Self::Object(object, _) => object.map.len(),
+        }
+    }
+
+    fn close(&self) -> u8 {
+        match self {
+            Self::Array(_) => b']',
+            Self::Object(..) => b'}',
+        }
+    }
+
+    fn comments_mut(&mut self) -> &mut Vec<Comment> {
+        match self {
+            Self::Array(arr) => &mut arr.comments,
+            Self::Object(object, _) => &mut object.comments,
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        match self {
+            Self::Array(arr) => arr.arr.push(value),
+            Self::Object(object, key) => object.map.push((std::mem::take(key), value)),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Self::Array(arr) => Value::Array(arr),
+            Self::Object(mut object, _) => {
+                object.check_duplicates();
+                Value::Object(object)
+            }
+        }
+    }
+}
+
+impl Scanner<'_> {
+    /// Records the error, unless it was already recorded. Returns whether it is new.
+    fn record(&mut self, err: &ParseError) -> bool {
+        if self
+            .errors
+            .last()
+            .is_some_and(|last| last.offset == err.offset)
+        {
+            return false;
+        }
+        self.errors.push(err.clone());
+        true
+    }
+
+    /// Skips to the end of the string whose content starts at the current position.
+    fn skip_string(&mut self, quote: u8) {
+        while let Some(i) = memchr2(quote, b'\\', &self.bytes[self.pos..]) {
+            self.pos += i + 1;
+            if self.bytes[self.pos - 1] == quote {
+                return;
+            }
+            // the escaped character
+            self.pos += 1;
+        }
+        self.pos = self.bytes.len();
+    }
+
+    /// Skips to the next `,`, `]` or `}` outside of strings, the places parsing can continue from
+    /// after an error. An error inside of a string skips the rest of it first, unless the string
+    /// isn't closed.
+    fn skip_to_separator(&mut self, err: &ParseError) {
+        if let Some(quote) = self.in_string.take() {
+            if !matches!(err.kind, ParseErrorKind::Unclosed(_)) {
+                self.skip_string(quote);
+            }
+        }
+        let lenient = self.lenient;
+        let stops = |b: &u8| matches!(b, b',' | b']' | b'}' | b'"') || (lenient && *b == b'\'');
+        while let Some(i) = self.bytes[self.pos..].iter().position(stops) {
+            self.pos += i;
+            match self.bytes[self.pos] {
+                quote @ (b'"' | b'\'') => {
+                    self.pos += 1;
+                    self.skip_string(quote);
+                }
+                _ => return,
+            }
+        }
+        self.pos = self.bytes.len();
+    }
+
+    /// Turns an error into a [`Value::Error`] and skips the rest of the value, or returns it
+    /// when not recovering.
+    fn recover(&mut self, err: ParseError) -> Result<Value, ParseError> {
+        if !self.recover {
+            return Err(err);
+        }
+        self.record(&err);
+        self.skip_to_separator(&err);
+        Ok(Value::Error(err))
+    }
+}
+
+/// Records where the values are in the source while parsing, see [`parse_spans`]. Does nothing
+/// unless enabled.
+#[derive(Default)]
+struct SpanRecorder {
+    enabled: bool,
+    /// The containers being parsed: their key, where they start and their items so far.
+    frames: Vec<(Option<Range<usize>>, usize, Vec<SourceItem>)>,
+    /// The key of the value being parsed.
+    key: Option<Range<usize>>,
+    /// Where the value being parsed starts.
+    start: usize,
+    /// The last value that was completed, with its key.
+    value: Option<(Option<Range<usize>>, SourceSpan)>,
+}
+
+impl SpanRecorder {
+    fn open(&mut self) {
+        if self.enabled {
+            self.frames.push((self.key.take(), self.start, Vec::new()));
+        }
+    }
+
+    /// Completes a value that isn't the content of a container opened with [`Self::open`].
+    fn leaf(&mut self, bytes: &[u8], start: usize, end: usize) {
+        if self.enabled {
+            let span = SourceSpan::new(bytes, start..end, Vec::new());
+            self.value = Some((self.key.take(), span));
+        }
+    }
+
+    /// Completes the innermost container.
+    fn close(&mut self, bytes: &[u8], end: usize) {
+        if self.enabled {
+            let (key, start, items) = self.frames.pop().unwrap();
+            self.value = Some((key, SourceSpan::new(bytes, start..end, items)));
+        }
+    }
+
+    /// Adds the completed value to the innermost container.
+    fn push(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        let (key, span) = self.value.take().unwrap();
+        let (_, start, items) = self.frames.last_mut().unwrap();
+        let prefix_start = items
+            .last()
+            .and_then(|item| item.origin.as_ref())
+            .map_or(*start + 1, |origin| origin.value.end);
+        items.push(SourceItem {
+            origin: Some(ItemOrigin {
+                prefix_start,
+                key,
+                value: span.range.clone(),
+                separator_end: None,
+            }),
+            key_changed: false,
+            value: Some(span),
+        });
+    }
+
+    /// Adds an error that takes no space in the source to the innermost container.
+    fn push_error(&mut self, bytes: &[u8], pos: usize) {
+        self.key = None;
+        self.leaf(bytes, pos, pos);
+        self.push();
+    }
+}
+
+/// Parses the next item of the innermost container, or the root value. Returns `None` after
+/// opening a container that isn't empty.
+fn parse_item(
+    scanner: &mut Scanner<'_>,
+    stack: &mut Vec<Frame>,
+    root_comments: &mut Vec<Comment>,
+    spans: &mut SpanRecorder,
+) -> Result<Option<Value>, ParseError> {
+    if spans.enabled {
+        scanner.skip_whitespace()?;
+        spans.start = scanner.pos;
+    }
+    if let Some(Frame::Object(_, key)) = stack.last_mut() {
+        *key = scanner.key()?;
+        if spans.enabled {
+            spans.key = Some(spans.start..scanner.pos);
+        }
+    }
+    let next = scanner.peek()?;
+    spans.start = scanner.pos;
+    match stack.last_mut() {
+        Some(frame) => {
+            let len = frame.len();
+            scanner.attach_comments(frame.comments_mut(), len);
+        }
+        None => scanner.attach_comments(root_comments, 0),
+    }
+
+    let value = match next {
+        b'[' => {
+            scanner.pos += 1;
+            if scanner.peek()? != b']' {
+                stack.push(Frame::Array(ValueArray::default()));
+                spans.open();
+                return Ok(None);
+            }
+            scanner.pos += 1;
+            let mut arr = ValueArray::default();
+            scanner.attach_comments(&mut arr.comments, 0);
+            Value::Array(arr)
+        }
+        b'{' => {
+            scanner.pos += 1;
+            if scanner.peek()? != b'}' {
+                stack.push(Frame::Object(ValueObject::default(), String::new()));
+                spans.open();
+                return Ok(None);
+            }
+            scanner.pos += 1;
+            let mut object = ValueObject::default();
+            scanner.attach_comments(&mut object.comments, 0);
+            Value::Object(object)
+        }
+        _ => scanner.scalar()?,
+    };
+    Ok(Some(value))
+}
+
+/// Parses a single value that spans the whole input. The parser is iterative, so the nesting depth
+/// is only limited by the available memory. Returns the comments around the value.
+///
+/// When recovering, errors become [`Value::Error`] nodes: a bad value is replaced by one, a
+/// missing comma or a mismatched bracket gets one at its position and every container that is
+/// still open when the input ends is closed.
+fn parse_document(
+    scanner: &mut Scanner<'_>,
+    spans: &mut SpanRecorder,
+) -> Result<(Value, Vec<Comment>), ParseError> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut root_comments = Vec::new();
+    // set once the rest of the input is unusable
+    let mut truncated = false;
+
+    'value: loop {
+        let mut value = match parse_item(scanner, &mut stack, &mut root_comments, spans) {
+            Ok(Some(value)) => value,
+            Ok(None) => continue 'value,
+            Err(err) => scanner.recover(err)?,
+        };
+        spans.leaf(scanner.bytes, spans.start, scanner.pos);
+
+        // add the value to its container, closing every container that ends after it
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                match scanner.is_done() {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let err = scanner.unexpected();
+                        if !scanner.recover {
+                            return Err(err);
+                        }
+                        // there is no container to put the error in
+                        scanner.record(&err);
+                    }
+                    Err(err) if scanner.recover => {
+                        scanner.record(&err);
+                    }
+                    Err(err) => return Err(err),
+                }
+                scanner.attach_comments(&mut root_comments, 1);
+                return Ok((value, root_comments));
+            };
+            frame.push(value);
+            spans.push();
+
+            let close = frame.close();
+            let mut next = scanner.peek();
+            if next == Ok(b',') {
+                scanner.pos += 1;
+                next = scanner.peek();
+                if !scanner.lenient || next != Ok(close) {
+                    continue 'value;
+                }
+                scanner.extensions.trailing_commas = true;
+            }
+            match next {
+                Ok(b) if b == close => scanner.pos += 1,
+                Err(err) if scanner.recover => {
+                    // the input ends inside of the container
+                    if !truncated && scanner.record(&err) {
+                        frame.push(Value::Error(err));
+                        spans.push_error(scanner.bytes, scanner.pos);
+                    }
+                    truncated = true;
+                    scanner.pos = scanner.bytes.len();
+                }
+                Err(err) => return Err(err),
+                Ok(_) if !scanner.recover => return Err(scanner.unexpected()),
+                Ok(b']' | b'}') => {
+                    // a mismatched bracket, assume that this container is missing its own
+                    let err = scanner.unexpected();
+                    if scanner.record(&err) {
+                        frame.push(Value::Error(err));
+                        spans.push_error(scanner.bytes, scanner.pos);
+                    }
+                }
+                Ok(b) => {
+                    let err = scanner.unexpected();
+                    let starts_value = matches!(
+                        b,
+                        b'"' | b'\'' | b'[' | b'{' | b'-' | b'+' | b'.' | b'0'..=b'9'
+                    ) || b.is_ascii_alphabetic();
+                    if starts_value {
+                        // assume a missing comma, the error marks its position
+                        scanner.record(&err);
+                        frame.push(Value::Error(err));
+                        spans.push_error(scanner.bytes, scanner.pos);
+                        continue 'value;
+                    }
+                    let start = scanner.pos;
+                    value = scanner.recover(err)?;
+                    spans.leaf(scanner.bytes, start, scanner.pos);
+                    continue;
+                }
+            }
+
+            let mut frame = stack.pop().unwrap();
+            let len = frame.len();
+            scanner.attach_comments(frame.comments_mut(), len);
+            value = frame.into_value();
+            spans.close(scanner.bytes, scanner.pos);
+        }
+    }
+}
+
+/// Parses a JSON document.
+pub fn parse(s: &str) -> Result<Value, ParseError> {
+    parse_document(&mut Scanner::from_str(s), &mut SpanRecorder::default()).map(|(value, _)| value)
+}
+
+/// Parses a JSON, JSONC or JSON5 document. Comments are kept with the containers they appear in.
+pub fn parse_lenient(s: &str) -> Result<Parsed, ParseError> {
+    parse_with(
+        s,
+        ParseOptions {
+            lenient: true,
+            recover: false,
+        },
+    )
+}
+
+/// Parses a document with the given options. When recovering this never fails, the parts that
+/// couldn't be parsed are replaced by [`Value::Error`] nodes.
+pub fn parse_with(s: &str, options: ParseOptions) -> Result<Parsed, ParseError> {
+    let mut scanner = Scanner::with_options(s, options);
+    let (value, comments) = parse_document(&mut scanner, &mut SpanRecorder::default())?;
+    Ok(Parsed {
+        value,
+        comments,
+        extensions: scanner.extensions,
+        errors: scanner.errors,
+    })
+}
+
+/// Parses a document like [`parse_with`], but only returns where its values are.
+pub(crate) fn parse_spans(s: &str, options: ParseOptions) -> Result<SourceSpan, ParseError> {
+    let mut scanner = Scanner::with_options(s, options);
+    let mut spans = SpanRecorder {
+        enabled: true,
+        ..Default::default()
+    };
+    parse_document(&mut scanner, &mut spans)?;
+    Ok(spans.value.unwrap().1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_lenient, parse_with, ParseErrorKind, ParseOptions};
+    use crate::value::Comment;
+
+    #[test]
+    fn parse_values() {
+        let value =
+            parse(r#" {"a": [1, -2.5e3, true, false, null], "b": {}, "c": [], "d": "x\"y"} "#)
+                .unwrap();
+        assert_eq!(
+            value.to_string(),
+            "{\n    \"a\": [\n        1,\n        -2.5e3,\n        true,\n        false,\n        null\n    ],\n    \"b\": {},\n    \"c\": [],\n    \"d\": \"x\\\"y\"\n}"
+        );
+        assert_eq!(parse("\"top\"").unwrap().get_string(), Some("top"));
+        assert!(parse("0").unwrap().is_number());
+    }
+
+    #[test]
+    fn matches_example_files() {
+        for file in [
+            "example_files/01.json",
+            "example_files/02.json",
+            "example_files/03.json",
+        ] {
+            let source = std::fs::read_to_string(file).unwrap();
+            let value = parse(&source).unwrap();
+            assert_eq!(
+                parse(&value.to_string()).unwrap().to_string(),
+                value.to_string()
+            );
+        }
+    }
+
+    #[cfg(feature = "pest-parser")]
+    #[test]
+    fn same_as_pest() {
+        for file in [
+            "example_files/01.json",
+            "example_files/02.json",
+            "example_files/03.json",
+        ] {
+            let source = std::fs::read_to_string(file).unwrap();
+            let expected = crate::value::pest_parser::parse(&source).unwrap();
+            assert_eq!(parse(&source).unwrap().to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 10_000;
+        let source = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        let mut value = &parse(&source).unwrap();
+        for _ in 0..depth - 1 {
+            value = value.get_path(&[0]).unwrap();
+        }
+        assert!(value.as_array().unwrap().arr.is_empty());
+    }
+
+    #[test]
+    fn lenient() {
+        let parsed = parse_lenient(
+            "/* a\n * b */ [ // first\n  'it\\'s \"x\"', \"\\x41\\\n\", +Infinity, NaN, -0X1f, { $k_1: null, }, // end\n] // after",
+        )
+        .unwrap();
+        let arr = parsed.value.as_array().unwrap();
+        assert_eq!(arr.arr[0].get_string(), Some("it's \\\"x\\\""));
+        assert_eq!(arr.arr[1].get_decoded_string().unwrap(), "A");
+        assert_eq!(arr.arr[4].get_number().unwrap().as_i64(), Some(-31));
+        assert_eq!(arr.arr[5].as_object().unwrap().map[0].0, "$k_1");
+
+        let comment = |before, text: &str, inline| Comment {
+            before,
+            text: text.to_string(),
+            inline,
+        };
+        assert_eq!(
+            arr.comments,
+            [comment(0, "// first", true), comment(6, "// end", true)]
+        );
+        assert_eq!(
+            parsed.comments,
+            [
+                comment(0, "/* a\n * b */", false),
+                comment(1, "// after", true)
+            ]
+        );
+        assert_eq!(parsed.extensions.names().len(), 8);
+
+        for source in ["[1,]", "{a: 1}", "['a']", "// c\n1", "0x1", "[.5]", "NaN"] {
+            assert!(parse(source).is_err(), "{source}");
+            assert!(parse_lenient(source).is_ok(), "{source}");
+        }
+        assert_eq!(
+            parse_lenient("[1 /* x").unwrap_err().kind,
+            ParseErrorKind::UnclosedComment
+        );
+        assert!(parse_lenient("[,]").is_err());
+        assert!(parse_lenient("{1a: 1}").is_err());
+    }
+
+    #[test]
+    fn recover() {
+        let options = ParseOptions {
+            lenient: false,
+            recover: true,
+        };
+        let cases = [
+            ("[1, x, 3]", "[1,<error: unexpected `x`>,3]", 1),
+            ("[1 2]", "[1,<error: unexpected `2`>,2]", 1),
+            (
+                "[1, [2, tru]]",
+                "[\n    1,\n    [2,<error: invalid literal>]\n]",
+                1,
+            ),
+            (
+                "{\"a\": [1, 2}",
+                "{\n    \"a\": [1,2,<error: unexpected `}`>]\n}",
+                1,
+            ),
+            (
+                "[1, {\"a\": [2",
+                "[\n    1,\n    {\n        \"a\": [2,<error: unexpected end of input>]\n    }\n]",
+                1,
+            ),
+            ("[\"abc, 1]", "[<error: unclosed `\"`>,1]", 1),
+            // recovering skips over strings
+            ("[\"a\\x,\", 2]", "[<error: invalid escape sequence>,2]", 1),
+            ("[tru \"x, y\", 2]", "[<error: invalid literal>,2]", 1),
+            ("[1, 2]] 3", "[1,2]", 1),
+            ("", "<error: unexpected end of input>", 1),
+        ];
+        for (source, expected, errors) in cases {
+            let parsed = parse_with(source, options).unwrap();
+            assert_eq!(parsed.value.to_string(), expected, "{source:?}");
+            assert_eq!(parsed.errors.len(), errors, "{source:?}");
+        }
+
+        let parsed = parse_with("[1,\n x,\n 2 3]", options).unwrap();
+        let errors = parsed.errors.iter().map(|e| (e.line, e.column));
+        assert_eq!(errors.collect::<Vec<_>>(), [(2, 2), (3, 4)]);
+        let parsed = parse_with("[\"é\", x, x,\n\"é\", x]", options).unwrap();
+        let errors = parsed.errors.iter().map(|e| (e.line, e.column));
+        assert_eq!(errors.collect::<Vec<_>>(), [(1, 7), (1, 10), (2, 6)]);
+
+        // the errors of missing commas have no key, they aren't repeated keys
+        let parsed = parse_with(r#"{"a": 1 "b": 2 "c": 3}"#, options).unwrap();
+        let object = parsed.value.as_object().unwrap();
+        assert_eq!(object.len(), 5);
+        assert!(!object.has_duplicate_keys());
+        assert!(parsed.value.find_duplicate_keys().is_empty());
+        assert!(parse_with("[1, x]", ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let value = parse(r#"{"a": 1, "b": {"c": 1, "d": 2}, "\u0061": 2, "a": 3}"#).unwrap();
+        let object = value.as_object().unwrap();
+        assert!(object.duplicate_keys);
+        assert!(!object.get("b").unwrap().as_object().unwrap().duplicate_keys);
+        assert_eq!(object.duplicates(), [0, 2, 3]);
+        assert_eq!(value.find_duplicate_keys(), [[0], [2], [3]]);
+
+        // larger objects are checked with a set
+        let entries = (0..20).map(|i| format!("\"k{}\": {i}", i % 19));
+        let value = parse(&format!("{{{}}}", entries.collect::<Vec<_>>().join(","))).unwrap();
+        assert_eq!(value.as_object().unwrap().duplicates(), [0, 19]);
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("", ParseErrorKind::UnexpectedEnd, 1, 1),
+            ("[1, 2", ParseErrorKind::UnexpectedEnd, 1, 6),
+            ("[1 2]", ParseErrorKind::Unexpected('2'), 1, 4),
+            ("{\n  \"a\" 1}", ParseErrorKind::Unexpected('1'), 2, 7),
+            ("{\"a\": tru}", ParseErrorKind::InvalidLiteral, 1, 7),
+            ("[01]", ParseErrorKind::Unexpected('1'), 1, 3),
+            ("[1.]", ParseErrorKind::InvalidNumber, 1, 2),
+            ("[\"a\\x\"]", ParseErrorKind::InvalidEscape, 1, 4),
+            ("[\"abc", ParseErrorKind::Unclosed('"'), 1, 2),
+            ("{} x", ParseErrorKind::Unexpected('x'), 1, 4),
+            ("[é]", ParseErrorKind::Unexpected('é'), 1, 2),
+            ("{1: 2}", ParseErrorKind::Unexpected('1'), 1, 2),
+        ];
+        for (source, kind, line, column) in cases {
+            let err = parse(source).unwrap_err();
+            assert_eq!(
+                (err.kind, err.line, err.column),
+                (kind, line, column),
+                "{source:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,51 @@
+//! The original pest based parser, kept to benchmark [`super::parse`] against.
+
+use pest::{error::Error, iterators::Pair, Parser};
+use pest_derive::Parser;
+
+#[derive(Parser)]
+#[grammar = "value/value.pest"]
+struct ValueParser;
+
+use super::{Number, Value, ValueArray, ValueObject};
+
+fn parse_value(pair: Pair<'_, Rule>) -> Value {
+    match pair.as_rule() {
+        Rule::object => Value::Object(ValueObject::from_entries(
+            pair.into_inner()
+                .map(|pair| {
+                    let mut inner_rules = pair.into_inner();
+                    let name = inner_rules
+                        .next()
+                        .unwrap()
+                        .into_inner()
+                        .next()
+                        .unwrap()
+                        .as_str()
+                        .to_string();
+                    let value = parse_value(inner_rules.next().unwrap());
+                    (name, value)
+                })
+                .collect(),
+        )),
+        Rule::array => Value::Array(ValueArray::new(
+            pair.into_inner().map(parse_value).collect(),
+        )),
+        Rule::string => Value::String(pair.into_inner().next().unwrap().as_str().to_string()),
+        Rule::number => Value::Number(Number::parse(pair.as_str()).unwrap()),
+        Rule::bool => Value::Bool(pair.as_str().parse().unwrap()),
+        Rule::null => Value::Null,
+        Rule::json
+        | Rule::EOI
+        | Rule::pair
+        | Rule::value
+        | Rule::inner
+        | Rule::char
+        | Rule::WHITESPACE => unreachable!(),
+    }
+}
+
+pub fn parse(s: &str) -> Result<Value, Box<Error<Rule>>> {
+    let json = ValueParser::parse(Rule::json, s)?.next().unwrap();
+    Ok(parse_value(json))
+}
@@ -0,0 +1,450 @@
+//! Where the values of a document are in its source, so that an edited document can be written
+//! back without touching the formatting of the parts that didn't change.
+
+use std::ops::Range;
+
+use super::{
+    parser::parse_spans,
+    writer::{LayoutConfig, Writer},
+    ParseError, ParseOptions, Value,
+};
+
+/// Where an item of a container was in the source.
+#[derive(Debug, Clone)]
+pub struct ItemOrigin {
+    /// The end of the previous item, or of the opening bracket for the first one. The text up to
+    /// the item is its separator.
+    pub(crate) prefix_start: usize,
+    /// The key of an object entry, up to and including its colon.
+    pub(crate) key: Option<Range<usize>>,
+    pub(crate) value: Range<usize>,
+    /// Where the next item started, the text up to it is the separator that followed this one.
+    pub(crate) separator_end: Option<usize>,
+}
+
+impl ItemOrigin {
+    fn start(&self) -> usize {
+        self.key.as_ref().map_or(self.value.start, |key| key.start)
+    }
+}
+
+/// The span of a value that wasn't replaced since it was parsed, with the spans of its items
+/// when it is a container.
+#[derive(Debug, Clone)]
+pub struct SourceSpan {
+    pub(crate) range: Range<usize>,
+    /// Where the last item ended in the source, or the opening bracket.
+    pub(crate) content_end: usize,
+    /// Where the closing bracket is, the end of the range if it is missing.
+    pub(crate) close_start: usize,
+    /// Where the first item started, the text before it follows the opening bracket.
+    pub(crate) first_start: Option<usize>,
+    pub(crate) items: Vec<SourceItem>,
+}
+
+impl SourceSpan {
+    pub(crate) fn new(source: &[u8], range: Range<usize>, mut items: Vec<SourceItem>) -> Self {
+        let starts = items
+            .iter()
+            .map(|item| item.origin.as_ref().map(ItemOrigin::start))
+            .collect::<Vec<_>>();
+        for (item, next_start) in items.iter_mut().zip(starts.iter().skip(1)) {
+            if let Some(origin) = &mut item.origin {
+                origin.separator_end = *next_start;
+            }
+        }
+
+        let content_end = items
+            .last()
+            .and_then(|item| item.origin.as_ref())
+            .map_or(range.start + 1, |origin| origin.value.end)
+            .min(range.end);
+        let closed = range.end > content_end && matches!(source[range.end - 1], b']' | b'}');
+        Self {
+            close_start: if closed { range.end - 1 } else { range.end },
+            content_end,
+            first_start: starts.first().copied().flatten(),
+            range,
+            items,
+        }
+    }
+}
+
+/// An item of a container. Items that were inserted have no origin, items whose value was
+/// replaced have no span.
+#[derive(Debug, Clone)]
+pub struct SourceItem {
+    pub(crate) origin: Option<ItemOrigin>,
+    pub(crate) key_changed: bool,
+    pub(crate) value: Option<SourceSpan>,
+}
+
+impl SourceItem {
+    /// An item that wasn't in the source.
+    pub fn new() -> Self {
+        Self {
+            origin: None,
+            key_changed: false,
+            value: None,
+        }
+    }
+}
+
+impl Default for SourceItem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The spans of the values of a document, kept in sync with the edits made to it.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    /// The range of the root value, the rest of the source is kept as is.
+    range: Range<usize>,
+    root: Option<SourceSpan>,
+}
+
+impl SourceMap {
+    pub fn build(source: &str, options: ParseOptions) -> Result<Self, ParseError> {
+        let root = parse_spans(source, options)?;
+        Ok(Self {
+            range: root.range.clone(),
+            root: Some(root),
+        })
+    }
+
+    /// The items of the container at `path`, unless it was replaced.
+    fn items_mut(&mut self, path: &[usize]) -> Option<&mut Vec<SourceItem>> {
+        let mut span = self.root.as_mut()?;
+        for &i in path {
+            span = span.items.get_mut(i)?.value.as_mut()?;
+        }
+        Some(&mut span.items)
+    }
+
+    /// The item at `path`, which can't be the root.
+    fn item_mut(&mut self, path: &[usize]) -> Option<&mut SourceItem> {
+        let (&i, parent) = path.split_last()?;
+        self.items_mut(parent)?.get_mut(i)
+    }
+
+    /// Sets the span of the value at `path`, returning the previous one. `None` means that the
+    /// value is new and gets written from scratch.
+    pub fn replace(&mut self, path: &[usize], span: Option<SourceSpan>) -> Option<SourceSpan> {
+        if path.is_empty() {
+            return std::mem::replace(&mut self.root, span);
+        }
+        let item = self.item_mut(path)?;
+        std::mem::replace(&mut item.value, span)
+    }
+
+    pub fn insert(&mut self, path: &[usize], item: SourceItem) {
+        let Some((&i, parent)) = path.split_last() else {
+            return;
+        };
+        if let Some(items) = self.items_mut(parent) {
+            items.insert(i.min(items.len()), item);
+        }
+    }
+
+    pub fn remove(&mut self, path: &[usize]) -> Option<SourceItem> {
+        let (&i, parent) = path.split_last()?;
+        let items = self.items_mut(parent)?;
+        (i < items.len()).then(|| items.remove(i))
+    }
+
+    /// Marks the key at `path` as changed or not, returning its previous state.
+    pub fn set_key_changed(&mut self, path: &[usize], changed: bool) -> bool {
+        match self.item_mut(path) {
+            Some(item) => std::mem::replace(&mut item.key_changed, changed),
+            None => true,
+        }
+    }
+
+    /// Writes `value` into `source`: the values that didn't change are copied from it, the others
+    /// are written with `layout`.
+    pub fn write(&self, source: &str, value: &Value, layout: &LayoutConfig) -> String {
+        let mut out = SourceWriter {
+            source,
+            layout,
+            out: String::with_capacity(source.len()),
+        };
+        out.out.push_str(&source[..self.range.start]);
+        out.write_value(value, self.root.as_ref());
+        out.out.push_str(&source[self.range.end..]);
+        out.out
+    }
+}
+
+struct SourceWriter<'a> {
+    source: &'a str,
+    layout: &'a LayoutConfig,
+    out: String,
+}
+
+/// The indentation of the line that contains `offset`.
+fn line_indent(text: &str, offset: usize) -> &str {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &text[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+impl SourceWriter<'_> {
+    fn render(&self, value: &Value) -> String {
+        let mut writer = Writer::new();
+        writer.with_layout(self.layout);
+        writer.with_all_expanded(true);
+        writer.write_value(value);
+        let indent = line_indent(&self.out, self.out.len()).to_string();
+        writer.to_string().replace('\n', &format!("\n{indent}"))
+    }
+
+    fn write_value(&mut self, value: &Value, span: Option<&SourceSpan>) {
+        let Some(span) = span else {
+            let rendered = self.render(value);
+            self.out.push_str(&rendered);
+            return;
+        };
+        let items: Vec<(Option<&str>, &Value)> = match value {
+            Value::Array(arr) => arr.arr.iter().map(|v| (None, v)).collect(),
+            Value::Object(object) => object
+                .entries()
+                .iter()
+                .map(|(k, v)| (Some(k.as_str()), v))
+                .collect(),
+            _ => {
+                self.out.push_str(&self.source[span.range.clone()]);
+                return;
+            }
+        };
+        let source = self.source;
+        // the span doesn't describe this container, e.g. a recovered one that was cut short
+        let opens = matches!(source.as_bytes().get(span.range.start), Some(b'[' | b'{'));
+        if !opens || items.len() != span.items.len() {
+            let rendered = self.render(value);
+            self.out.push_str(&rendered);
+            return;
+        }
+        let open_end = span.range.start + 1;
+        self.out.push_str(&source[span.range.start..open_end]);
+
+        // how new items are separated, taken from the first item that is still there
+        let container_indent = line_indent(source, span.range.start);
+        let first = span.items.iter().find_map(|item| item.origin.as_ref());
+        let (multiline, item_indent) = match first {
+            Some(origin) => {
+                let leading = &source[origin.prefix_start..origin.start()];
+                match leading.rfind('\n') {
+                    Some(i) => (true, leading[i + 1..].to_string()),
+                    None => (false, String::new()),
+                }
+            }
+            None => {
+                let rendered = self.render(value);
+                let indent = self.layout.indent.as_string();
+                (
+                    rendered.contains('\n'),
+                    format!("{container_indent}{indent}"),
+                )
+            }
+        };
+
+        let mut previous_end = Some(open_end);
+        // the separator that followed the previous item in the source, if it was there
+        let mut separator = span.first_start.map(|end| open_end..end);
+        for (i, ((key, item_value), item)) in items.into_iter().zip(&span.items).enumerate() {
+            match (&item.origin, previous_end) {
+                // the item and the one before it were next to each other in the source
+                (Some(origin), Some(end)) if origin.prefix_start == end => {
+                    self.out.push_str(&source[end..origin.start()]);
+                }
+                // keeps the comments that followed the previous item when the next one changed
+                _ if separator.is_some() => {
+                    self.out.push_str(&source[separator.clone().unwrap()]);
+                }
+                _ => {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    if multiline {
+                        self.out.push('\n');
+                        self.out.push_str(&item_indent);
+                    } else if i > 0 && !self.layout.compact {
+                        self.out.push(' ');
+                    }
+                }
+            }
+
+            if let Some(key) = key {
+                match item.origin.as_ref().and_then(|o| Some((o, o.key.clone()?))) {
+                    Some((origin, range)) if !item.key_changed => {
+                        self.out.push_str(&source[range.start..origin.value.start]);
+                    }
+                    Some((origin, range)) => {
+                        self.out.push_str(&format!("\"{key}\":"));
+                        self.out.push_str(&source[range.end..origin.value.start]);
+                    }
+                    None => {
+                        let separator = if self.layout.space_after_colon && !self.layout.compact {
+                            ": "
+                        } else {
+                            ":"
+                        };
+                        self.out.push_str(&format!("\"{key}\"{separator}"));
+                    }
+                }
+            }
+
+            self.write_value(item_value, item.value.as_ref());
+            previous_end = item.origin.as_ref().map(|origin| origin.value.end);
+            separator = item
+                .origin
+                .as_ref()
+                .and_then(|origin| Some(origin.value.end..origin.separator_end?));
+        }
+
+        match previous_end {
+            // the last item is still the last one, keep what followed it
+            Some(end) if end == span.content_end => {
+                self.out.push_str(&source[end..span.close_start]);
+            }
+            _ if multiline && !span.items.is_empty() => {
+                self.out.push('\n');
+                self.out.push_str(container_indent);
+            }
+            _ => {}
+        }
+        self.out.push_str(&source[span.close_start..span.range.end]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{parse_with, ValueArray};
+
+    fn options() -> ParseOptions {
+        ParseOptions {
+            lenient: true,
+            recover: false,
+        }
+    }
+
+    /// Applies `edit` to the value parsed from `source` and its map, then writes it back.
+    fn edit(source: &str, edit: impl FnOnce(&mut Value, &mut SourceMap)) -> String {
+        let mut value = parse_with(source, options()).unwrap().value;
+        let mut map = SourceMap::build(source, options()).unwrap();
+        edit(&mut value, &mut map);
+        map.write(source, &value, &LayoutConfig::default())
+    }
+
+    fn array(value: &mut Value, path: &[usize]) -> ValueArray {
+        value
+            .get_path_mut(path)
+            .unwrap()
+            .as_array_mut()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn unchanged() {
+        let source = "// c\n{\n  a: [1,  2, /* x */ 0x3,],\n  'b' : {\"c\": null}\n}\n";
+        assert_eq!(edit(source, |_, _| {}), source);
+    }
+
+    #[test]
+    fn replace() {
+        let source = "{\n  \"a\": [1,  2],\n  \"b\" :  true // c\n}";
+        let replaced = edit(source, |value, map| {
+            *value.get_path_mut(&[1]).unwrap() = Value::Null;
+            map.replace(&[1], None);
+        });
+        assert_eq!(replaced, "{\n  \"a\": [1,  2],\n  \"b\" :  null // c\n}");
+
+        let nested = edit(source, |value, map| {
+            *value.get_path_mut(&[0, 1]).unwrap() = parse_with("[3, 4]", options()).unwrap().value;
+            map.replace(&[0, 1], None);
+        });
+        assert_eq!(nested, "{\n  \"a\": [1,  [3,4]],\n  \"b\" :  true // c\n}");
+    }
+
+    #[test]
+    fn remove_and_insert() {
+        let source = "{\n  \"a\": [1,  2,  3],\n  \"b\": 2\n}";
+        let removed = edit(source, |value, map| {
+            let mut arr = array(value, &[0]);
+            arr.arr.remove(0);
+            arr.arr.remove(1);
+            *value.get_path_mut(&[0]).unwrap() = Value::Array(arr);
+            map.remove(&[0, 0]);
+            map.remove(&[0, 1]);
+        });
+        assert_eq!(removed, "{\n  \"a\": [2],\n  \"b\": 2\n}");
+
+        let removed = edit(source, |value, map| {
+            value.as_object_mut().unwrap().remove(1);
+            map.remove(&[1]);
+        });
+        assert_eq!(removed, "{\n  \"a\": [1,  2,  3]\n}");
+
+        let inserted = edit(source, |value, map| {
+            let object = value.as_object_mut().unwrap();
+            object.insert(1, "c".to_string(), Value::Null);
+            object.push("d".to_string(), Value::Bool(true));
+            map.insert(&[1], SourceItem::new());
+            map.insert(&[3], SourceItem::new());
+            let mut arr = array(value, &[0]);
+            arr.arr.push(Value::Null);
+            *value.get_path_mut(&[0]).unwrap() = Value::Array(arr);
+            map.insert(&[0, 3], SourceItem::new());
+        });
+        assert_eq!(
+            inserted,
+            "{\n  \"a\": [1,  2,  3, null],\n  \"c\": null,\n  \"b\": 2,\n  \"d\": true\n}"
+        );
+
+        let emptied = edit("[1, 2]", |value, map| {
+            *value = Value::Array(ValueArray::default());
+            map.remove(&[0]);
+            map.remove(&[0]);
+        });
+        assert_eq!(emptied, "[]");
+
+        let commented = "[\n  1, // one\n  2,\n  3\n]";
+        let removed = edit(commented, |value, map| {
+            *value = parse_with("[1, 3]", options()).unwrap().value;
+            map.remove(&[1]);
+        });
+        assert_eq!(removed, "[\n  1, // one\n  3\n]");
+    }
+
+    #[test]
+    fn rename() {
+        let source = "{a : 1, \"b\":2}";
+        let renamed = edit(source, |value, map| {
+            value.as_object_mut().unwrap().rename(0, "c d".to_string());
+            map.set_key_changed(&[0], true);
+        });
+        assert_eq!(renamed, "{\"c d\": 1, \"b\":2}");
+    }
+
+    #[test]
+    fn recovered() {
+        let options = ParseOptions {
+            lenient: true,
+            recover: true,
+        };
+        let source = "[1, x, {\"a\": [2 3";
+        let mut value = parse_with(source, options).unwrap().value;
+        let mut map = SourceMap::build(source, options).unwrap();
+        let layout = LayoutConfig::default();
+        assert_eq!(map.write(source, &value, &layout), source);
+
+        *value.get_path_mut(&[0]).unwrap() = Value::Null;
+        map.replace(&[0], None);
+        assert!(map
+            .write(source, &value, &layout)
+            .starts_with("[null, x, {"));
+    }
+}
@@ -1,10 +1,24 @@
-use super::{Value, ValueArray, ValueObject};
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use serde::Deserialize;
+
+use super::{
+    escape::{escape_control, unescape},
+    Comment, Number, Value, ValueObject,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ElementType {
     Whtiespace,
     Comma,
     Key,
+    DuplicateKey, // a key repeated in its object
     NullLiteral,
     BoolLiteral,
     NumberLiteral,
@@ -14,6 +28,8 @@ pub enum ElementType {
     Brace,            // `{` or `}`
     CollapsedBracket, // `[ ... ]`
     CollapsedBrace,   // `{ ... }`
+    Comment,
+    Error,
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +58,10 @@ impl Element {
         Self::new(ElementType::Key, s)
     }
 
+    fn duplicate_key(s: impl ToString) -> Self {
+        Self::new(ElementType::DuplicateKey, s)
+    }
+
     fn null_literal() -> Self {
         Self::new(ElementType::NullLiteral, "null")
     }
@@ -85,12 +105,237 @@ impl Element {
     fn collapsed_brace() -> Self {
         Self::new(ElementType::CollapsedBrace, "{ ... }")
     }
+
+    fn comment(s: impl ToString) -> Self {
+        Self::new(ElementType::Comment, s)
+    }
+
+    fn error(s: impl ToString) -> Self {
+        Self::new(ElementType::Error, s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum Indent {
+    /// A number of spaces per level.
+    Spaces(usize),
+    /// `"tab"`, one tab per level.
+    Tab(TabIndent),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TabIndent {
+    Tab,
+}
+
+impl Indent {
+    pub const TAB: Self = Self::Tab(TabIndent::Tab);
+
+    pub(crate) fn as_string(&self) -> String {
+        match self {
+            Self::Spaces(width) => " ".repeat(*width),
+            Self::Tab(_) => "\t".to_string(),
+        }
+    }
+}
+
+/// How the [`Writer`] lays out values. Without `max_width` arrays of less than 5 scalars and
+/// objects with a single scalar are written on one line, with it every container that fits in
+/// `max_width` columns is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub indent: Indent,
+    pub max_width: Option<usize>,
+    /// Write the entries of objects ordered by their keys.
+    pub sort_keys: bool,
+    pub space_after_colon: bool,
+    /// Write everything on a single line, without spaces and comments.
+    pub compact: bool,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            indent: Indent::Spaces(4),
+            max_width: None,
+            sort_keys: false,
+            space_after_colon: true,
+            compact: false,
+        }
+    }
+}
+
+/// The size of a rendered container: the number of line breaks in it and the width of its last
+/// line, without the indentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    lines: i32,
+    width: i32,
+}
+
+/// Caches the [`Layout`] of a container so that the [`Writer`] can skip over it without visiting
+/// its children. Containers clear it whenever they are accessed mutably, as any change to their
+/// content may change their size.
+#[derive(Debug, Default)]
+pub(crate) struct LayoutCache {
+    lines: AtomicU64,
+    width: AtomicU64,
+}
+
+impl LayoutCache {
+    /// Both halves of the layout are stored together with the key of the writer settings they were
+    /// made with, in the high 32 bits. Writers on other threads may store a layout at the same
+    /// time, it is only used when both halves have the same key.
+    fn get(&self, key: u32) -> Option<Layout> {
+        let lines = self.lines.load(Ordering::Relaxed);
+        let width = self.width.load(Ordering::Relaxed);
+        (lines >> 32 == key as u64 && width >> 32 == key as u64).then_some(Layout {
+            lines: lines as u32 as i32,
+            width: width as u32 as i32,
+        })
+    }
+
+    fn set(&self, key: u32, layout: Layout) {
+        if layout.lines >= 0 && layout.width >= 0 {
+            let key = (key as u64) << 32;
+            self.lines
+                .store(key | layout.lines as u64, Ordering::Relaxed);
+            self.width
+                .store(key | layout.width as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self.lines.get_mut() = 0;
+        *self.width.get_mut() = 0;
+    }
+}
+
+/// The settings of a [`Writer`] that change the size of containers.
+#[derive(PartialEq)]
+struct LayoutSettings {
+    layout: LayoutConfig,
+    indent_str: String,
+    decode_strings: bool,
+    valid_json: bool,
+    all_expanded: bool,
+}
+
+/// Every combination of settings that was written with, the key of a combination in the
+/// [`LayoutCache`] is its index plus one. There are only ever a few of them.
+static LAYOUT_SETTINGS: Mutex<Vec<LayoutSettings>> = Mutex::new(Vec::new());
+
+impl Clone for LayoutCache {
+    fn clone(&self) -> Self {
+        Self {
+            lines: AtomicU64::new(self.lines.load(Ordering::Relaxed)),
+            width: AtomicU64::new(self.width.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The location of a rendered value. `path` holds the index of the child taken at every level,
+/// for objects this is the index of the entry. `end` is exclusive, `key` is the start of the key
+/// for values inside objects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeSpan {
+    pub path: Vec<usize>,
+    pub key: Option<[i32; 2]>,
+    pub start: [i32; 2],
+    pub end: [i32; 2],
+}
+
+impl NodeSpan {
+    /// Whether the position is on the value or on its key.
+    pub fn contains(&self, pos: [i32; 2]) -> bool {
+        self.key.unwrap_or(self.start) <= pos && pos < self.end
+    }
+}
+
+/// The output of a [`Writer`]. When rendering a window only the lines inside of it are kept,
+/// `first_line` is the index of the first one, and `nodes` are the values visible in it. Without
+/// a window no nodes are recorded. When rendering a subtree the first line starts at
+/// the column of the subtree.
+#[derive(Debug, Clone)]
+pub struct Rendered {
+    pub lines: Vec<Vec<Element>>,
+    pub first_line: i32,
+    pub line_count: i32,
+    pub nodes: Vec<NodeSpan>,
+    pub target: Option<NodeSpan>,
+}
+
+/// The items of a container, see [`Writer::write_value`].
+#[derive(Clone, Copy)]
+enum Items<'a> {
+    Array(&'a [Value]),
+    Object(&'a ValueObject),
+    /// Values from anywhere written as the items of an array, like the results of a query.
+    Values(&'a [&'a Value]),
+}
+
+impl<'a> Items<'a> {
+    fn len(self) -> usize {
+        match self {
+            Self::Array(arr) => arr.len(),
+            Self::Object(object) => object.map.len(),
+            Self::Values(values) => values.len(),
+        }
+    }
+
+    /// The key and value of the item at `i`.
+    fn get(self, i: usize) -> (Option<&'a str>, &'a Value) {
+        match self {
+            Self::Array(arr) => (None, &arr[i]),
+            Self::Object(object) => (Some(&object.map[i].0), &object.map[i].1),
+            Self::Values(values) => (None, values[i]),
+        }
+    }
+}
+
+/// A container whose items are being written. The writer keeps a stack of them rather than
+/// recursing, so that deeply nested documents don't overflow the stack.
+struct Container<'a> {
+    items: Items<'a>,
+    comments: &'a [Comment],
+    expanded: bool,
+    /// The order to write the items in, when it isn't theirs.
+    order: Option<Vec<usize>>,
+    /// The entries of an object whose key is repeated.
+    duplicates: Vec<usize>,
+    /// The position of the next item to write.
+    position: usize,
+    cache: Option<&'a LayoutCache>,
+    key: Option<[i32; 2]>,
+    start: [i32; 2],
 }
 
 pub struct Writer {
     buffer: Vec<Vec<Element>>,
     indent_str: String,
     indent: u32,
+    layout: LayoutConfig,
+    /// The key of the settings in the [`LayoutCache`], 0 until it is needed.
+    layout_key: u32,
+    decode_strings: bool,
+    valid_json: bool,
+    all_expanded: bool,
+    line: i32,
+    col: i32,
+    start_line: i32,
+    window: Option<[i32; 2]>,
+    path: Vec<usize>,
+    pending_key: Option<[i32; 2]>,
+    nodes: Vec<NodeSpan>,
+    target: Option<Vec<usize>>,
+    target_span: Option<NodeSpan>,
+    /// Whether a container around the whole window was recorded, see [`Writer::record_node`].
+    recorded_around: bool,
+    /// Only the first line is written, cut after this many characters.
+    preview_width: Option<i32>,
 }
 
 impl Writer {
@@ -99,6 +344,22 @@ impl Writer {
             buffer: vec![vec![]],
             indent_str: "    ".to_string(),
             indent: 0,
+            layout: LayoutConfig::default(),
+            layout_key: 0,
+            decode_strings: false,
+            valid_json: false,
+            all_expanded: false,
+            line: 0,
+            col: 0,
+            start_line: 0,
+            window: None,
+            path: Vec::new(),
+            pending_key: None,
+            nodes: Vec::new(),
+            target: None,
+            target_span: None,
+            recorded_around: false,
+            preview_width: None,
         }
     }
 
@@ -106,7 +367,7 @@ impl Writer {
         self.buffer
             .iter()
             .map(|v| {
-                v.into_iter()
+                v.iter()
                     .map(|e| e.content.clone())
                     .collect::<Vec<_>>()
                     .join("")
@@ -116,165 +377,831 @@ impl Writer {
 
     pub fn with_indent_str(&mut self, indent_str: impl ToString) {
         self.indent_str = indent_str.to_string();
+        self.update_layout_key();
     }
 
     pub fn with_indent_size(&mut self, indent_size: usize) {
-        self.indent_str = vec![" "; indent_size].join(" ");
+        self.indent_str = " ".repeat(indent_size);
+        self.update_layout_key();
     }
 
-    fn push_last(&mut self, e: Element) {
-        self.buffer.last_mut().unwrap().push(e);
+    pub fn with_layout(&mut self, layout: &LayoutConfig) {
+        self.indent_str = layout.indent.as_string();
+        self.layout = layout.clone();
+        self.update_layout_key();
     }
 
-    fn newline(&mut self) {
-        self.buffer.push(Vec::new());
-        for _ in 0..(self.indent as usize) {
-            self.push_last(Element::whtiespace(self.indent_str.clone()));
+    /// Render strings and keys with their escape sequences decoded. Control characters stay escaped
+    /// so that every value still fits on a single line.
+    pub fn with_decoded_strings(&mut self, decode_strings: bool) {
+        self.decode_strings = decode_strings;
+        self.update_layout_key();
+    }
+
+    /// Write standard JSON whatever the document was parsed with: comments are dropped, numbers
+    /// are normalized, numbers that aren't finite and errors are written as `null`, and collapsed
+    /// containers are written in full. Lazy containers must be parsed beforehand.
+    pub fn with_valid_json(&mut self, valid_json: bool) {
+        self.valid_json = valid_json;
+        self.update_layout_key();
+    }
+
+    /// Write collapsed containers in full, like when saving the document.
+    pub fn with_all_expanded(&mut self, all_expanded: bool) {
+        self.all_expanded = all_expanded;
+        self.update_layout_key();
+    }
+
+    /// Only keep the lines in `[start, end)`, the rest of the document is still laid out but isn't
+    /// stored. Must be set before writing.
+    pub fn with_window(&mut self, window: [i32; 2]) {
+        self.window = Some(window);
+        if !self.in_window(self.line) {
+            self.buffer.clear();
         }
     }
 
-    fn write_null(&mut self) {
-        self.push_last(Element::null_literal());
+    /// Only write the start of the first line, `width` characters of it. The rest of the value
+    /// isn't visited at all, which keeps the preview of a large value cheap.
+    pub fn with_preview(&mut self, width: usize) {
+        self.preview_width = Some(width.try_into().unwrap_or(i32::MAX));
     }
 
-    fn write_bool(&mut self, value: bool) {
-        self.push_last(Element::bool_literal(value));
+    /// Record the location of the value at `path` even if it is outside of the window.
+    pub fn with_target(&mut self, path: Vec<usize>) {
+        self.target = Some(path);
     }
 
-    // fn write_number(&mut self, value: &Number) {
-    //     self.push_last(value.to_string())
-    // }
+    /// Start writing the value rendered at `node`, which is indented by `indent` levels, to render
+    /// a subtree in place. Must be set after the window and before writing.
+    pub fn with_start(&mut self, node: &NodeSpan, indent: u32) {
+        [self.line, self.col] = node.start;
+        self.start_line = node.start[0];
+        self.path = node.path.clone();
+        self.pending_key = node.key;
+        self.indent = indent;
+        self.buffer = if self.in_window(self.line) {
+            vec![vec![]]
+        } else {
+            vec![]
+        };
+    }
 
-    fn write_number(&mut self, value: &str) {
-        self.push_last(Element::number_literal(value));
+    /// The number of indentation levels in `width` characters of indentation.
+    pub fn indent_levels(&self, width: i32) -> u32 {
+        (width / self.indent_str.chars().count().max(1) as i32) as u32
     }
 
-    fn write_string(&mut self, value: impl ToString) {
-        let value = value.to_string();
-        self.push_last(Element::string_literal(format!("\"{value}\"")));
+    /// The settings changed, their key is looked up again once a layout is cached.
+    fn update_layout_key(&mut self) {
+        self.layout_key = 0;
     }
 
-    fn write_arr(&mut self, arr: &[Value], collapse: bool) {
-        if collapse {
-            self.push_last(Element::collapsed_bracket());
-            return;
+    /// The key of the settings that change the size of containers, see [`LayoutCache`]. Equal
+    /// settings always get the same key, different ones never do.
+    fn layout_key(&mut self) -> u32 {
+        if self.layout_key == 0 {
+            let settings = LayoutSettings {
+                layout: self.layout.clone(),
+                indent_str: self.indent_str.clone(),
+                decode_strings: self.decode_strings,
+                valid_json: self.valid_json,
+                all_expanded: self.all_expanded,
+            };
+            let mut known = LAYOUT_SETTINGS
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            let index = match known.iter().position(|known| *known == settings) {
+                Some(index) => index,
+                None => {
+                    known.push(settings);
+                    known.len() - 1
+                }
+            };
+            self.layout_key = index as u32 + 1;
         }
+        self.layout_key
+    }
 
-        let short = arr.len() < 5;
-        let simple = arr
-            .iter()
-            .find(|value| value.is_array() || value.is_object())
-            .is_none();
-        let expanded = !short || !simple;
+    fn indent_width(&self) -> i32 {
+        self.indent as i32 * self.indent_str.chars().count() as i32
+    }
 
-        self.push_last(Element::open_bracket());
+    fn in_window(&self, line: i32) -> bool {
+        self.window
+            .map(|[start, end]| start <= line && line < end)
+            .unwrap_or(true)
+    }
 
-        if expanded {
-            self.indent += 1;
+    fn position(&self) -> [i32; 2] {
+        [self.line, self.col]
+    }
+
+    fn skip_comments(&self) -> bool {
+        self.layout.compact || self.valid_json
+    }
+
+    fn is_collapsed(&self, collapse: bool) -> bool {
+        collapse && !self.valid_json && !self.all_expanded
+    }
+
+    fn number_text<'a>(&self, value: &'a Number) -> Cow<'a, str> {
+        if self.valid_json {
+            value.to_json().unwrap_or(Cow::Borrowed("null"))
+        } else {
+            Cow::Borrowed(value.lexeme())
+        }
+    }
+
+    fn display_string(&self, raw: &str) -> String {
+        // an escape sequence takes at most 12 characters, this is enough to fill the preview
+        let raw = match self.preview_width {
+            Some(width) => raw
+                .char_indices()
+                .nth((width as usize).saturating_mul(12))
+                .map_or(raw, |(i, _)| &raw[..i]),
+            None => raw,
+        };
+        if self.decode_strings {
+            escape_control(&unescape(raw)).into_owned()
+        } else {
+            raw.to_string()
+        }
+    }
+
+    /// Whether the preview is full, nothing more gets written.
+    fn is_cut(&self) -> bool {
+        self.preview_width
+            .is_some_and(|width| self.line > 0 || self.col >= width)
+    }
+
+    fn push_last(&mut self, mut e: Element) {
+        if let Some(width) = self.preview_width {
+            if self.is_cut() {
+                return;
+            }
+            let rest = (width - self.col) as usize;
+            if let Some((i, _)) = e.content.char_indices().nth(rest) {
+                e.content.truncate(i);
+            }
+        }
+        self.col += e.content.chars().count() as i32;
+        if self.in_window(self.line) {
+            self.buffer.last_mut().unwrap().push(e);
+        }
+    }
+
+    fn newline(&mut self) {
+        self.line += 1;
+        self.col = 0;
+        if self.preview_width.is_some() {
+            return;
+        }
+        if !self.in_window(self.line) {
+            // the lines that aren't kept only need their width
+            self.col = self.indent_width();
+            return;
+        }
+        self.buffer.push(Vec::new());
+        if self.indent > 0 {
+            let indent = self.indent_str.repeat(self.indent as usize);
+            self.push_last(Element::whtiespace(indent));
+        }
+    }
+
+    /// Writes a comment, every line of a block comment goes on its own line.
+    fn write_comment(&mut self, text: &str) {
+        for (i, line) in text.lines().enumerate() {
+            if i == 0 {
+                self.push_last(Element::comment(line));
+            } else {
+                self.newline();
+                self.push_last(Element::comment(format!(" {}", line.trim_start())));
+            }
+        }
+    }
+
+    /// Writes the comments placed before the item at `before`. Inline comments stay on the current
+    /// line, the others get lines of their own.
+    fn write_comments(&mut self, comments: &[Comment], before: usize) {
+        if self.skip_comments() {
+            return;
+        }
+        for comment in comments.iter().filter(|c| c.before == before && c.inline) {
+            self.push_last(Element::whtiespace(" "));
+            self.write_comment(&comment.text);
+        }
+        for comment in comments.iter().filter(|c| c.before == before && !c.inline) {
             self.newline();
+            self.write_comment(&comment.text);
         }
+    }
 
-        for i in 0..arr.len() {
-            self.write_value(&arr[i]);
+    fn key_separator(&self) -> &'static str {
+        if self.layout.space_after_colon && !self.layout.compact {
+            ": "
+        } else {
+            ":"
+        }
+    }
 
-            if i != arr.len() - 1 {
-                self.push_last(Element::comma());
+    /// The items of `value` if it is a container that is written with them.
+    fn open_items<'a>(&self, value: &'a Value) -> Option<(Items<'a>, &'a [Comment])> {
+        match value {
+            Value::Array(arr) if !self.is_collapsed(arr.collapse) => {
+                Some((Items::Array(&arr.arr), &arr.comments))
+            }
+            Value::Object(object) if !self.is_collapsed(object.collapse) => {
+                Some((Items::Object(object), &object.comments))
             }
+            _ => None,
+        }
+    }
 
-            if i == arr.len() - 1 {
-                if expanded {
-                    self.indent -= 1;
+    /// The width of a value that isn't written with its items.
+    fn atom_width(&self, value: &Value) -> i32 {
+        match value {
+            Value::Null => 4,
+            Value::Bool(x) => x.to_string().len() as i32,
+            Value::Number(x) => self.number_text(x).chars().count() as i32,
+            Value::String(x) => self.display_string(x).chars().count() as i32 + 2,
+            Value::Array(_) | Value::Object(_) | Value::Lazy(_) => 7,
+            Value::Error(_) if self.valid_json => 4,
+            Value::Error(err) => format!("<error: {}>", err.kind).chars().count() as i32,
+        }
+    }
+
+    /// The width of `value` written on a single line, if it can be and it isn't wider than
+    /// `budget`.
+    fn inline_width(&self, value: &Value, budget: i32) -> Option<i32> {
+        match self.open_items(value) {
+            Some((items, comments)) => self.inline_items_width(items, comments, budget),
+            None => Some(self.atom_width(value)).filter(|&width| width <= budget),
+        }
+    }
+
+    /// The width of a container on a single line, with its brackets. Nested containers are
+    /// measured with a stack, and only until the budget is exceeded.
+    fn inline_items_width(
+        &self,
+        items: Items<'_>,
+        comments: &[Comment],
+        budget: i32,
+    ) -> Option<i32> {
+        let separator = self.key_separator().len() as i32;
+        let mut width = 0;
+        let mut stack = Vec::new();
+        let mut next = Some((items, comments));
+        loop {
+            if let Some((items, comments)) = next.take() {
+                if !comments.is_empty() && !self.skip_comments() {
+                    return None;
                 }
+                width += 2 + items.len().saturating_sub(1) as i32;
+                stack.push((items, 0));
             }
-
-            if expanded {
-                self.newline();
+            if width > budget {
+                return None;
+            }
+            let Some((items, position)) = stack.last_mut() else {
+                return Some(width);
+            };
+            if *position == items.len() {
+                stack.pop();
+                continue;
+            }
+            let (key, item) = items.get(*position);
+            *position += 1;
+            if let Some(key) = key {
+                width += self.display_string(key).chars().count() as i32 + 2 + separator;
+            }
+            match self.open_items(item) {
+                Some(open) => next = Some(open),
+                None => width += self.atom_width(item),
             }
         }
+    }
 
-        // if expanded {
-        //     self.indent -= 1;
-        // }
+    /// Whether the container is written on multiple lines, `short` is the decision of the default
+    /// layout.
+    fn expand_container(&self, items: Items<'_>, short: bool, comments: &[Comment]) -> bool {
+        if self.layout.compact {
+            return false;
+        }
+        match self.layout.max_width {
+            _ if !comments.is_empty() && !self.valid_json => true,
+            None => !short,
+            Some(max_width) => self
+                .inline_items_width(items, comments, max_width as i32 - self.col)
+                .is_none(),
+        }
+    }
 
-        self.push_last(Element::close_bracket());
+    /// Whether the value would be written on a single line at column `col`, always false without
+    /// `max_width`.
+    pub fn fits_inline(&self, value: &Value, col: i32) -> bool {
+        self.layout
+            .max_width
+            .is_some_and(|max_width| self.inline_width(value, max_width as i32 - col).is_some())
     }
 
-    fn write_object(&mut self, object: &[(&str, Value)], collapse: bool) {
-        if collapse {
-            self.push_last(Element::collapsed_brace());
-            return;
+    /// Skips over a container using its cached layout when none of it is visible.
+    fn skip_cached(&mut self, cache: &LayoutCache) -> bool {
+        let Some(layout) = cache.get(self.layout_key()) else {
+            return false;
+        };
+        let [start, end] = [self.line, self.line + layout.lines];
+        let hidden = self
+            .window
+            .is_some_and(|[window_start, window_end]| end < window_start || start >= window_end);
+        let has_target = self
+            .target
+            .as_ref()
+            .is_some_and(|target| target.starts_with(&self.path));
+        if !hidden || has_target {
+            return false;
         }
+        if layout.lines == 0 {
+            self.col += layout.width;
+        } else {
+            self.line = end;
+            self.col = self.indent_width() + layout.width;
+        }
+        true
+    }
 
-        let short = object.len() < 2;
-        let simple = object
-            .iter()
-            .find(|(_, value)| value.is_array() || value.is_object())
-            .is_none();
-        let expanded = !short || !simple;
+    /// Stores the layout of the container that was written from `start`. The layouts of a preview
+    /// may be cut short and aren't kept.
+    fn cache_layout(&mut self, cache: &LayoutCache, start: [i32; 2]) {
+        if self.preview_width.is_some() {
+            return;
+        }
+        let lines = self.line - start[0];
+        let width = if lines == 0 {
+            self.col - start[1]
+        } else {
+            self.col - self.indent_width()
+        };
+        let key = self.layout_key();
+        cache.set(key, Layout { lines, width });
+    }
 
-        self.push_last(Element::open_brace());
+    /// Writes the opening bracket of a container, its items are written next.
+    fn open_container<'a>(
+        &mut self,
+        items: Items<'a>,
+        comments: &'a [Comment],
+        cache: Option<&'a LayoutCache>,
+        key: Option<[i32; 2]>,
+        start: [i32; 2],
+    ) -> Container<'a> {
+        let has_containers = (0..items.len()).any(|i| items.get(i).1.is_container());
+        let (short, order, duplicates) = match items {
+            Items::Object(object) => {
+                let order = self.layout.sort_keys.then(|| {
+                    let mut order = (0..object.map.len()).collect::<Vec<_>>();
+                    order.sort_by_cached_key(|&i| unescape(&object.map[i].0));
+                    order
+                });
+                (object.map.len() < 2, order, object.duplicates())
+            }
+            _ => (items.len() < 5, None, Vec::new()),
+        };
+        let expanded = self.expand_container(items, short && !has_containers, comments);
 
+        self.push_last(match items {
+            Items::Object(_) => Element::open_brace(),
+            _ => Element::open_bracket(),
+        });
         if expanded {
             self.indent += 1;
+        }
+        Container {
+            items,
+            comments,
+            expanded,
+            order,
+            duplicates,
+            position: 0,
+            cache,
+            key,
+            start,
+        }
+    }
+
+    /// Writes what comes before the next item of the container, like its key, and returns it.
+    fn next_item<'a>(&mut self, container: &mut Container<'a>) -> &'a Value {
+        let position = container.position;
+        container.position += 1;
+        if position != 0 {
+            self.path.pop();
+            self.push_last(Element::comma());
+        }
+        let i = container
+            .order
+            .as_ref()
+            .map_or(position, |order| order[position]);
+        self.write_comments(container.comments, i);
+        if container.expanded {
             self.newline();
         }
+        self.path.push(i);
 
-        for i in 0..object.len() {
-            self.push_last(Element::key(format!("\"{}\": ", object[i].0)));
+        let (key, value) = container.items.get(i);
+        if let Some(key) = key {
+            let key_start = self.position();
+            let key = format!("\"{}\"{}", self.display_string(key), self.key_separator());
+            self.push_last(if container.duplicates.binary_search(&i).is_ok() {
+                Element::duplicate_key(key)
+            } else {
+                Element::key(key)
+            });
+            self.pending_key = Some(key_start);
+        }
+        value
+    }
 
-            self.write_value(&object[i].1);
+    /// Writes the closing bracket of a container whose items were all written.
+    fn close_container(&mut self, container: Container<'_>) {
+        let len = container.items.len();
+        if len != 0 {
+            self.path.pop();
+        }
+        self.write_comments(container.comments, len);
+        if container.expanded {
+            self.indent -= 1;
+            self.newline();
+        }
+        self.push_last(match container.items {
+            Items::Object(_) => Element::close_brace(),
+            _ => Element::close_bracket(),
+        });
+        if let Some(cache) = container.cache {
+            self.cache_layout(cache, container.start);
+        }
+        self.record_node(container.key, container.start);
+    }
 
-            if i != object.len() - 1 {
-                self.push_last(Element::comma());
+    /// Starts writing `value`: scalars are written at once, containers are pushed on the stack.
+    fn start_value<'a>(&mut self, value: &'a Value, stack: &mut Vec<Container<'a>>) {
+        let key = self.pending_key.take();
+        let start = self.position();
+        let (items, comments, collapse, cache) = match value {
+            Value::Null => return self.write_atom(key, start, Element::null_literal()),
+            Value::Bool(x) => return self.write_atom(key, start, Element::bool_literal(*x)),
+            Value::Number(x) => {
+                let text = self.number_text(x);
+                return self.write_atom(key, start, Element::number_literal(text));
             }
-
-            if i == object.len() - 1 {
-                if expanded {
-                    self.indent -= 1;
-                }
+            Value::String(x) => {
+                let text = format!("\"{}\"", self.display_string(x));
+                return self.write_atom(key, start, Element::string_literal(text));
             }
-
-            if expanded {
-                self.newline();
+            Value::Lazy(container) => {
+                let element = if container.kind.is_array() {
+                    Element::collapsed_bracket()
+                } else {
+                    Element::collapsed_brace()
+                };
+                return self.write_atom(key, start, element);
+            }
+            Value::Error(_) if self.valid_json => {
+                return self.write_atom(key, start, Element::null_literal())
+            }
+            Value::Error(err) => {
+                let text = format!("<error: {}>", err.kind);
+                return self.write_atom(key, start, Element::error(text));
             }
+            Value::Array(arr) => (
+                Items::Array(&arr.arr),
+                &arr.comments,
+                arr.collapse,
+                &arr.layout,
+            ),
+            Value::Object(object) => (
+                Items::Object(object),
+                &object.comments,
+                object.collapse,
+                &object.layout,
+            ),
+        };
+
+        if self.skip_cached(cache) {
+            self.record_node(key, start);
+        } else if self.is_collapsed(collapse) {
+            self.push_last(match items {
+                Items::Object(_) => Element::collapsed_brace(),
+                _ => Element::collapsed_bracket(),
+            });
+            self.cache_layout(cache, start);
+            self.record_node(key, start);
+        } else {
+            stack.push(self.open_container(items, comments, Some(cache), key, start));
         }
+    }
 
-        // if expanded {
-        //     self.indent -= 1;
-        // }
+    fn write_atom(&mut self, key: Option<[i32; 2]>, start: [i32; 2], element: Element) {
+        self.push_last(element);
+        self.record_node(key, start);
+    }
 
-        self.push_last(Element::close_brace());
+    /// Writes the items of the containers on the stack, and of the containers in them.
+    fn write_containers(&mut self, mut stack: Vec<Container<'_>>) {
+        while let Some(container) = stack.last_mut() {
+            if self.is_cut() {
+                break;
+            }
+            if container.position < container.items.len() {
+                let item = self.next_item(container);
+                self.start_value(item, &mut stack);
+            } else {
+                let container = stack.pop().unwrap();
+                self.close_container(container);
+            }
+        }
     }
 
-    pub fn write_value(&mut self, value: &Value) {
-        match value {
-            Value::Null => self.write_null(),
-            Value::Bool(x) => self.write_bool(*x),
-            Value::Number(x) => self.write_number(x),
-            Value::String(x) => self.write_string(x),
-            Value::Array(ValueArray { collapse, arr }) => self.write_arr(arr.as_slice(), *collapse),
-            Value::Object(ValueObject { collapse, map }) => {
-                self.write_object(map.as_slice(), *collapse)
+    /// Records the location of the value that was written from `start`, when it is visible in the
+    /// window or the target. Of the containers around the whole window only the innermost one is kept, the
+    /// others add nothing but would make the nodes grow with the square of the depth.
+    fn record_node(&mut self, key: Option<[i32; 2]>, start: [i32; 2]) {
+        let end = self.position();
+        let (visible, around) = match self.window {
+            Some([window_start, window_end]) => (
+                start[0] < window_end && end[0] >= window_start,
+                start[0] < window_start && end[0] >= window_end,
+            ),
+            // the whole document is written as text, e.g. to save it
+            None => (false, false),
+        };
+        let visible = visible && !(around && self.recorded_around);
+        self.recorded_around |= around;
+        let is_target = self.target.as_ref() == Some(&self.path);
+        if visible || is_target {
+            let span = NodeSpan {
+                path: self.path.clone(),
+                key,
+                start,
+                end,
+            };
+            if is_target {
+                self.target_span = Some(span.clone());
+            }
+            if visible {
+                self.nodes.push(span);
             }
         }
     }
 
+    /// Writes a whole document, with the comments around its root value.
+    pub fn write_root(&mut self, value: &Value, comments: &[Comment]) {
+        let comments = if self.skip_comments() { &[] } else { comments };
+        for comment in comments.iter().filter(|c| c.before == 0) {
+            self.write_comment(&comment.text);
+            self.newline();
+        }
+        self.write_value(value);
+        self.write_comments(comments, 1);
+    }
+
+    pub fn write_value(&mut self, value: &Value) {
+        let mut stack = Vec::new();
+        self.start_value(value, &mut stack);
+        self.write_containers(stack);
+    }
+
+    /// Writes `values` as the items of an array, without them having to be in one.
+    pub fn write_array(&mut self, values: &[&Value]) {
+        let key = self.pending_key.take();
+        let start = self.position();
+        let container = self.open_container(Items::Values(values), &[], None, key, start);
+        self.write_containers(vec![container]);
+    }
+
     pub fn get(self) -> Vec<Vec<Element>> {
         self.buffer
     }
+
+    pub fn finish(self) -> Rendered {
+        Rendered {
+            lines: self.buffer,
+            first_line: self
+                .window
+                .map(|[start, _]| start.max(self.start_line))
+                .unwrap_or(self.start_line),
+            line_count: self.line + 1,
+            nodes: self.nodes,
+            target: self.target_span,
+        }
+    }
 }
 
-impl ToString for Writer {
-    fn to_string(&self) -> String {
-        self.buffer
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for Writer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, line) in self.buffer.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            for e in line {
+                f.write_str(&e.content)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::parse;
+
+    fn render(value: &Value, window: Option<[i32; 2]>) -> (Vec<String>, i32) {
+        let mut writer = Writer::new();
+        if let Some(window) = window {
+            writer.with_window(window);
+        }
+        writer.write_value(value);
+        let rendered = writer.finish();
+        let lines = rendered
+            .lines
             .iter()
-            .map(|line| {
-                line.iter()
-                    .map(|e| e.content.as_str())
-                    .collect::<Vec<_>>()
-                    .join("")
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
+            .map(|line| line.iter().map(|e| e.content.as_str()).collect())
+            .collect();
+        (lines, rendered.line_count)
+    }
+
+    fn render_with(source: &str, layout: &str) -> String {
+        let value = crate::value::parse_lenient(source).unwrap();
+        let mut writer = Writer::new();
+        writer.with_layout(&toml::from_str(layout).unwrap());
+        writer.write_root(&value.value, &value.comments);
+        writer.to_string()
+    }
+
+    #[test]
+    fn layouts() {
+        let source = r#"{"b": [1, 2, 3, 4, 5], "a": {"c": [true], "d": "x"}}"#;
+        assert_eq!(
+            render_with(source, ""),
+            "{\n    \"b\": [\n        1,\n        2,\n        3,\n        4,\n        5\n    ],\n    \"a\": {\n        \"c\": [true],\n        \"d\": \"x\"\n    }\n}"
+        );
+        assert_eq!(
+            render_with(source, "max_width = 30\nindent = 2"),
+            "{\n  \"b\": [1,2,3,4,5],\n  \"a\": {\"c\": [true],\"d\": \"x\"}\n}"
+        );
+        assert_eq!(
+            render_with(source, "max_width = 100"),
+            r#"{"b": [1,2,3,4,5],"a": {"c": [true],"d": "x"}}"#
+        );
+        assert_eq!(
+            render_with(source, "max_width = 0\nindent = \"tab\"\nsort_keys = true"),
+            "{\n\t\"a\": {\n\t\t\"c\": [\n\t\t\ttrue\n\t\t],\n\t\t\"d\": \"x\"\n\t},\n\t\"b\": [\n\t\t1,\n\t\t2,\n\t\t3,\n\t\t4,\n\t\t5\n\t]\n}"
+        );
+        assert_eq!(
+            render_with("// c\n{'a': [1, /* x */ 2]}", "compact = true"),
+            r#"{"a":[1,2]}"#
+        );
+        assert_eq!(
+            render_with(r#"{"a": 1}"#, "space_after_colon = false"),
+            r#"{"a":1}"#
+        );
+
+        let mut writer = Writer::new();
+        writer.with_indent_size(2);
+        writer.write_value(&crate::value::parse("[[1]]").unwrap());
+        assert_eq!(writer.to_string(), "[\n  [1]\n]");
+    }
+
+    #[test]
+    fn preview() {
+        let preview = |source: &str, width: usize| {
+            let mut writer = Writer::new();
+            writer.with_layout(&toml::from_str("compact = true").unwrap());
+            writer.with_preview(width);
+            writer.write_value(&parse(source).unwrap());
+            writer.to_string()
+        };
+        assert_eq!(
+            preview(r#"{"a": [1, 2], "b": "xyz"}"#, 100),
+            r#"{"a":[1,2],"b":"xyz"}"#
+        );
+        assert_eq!(
+            preview(r#"{"a": [1, 2], "b": "xyz"}"#, 15),
+            r#"{"a":[1,2],"b":"#
+        );
+        assert_eq!(preview(r#"["\u00e9\u00e9\u00e9"]"#, 4), r#"["\u"#);
+        let long = format!("[{}]", vec!["[1, 2]"; 100_000].join(","));
+        assert_eq!(preview(&long, 8), "[[1,2],[");
+
+        // only the first line of an expanded value
+        let mut writer = Writer::new();
+        writer.with_layout(&toml::from_str("max_width = 0").unwrap());
+        writer.with_preview(10);
+        writer.write_value(&parse("[1, 2]").unwrap());
+        assert_eq!(writer.to_string(), "[");
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 100_000;
+        let source = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        let value = parse(&source).unwrap();
+        for start in [0, depth as i32 - 2, 2 * depth as i32 - 3] {
+            let mut writer = Writer::new();
+            writer.with_indent_size(1);
+            writer.with_window([start, start + 3]);
+            writer.write_value(&value);
+            let rendered = writer.finish();
+            assert_eq!(rendered.line_count, 2 * depth as i32 - 1);
+            let lines = rendered
+                .lines
+                .iter()
+                .map(|line| line.iter().map(|e| e.content.as_str()).collect::<String>())
+                .collect::<Vec<_>>();
+            let line = |line: i32| {
+                let depth = depth as i32 - 1;
+                let indent = line.min(2 * depth - line) as usize;
+                let bracket = match line.cmp(&depth) {
+                    std::cmp::Ordering::Less => "[",
+                    std::cmp::Ordering::Equal => "[]",
+                    std::cmp::Ordering::Greater => "]",
+                };
+                format!("{}{bracket}", " ".repeat(indent))
+            };
+            let expected = (start..start + 3)
+                .filter(|&i| i < rendered.line_count)
+                .map(line)
+                .collect::<Vec<_>>();
+            assert_eq!(lines, expected);
+            // the containers that start or end in the window, and the innermost one around it
+            assert!(rendered.nodes.len() <= 7, "{}", rendered.nodes.len());
+        }
+    }
+
+    #[test]
+    fn cached_layout() {
+        let mut value =
+            parse(r#"[{"a": [1, 2], "b": {"c": [[3], {}]}}, [4, 5, 6, 7, 8], {"d": "A"}]"#)
+                .unwrap();
+        for toggled in [None, Some(vec![0, 1]), Some(vec![1])] {
+            if let Some(path) = toggled {
+                match value.get_path_mut(&path).unwrap() {
+                    Value::Array(arr) => arr.toggle_collapse(),
+                    Value::Object(object) => object.toggle_collapse(),
+                    _ => unreachable!(),
+                }
+            }
+            let (full, line_count) = render(&value, None);
+            // the first windowed render fills the caches, the second one uses them
+            for _ in 0..2 {
+                for start in 0..line_count {
+                    let window = [start, start + 3];
+                    let end = (start + 3).min(line_count) as usize;
+                    assert_eq!(
+                        render(&value, Some(window)),
+                        (full[start as usize..end].to_vec(), line_count)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn layout_cache_keys() {
+        let long = "x".repeat(70_000);
+        let value = parse(&format!(r#"[[1, 2], ["{long}"], {{"a": [3]}}, 4]"#)).unwrap();
+        let render = |layout: &str, window: Option<[i32; 2]>| {
+            let mut writer = Writer::new();
+            writer.with_layout(&toml::from_str(layout).unwrap());
+            if let Some(window) = window {
+                writer.with_window(window);
+            }
+            writer.write_value(&value);
+            writer.lines()
+        };
+        let layouts = [
+            "",
+            "indent = 2",
+            "max_width = 80",
+            "max_width = 100000",
+            "indent = 2",
+        ];
+        let full = layouts.map(|layout| render(layout, None));
+        // every layout reuses the caches filled by the others, a line wider than 16 bits included
+        for _ in 0..2 {
+            for (layout, full) in layouts.iter().zip(&full) {
+                let last = full.len() as i32 - 1;
+                assert_eq!(
+                    render(layout, Some([last, last + 1])),
+                    [full.last().unwrap().clone()]
+                );
+            }
+        }
     }
 }
@@ -21,7 +21,7 @@ impl From<char> for CharType {
 
 pub fn next_word(s: &[char]) -> [i32; 2] {
     pub fn aux(s: &[char], initial: CharType, counter: i32) -> [i32; 2] {
-        match s.get(0).copied().map(CharType::from) {
+        match s.first().copied().map(CharType::from) {
             None => [1, 0],
             Some(ct) => {
                 if ct == CharType::Whitespace && initial != CharType::Whitespace {
@@ -35,13 +35,29 @@ pub fn next_word(s: &[char]) -> [i32; 2] {
         }
     }
 
-    match s.get(0).copied().map(CharType::from) {
+    match s.first().copied().map(CharType::from) {
         None => [1, 0],
         Some(ct) => aux(&s[1..], ct, 1),
     }
 }
 
-pub fn jump_next_char(s: &[char], c: char, logger: crate::logger::Logger) -> i32 {
+/// The distance back to the start of the previous word, `s` are the characters before the
+/// cursor in reverse order.
+pub fn previous_word(s: &[char]) -> usize {
+    let spaces = s.iter().take_while(|c| c.is_whitespace()).count();
+    match s.get(spaces).copied().map(CharType::from) {
+        None => spaces,
+        Some(ct) => {
+            spaces
+                + s[spaces..]
+                    .iter()
+                    .take_while(|c| CharType::from(**c) == ct)
+                    .count()
+        }
+    }
+}
+
+pub fn jump_next_char(s: &[char], c: char) -> i32 {
     if s.first().copied() == Some(c) {
         s.iter()
             .enumerate()
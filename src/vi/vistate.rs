@@ -17,9 +17,32 @@ pub enum ViCommand {
     LastLine,
     FirstColumn,
     LastColumn,
+    // horizontal scrolling
+    ScrollLeft,
+    ScrollRight,
+    ScrollCursorStart,
+    ScrollCursorEnd,
+    ToggleWrap,
+    // value display
+    ToggleStringDecoding,
+    ShowString,
+    ToggleFold,
+    ToggleProjection,
     // search results
     Next,
     Previous,
+    // diagnostics
+    NextError,
+    PreviousError,
+    ShowDiagnostics,
+    // editing
+    EditValue,
+    ChangeValue,
+    Delete,
+    InsertSibling,
+    CommandLine,
+    Undo,
+    Redo,
 }
 
 trait ViCommandBuilder {
@@ -41,16 +64,15 @@ where
     }
 }
 
+type Patterns = Vec<(simple_matcher::Pattern, Box<dyn ViCommandBuilder>)>;
+
 pub struct ViState {
     pending: String,
-    patterns: Vec<(simple_matcher::Pattern, Box<dyn ViCommandBuilder>)>,
+    patterns: Patterns,
 }
 
 impl ViState {
-    fn create_patterns() -> Result<
-        Vec<(simple_matcher::Pattern, Box<dyn ViCommandBuilder>)>,
-        simple_matcher::PatternParseError,
-    > {
+    fn create_patterns() -> Result<Patterns, simple_matcher::PatternParseError> {
         type C = ViCommand;
         Ok(vec![
             // simple navigation
@@ -74,9 +96,31 @@ impl ViState {
             ("G".parse()?, Box::new(C::LastLine)),
             ("0".parse()?, Box::new(C::FirstColumn)),
             ("$".parse()?, Box::new(C::LastColumn)),
+            // horizontal scrolling
+            ("zh".parse()?, Box::new(C::ScrollLeft)),
+            ("zl".parse()?, Box::new(C::ScrollRight)),
+            ("zs".parse()?, Box::new(C::ScrollCursorStart)),
+            ("ze".parse()?, Box::new(C::ScrollCursorEnd)),
+            ("zw".parse()?, Box::new(C::ToggleWrap)),
+            // value display
+            ("gs".parse()?, Box::new(C::ToggleStringDecoding)),
+            ("K".parse()?, Box::new(C::ShowString)),
+            ("za".parse()?, Box::new(C::ToggleFold)),
+            ("gr".parse()?, Box::new(C::ToggleProjection)),
             // search results
             ("n".parse()?, Box::new(C::Next)),
             ("p".parse()?, Box::new(C::Previous)),
+            // diagnostics
+            ("]e".parse()?, Box::new(C::NextError)),
+            ("[e".parse()?, Box::new(C::PreviousError)),
+            ("D".parse()?, Box::new(C::ShowDiagnostics)),
+            // editing
+            ("i".parse()?, Box::new(C::EditValue)),
+            ("cw".parse()?, Box::new(C::ChangeValue)),
+            ("dd".parse()?, Box::new(C::Delete)),
+            ("o".parse()?, Box::new(C::InsertSibling)),
+            (":".parse()?, Box::new(C::CommandLine)),
+            ("u".parse()?, Box::new(C::Undo)),
         ])
     }
 
@@ -91,6 +135,11 @@ impl ViState {
         self.pending.clear();
     }
 
+    /// Whether the keys typed so far are the start of a command.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
     pub fn process(&mut self, c: char) -> Option<ViCommand> {
         self.pending.push(c);
         let mut has_partial_matche = false;
@@ -107,9 +156,15 @@ impl ViState {
                 }
             }
         }
-        if has_partial_matche == false {
+        if !has_partial_matche {
             self.pending.clear();
         }
         None
     }
 }
+
+impl Default for ViState {
+    fn default() -> Self {
+        Self::new()
+    }
+}